@@ -29,7 +29,7 @@ export type Balances = Balance[];
 
 export interface Balance {
   accountName: string;
-  amount: number;
+  amount: string;
   commodityUnit: string;
   commodityDecimal: number;
 }
@@ -1,7 +1,7 @@
 // This file is auto-generated by @hey-api/openapi-ts
 
 import type { Options as ClientOptions, TDataShape, Client } from '@hey-api/client-fetch';
-import type { MutateAddData, MutateAddResponse, MutateAddError, MutateImportCsvData, MutateImportCsvError, MutateMigrateData, MutateMigrateError, GetOpenapiData, GetOpenapiResponse, GetOpenapiError, QueryAccountBalancesData, QueryAccountBalancesResponse, QueryAccountBalancesError, QueryAccountIncomeStatementData, QueryAccountIncomeStatementResponse, QueryAccountIncomeStatementError, QueryAccountNamesAllData, QueryAccountNamesAllResponse, QueryAccountNamesAllError, QueryAccountTransactionsData, QueryAccountTransactionsResponse, QueryAccountTransactionsError, QueryCommoditiesAllData, QueryCommoditiesAllResponse, QueryCommoditiesAllError, QueryExportCsvData, QueryExportCsvResponse, QueryExportCsvError, QueryExportHledgerData, QueryExportHledgerResponse, QueryExportHledgerError, QueryPrepareAddFcfsData, QueryPrepareAddFcfsResponse, QueryPrepareAddFcfsError, GetVersionData, GetVersionResponse } from './types.gen';
+import type { MutateAddData, MutateAddResponse, MutateAddError, MutateImportCsvData, MutateImportCsvResponse, MutateImportCsvError, MutateMigrateData, MutateMigrateError, GetOpenapiData, GetOpenapiResponse, GetOpenapiError, QueryAccountBalancesData, QueryAccountBalancesResponse, QueryAccountBalancesError, QueryAccountIncomeStatementData, QueryAccountIncomeStatementResponse, QueryAccountIncomeStatementError, QueryAccountNamesAllData, QueryAccountNamesAllResponse, QueryAccountNamesAllError, QueryAccountTransactionsData, QueryAccountTransactionsResponse, QueryAccountTransactionsError, QueryCommoditiesAllData, QueryCommoditiesAllResponse, QueryCommoditiesAllError, QueryExportCsvData, QueryExportCsvResponse, QueryExportCsvError, QueryExportHledgerData, QueryExportHledgerResponse, QueryExportHledgerError, QueryPrepareAddFcfsData, QueryPrepareAddFcfsResponse, QueryPrepareAddFcfsError, GetVersionData, GetVersionResponse } from './types.gen';
 import { client as _heyApiClient } from './client.gen';
 
 export type Options<TData extends TDataShape = TDataShape, ThrowOnError extends boolean = boolean> = ClientOptions<TData, ThrowOnError> & {
@@ -30,7 +30,7 @@ export const mutateAdd = <ThrowOnError extends boolean = false>(options: Options
 };
 
 export const mutateImportCsv = <ThrowOnError extends boolean = false>(options?: Options<MutateImportCsvData, ThrowOnError>) => {
-    return (options?.client ?? _heyApiClient).put<unknown, MutateImportCsvError, ThrowOnError>({
+    return (options?.client ?? _heyApiClient).put<MutateImportCsvResponse, MutateImportCsvError, ThrowOnError>({
         url: '/mutate/import-csv',
         ...options
     });
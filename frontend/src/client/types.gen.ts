@@ -4,7 +4,7 @@ export type AddFilterTransaction = {
     /**
      * amount added to debit account
      */
-    amount: number;
+    amount: Amount;
     /**
      * transaction code
      */
@@ -42,7 +42,7 @@ export type AddTransaction = {
     /**
      * amount added to debit account
      */
-    amount: number;
+    amount: Amount;
     /**
      * transaction code
      */
@@ -76,16 +76,103 @@ export type AddTransactions = {
     transactions: Array<AddTransaction>;
 };
 
+/**
+ * Amount in the smallest unit of its commodity as a string, requests setting `amount_format=decimal` send
+ * and receive decimal strings scaled by the decimal place of the commodity instead
+ */
+export type Amount = string;
+
+export type AmountFormat = 'integer' | 'decimal';
+
 export type Balance = {
     accountName: string;
-    amount: number;
+    amount: Amount;
     commodityDecimal: number;
     commodityUnit: string;
+    /**
+     * amount valued in the commodity of `value_in`, only set when requested
+     */
+    value?: null | MarketValue;
+};
+
+export type Commodity = {
+    /**
+     * location of decimal point
+     */
+    decimalPlace: number;
+    /**
+     * tigerbeetle ledger id
+     */
+    id: number;
+    /**
+     * ISO 4217 currency code, e.g. EUR
+     */
+    isoCode?: string | null;
+    /**
+     * display name, e.g. Euro
+     */
+    name?: string | null;
+    /**
+     * display symbol, e.g. €
+     */
+    symbol?: string | null;
+    /**
+     * commodity unit used in transactions
+     */
+    unit: string;
+};
+
+/**
+ * Machine readable reason of an error response
+ */
+export type ErrorCode = 'validation' | 'not_found' | 'conflict' | 'ledger_rejected' | 'disabled_by_config' | 'storage_unavailable' | 'internal';
+
+/**
+ * Error envelope returned by every route
+ */
+export type HttpErr = {
+    code: ErrorCode;
+    /**
+     * transfers rejected by tigerbeetle, only set on ledger errors
+     */
+    errors?: Array<TransferError>;
+    message: string;
+};
+
+export type ImportSummary = {
+    /**
+     * number of rows added to the ledger
+     */
+    imported: number;
+    /**
+     * rows not added because their transfer id already exists
+     */
+    skipped: Array<SkippedRow>;
+    /**
+     * hex u128 transfer ids of the added rows
+     */
+    transferIds: Array<string>;
 };
 
 export type IncomeStatement = {
     accountName: string;
-    amounts: Array<number>;
+    amounts: Array<Amount>;
+    commodityDecimal: number;
+    commodityUnit: string;
+    /**
+     * amounts valued in the commodity of `value_in`, only set when requested
+     */
+    values?: null | MarketValues;
+};
+
+export type MarketValue = {
+    amount: Amount;
+    commodityDecimal: number;
+    commodityUnit: string;
+};
+
+export type MarketValues = {
+    amounts: Array<Amount>;
     commodityDecimal: number;
     commodityUnit: string;
 };
@@ -123,17 +210,69 @@ export type MigrateCommodity = {
 export type QueryAccountBalancesBody = {
     accounts_glob: string;
     date?: number | null;
+    /**
+     * commodity unit to value each balance in at the balance date
+     */
+    value_in?: string | null;
 };
 
 export type QueryAccountIncomeStatementBody = {
     accounts_glob: string;
     dates: Array<number>;
+    /**
+     * commodity unit to value the amounts in at each date
+     */
+    value_in?: string | null;
 };
 
 export type QueryTransactionsBody = {
     accounts_glob: string;
+    /**
+     * Only transfers of at most this amount, in the format of `amount_format`
+     */
+    amount_max?: Amount | null;
+    /**
+     * Only transfers of at least this amount, in the format of `amount_format`
+     */
+    amount_min?: Amount | null;
+    /**
+     * Only transfers with a code of at most this
+     */
+    code_max?: number | null;
+    /**
+     * Only transfers with a code of at least this
+     */
+    code_min?: number | null;
+    /**
+     * Only transfers with one of these codes
+     */
+    codes?: Array<number> | null;
+    /**
+     * Only transfers of this commodity
+     */
+    commodity_unit?: string | null;
+    /**
+     * Only transfers with the other account of the transfer matching this glob
+     */
+    counterparty_glob?: string | null;
+    /**
+     * Value of the `next-cursor` header of the previous page
+     */
+    cursor?: string | null;
     date_newest: number;
     date_oldest: number;
+    /**
+     * Most transactions to return, the `next-cursor` response header is set when there are more
+     */
+    limit?: number | null;
+    /**
+     * Only transfers with this hex related id
+     */
+    related_id?: string | null;
+    /**
+     * Only transfers debiting or only transfers crediting the accounts
+     */
+    side?: TransferSide | null;
 };
 
 export type RequestMigrate = {
@@ -146,6 +285,17 @@ export type ResponseIncomeStatements = {
     incomeStatements: Array<IncomeStatement>;
 };
 
+export type SkippedRow = {
+    /**
+     * row of the csv file, the header is row 1
+     */
+    row: number;
+    /**
+     * hex u128 transfer id
+     */
+    transferId: string;
+};
+
 export type Transaction = {
     /**
      * transaction code
@@ -166,7 +316,7 @@ export type Transaction = {
     /**
      * amount removed from credit account
      */
-    creditAmount: number;
+    creditAmount: Amount;
     /**
      * account name
      */
@@ -174,7 +324,7 @@ export type Transaction = {
     /**
      * amount added to debit account
      */
-    debitAmount: number;
+    debitAmount: Amount;
     /**
      * unix time milliseconds
      */
@@ -193,12 +343,46 @@ export type Transaction = {
     transferId: string;
 };
 
+export type TransferError = {
+    /**
+     * account name
+     */
+    creditAccount: string;
+    /**
+     * account name
+     */
+    debitAccount: string;
+    /**
+     * index of the transaction in the request
+     */
+    index: number;
+    /**
+     * tigerbeetle result in snake case, e.g. exceeds_credits or linked_event_failed
+     */
+    kind: string;
+    /**
+     * hex u128 transfer id
+     */
+    transferId: string;
+};
+
+/**
+ * Side of a transfer the queried accounts are on.
+ */
+export type TransferSide = 'debit' | 'credit';
+
 export type Vec = Array<string>;
 
 export type MutateAddData = {
     body: AddTransactions;
     path?: never;
-    query?: never;
+    query?: {
+        /**
+         * `decimal` sends and returns amounts as decimal strings scaled by the decimal place of the
+         * commodity, defaults to integers in the smallest unit of the commodity
+         */
+        amount_format?: AmountFormat;
+    };
     url: '/mutate/add';
 };
 
@@ -206,11 +390,27 @@ export type MutateAddErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
+    /**
+     * Disabled by server configuration
+     */
+    403: HttpErr;
+    /**
+     * Transactions conflict with existing transactions
+     */
+    409: HttpErr;
+    /**
+     * Transactions rejected by the ledger
+     */
+    422: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
+    /**
+     * Ledger unavailable
+     */
+    503: HttpErr;
 };
 
 export type MutateAddError = MutateAddErrors[keyof MutateAddErrors];
@@ -227,30 +427,58 @@ export type MutateAddResponse = MutateAddResponses[keyof MutateAddResponses];
 export type MutateImportCsvData = {
     body?: never;
     path?: never;
-    query?: never;
+    query?: {
+        /**
+         * single character separating the columns, defaults to a comma
+         */
+        delimiter?: string | null;
+        /**
+         * `decimal` sends and returns amounts as decimal strings scaled by the decimal place of the
+         * commodity, defaults to integers in the smallest unit of the commodity
+         */
+        amount_format?: AmountFormat;
+    };
     url: '/mutate/import-csv';
 };
 
 export type MutateImportCsvErrors = {
     /**
-     * Bad request error occurred
+     * Rows that can not be imported
+     */
+    400: HttpErr;
+    /**
+     * Disabled by server configuration
+     */
+    403: HttpErr;
+    /**
+     * Transactions conflict with existing transactions
      */
-    400: string;
+    409: HttpErr;
+    /**
+     * Transactions rejected by the ledger
+     */
+    422: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
+    /**
+     * Ledger unavailable
+     */
+    503: HttpErr;
 };
 
 export type MutateImportCsvError = MutateImportCsvErrors[keyof MutateImportCsvErrors];
 
 export type MutateImportCsvResponses = {
     /**
-     * Returns status 200 when import is complete
+     * Returns the imported and skipped rows
      */
-    200: unknown;
+    200: ImportSummary;
 };
 
+export type MutateImportCsvResponse = MutateImportCsvResponses[keyof MutateImportCsvResponses];
+
 export type MutateMigrateData = {
     body: RequestMigrate;
     path?: never;
@@ -262,11 +490,15 @@ export type MutateMigrateErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
+    /**
+     * Disabled by server configuration
+     */
+    403: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type MutateMigrateError = MutateMigrateErrors[keyof MutateMigrateErrors];
@@ -289,7 +521,7 @@ export type GetOpenapiErrors = {
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type GetOpenapiError = GetOpenapiErrors[keyof GetOpenapiErrors];
@@ -306,7 +538,13 @@ export type GetOpenapiResponse = GetOpenapiResponses[keyof GetOpenapiResponses];
 export type QueryAccountBalancesData = {
     body: QueryAccountBalancesBody;
     path?: never;
-    query?: never;
+    query?: {
+        /**
+         * `decimal` sends and returns amounts as decimal strings scaled by the decimal place of the
+         * commodity, defaults to integers in the smallest unit of the commodity
+         */
+        amount_format?: AmountFormat;
+    };
     url: '/query/account-balances';
 };
 
@@ -314,11 +552,11 @@ export type QueryAccountBalancesErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type QueryAccountBalancesError = QueryAccountBalancesErrors[keyof QueryAccountBalancesErrors];
@@ -335,7 +573,13 @@ export type QueryAccountBalancesResponse = QueryAccountBalancesResponses[keyof Q
 export type QueryAccountIncomeStatementData = {
     body: QueryAccountIncomeStatementBody;
     path?: never;
-    query?: never;
+    query?: {
+        /**
+         * `decimal` sends and returns amounts as decimal strings scaled by the decimal place of the
+         * commodity, defaults to integers in the smallest unit of the commodity
+         */
+        amount_format?: AmountFormat;
+    };
     url: '/query/account-income-statements';
 };
 
@@ -343,11 +587,11 @@ export type QueryAccountIncomeStatementErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type QueryAccountIncomeStatementError = QueryAccountIncomeStatementErrors[keyof QueryAccountIncomeStatementErrors];
@@ -372,11 +616,11 @@ export type QueryAccountNamesAllErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type QueryAccountNamesAllError = QueryAccountNamesAllErrors[keyof QueryAccountNamesAllErrors];
@@ -393,7 +637,13 @@ export type QueryAccountNamesAllResponse = QueryAccountNamesAllResponses[keyof Q
 export type QueryAccountTransactionsData = {
     body: QueryTransactionsBody;
     path?: never;
-    query?: never;
+    query?: {
+        /**
+         * `decimal` sends and returns amounts as decimal strings scaled by the decimal place of the
+         * commodity, defaults to integers in the smallest unit of the commodity
+         */
+        amount_format?: AmountFormat;
+    };
     url: '/query/account-transactions';
 };
 
@@ -401,11 +651,11 @@ export type QueryAccountTransactionsErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type QueryAccountTransactionsError = QueryAccountTransactionsErrors[keyof QueryAccountTransactionsErrors];
@@ -430,20 +680,20 @@ export type QueryCommoditiesAllErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type QueryCommoditiesAllError = QueryCommoditiesAllErrors[keyof QueryCommoditiesAllErrors];
 
 export type QueryCommoditiesAllResponses = {
     /**
-     * Returns list of commodities
+     * Returns list of commodities sorted by unit
      */
-    200: Array<string>;
+    200: Array<Commodity>;
 };
 
 export type QueryCommoditiesAllResponse = QueryCommoditiesAllResponses[keyof QueryCommoditiesAllResponses];
@@ -451,7 +701,12 @@ export type QueryCommoditiesAllResponse = QueryCommoditiesAllResponses[keyof Que
 export type QueryExportCsvData = {
     body: QueryTransactionsBody;
     path?: never;
-    query?: never;
+    query?: {
+        /**
+         * single character separating the columns, defaults to a comma
+         */
+        delimiter?: string | null;
+    };
     url: '/query/export-csv';
 };
 
@@ -459,18 +714,18 @@ export type QueryExportCsvErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type QueryExportCsvError = QueryExportCsvErrors[keyof QueryExportCsvErrors];
 
 export type QueryExportCsvResponses = {
     /**
-     * Returns csv export
+     * Streams csv export
      */
     200: string;
 };
@@ -488,18 +743,18 @@ export type QueryExportHledgerErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type QueryExportHledgerError = QueryExportHledgerErrors[keyof QueryExportHledgerErrors];
 
 export type QueryExportHledgerResponses = {
     /**
-     * Returns hledger export
+     * Streams hledger export
      */
     200: string;
 };
@@ -509,7 +764,13 @@ export type QueryExportHledgerResponse = QueryExportHledgerResponses[keyof Query
 export type QueryPrepareAddFcfsData = {
     body: AddFilterTransactions;
     path?: never;
-    query?: never;
+    query?: {
+        /**
+         * `decimal` sends and returns amounts as decimal strings scaled by the decimal place of the
+         * commodity, defaults to integers in the smallest unit of the commodity
+         */
+        amount_format?: AmountFormat;
+    };
     url: '/query/prepare-add';
 };
 
@@ -517,11 +778,15 @@ export type QueryPrepareAddFcfsErrors = {
     /**
      * Bad request error occurred
      */
-    400: string;
+    400: HttpErr;
+    /**
+     * Disabled by server configuration
+     */
+    403: HttpErr;
     /**
      * Internal server error occurred
      */
-    500: string;
+    500: HttpErr;
 };
 
 export type QueryPrepareAddFcfsError = QueryPrepareAddFcfsErrors[keyof QueryPrepareAddFcfsErrors];
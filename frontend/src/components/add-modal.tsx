@@ -31,8 +31,30 @@ import {
   AddFilterTransaction,
   AddTransaction,
   AddTransactions,
+  HttpErr,
 } from "@/client";
 
+function httpErrMessage(err: any): string {
+  if (!err?.code) return (err || "Unknown error").toString();
+  const httpErr = err as HttpErr;
+
+  switch (httpErr.code) {
+    case "disabled_by_config":
+      return "Adding transactions is disabled on this server";
+    case "ledger_rejected":
+    case "conflict":
+      return [
+        httpErr.message,
+        ...(httpErr.errors || []).map(
+          (e) =>
+            `#${e.index + 1} ${e.debitAccount} -> ${e.creditAccount}: ${e.kind}`,
+        ),
+      ].join("\n");
+    default:
+      return httpErr.message;
+  }
+}
+
 const RE_IS_ACCOUNT = /^(a|l|e|r|x):.*/;
 const RE_HEXADECIMAL = /^[a-f0-9]{1,31}$/;
 
@@ -51,7 +73,7 @@ const zodAddTransaction = z.object({
           ),
         debitAccount: z.string().regex(RE_IS_ACCOUNT),
         creditAccount: z.string().regex(RE_IS_ACCOUNT),
-        amount: z.number(),
+        amount: z.string().regex(/^\d+$/),
       }),
     )
     .min(1),
@@ -71,7 +93,7 @@ const zodAddFilterTransaction = z.object({
           ),
         debitAccount: z.string().regex(RE_IS_ACCOUNT),
         creditAccountsFilter: z.array(z.string().regex(RE_IS_ACCOUNT)).min(1),
-        amount: z.number(),
+        amount: z.string().regex(/^\d+$/),
       }),
     )
     .min(1),
@@ -145,7 +167,7 @@ export default function AddModal() {
             relatedId: related_id,
             debitAccount: debit,
             creditAccountsFilter,
-            amount: parseInt(amount),
+            amount: amount.trim(),
           });
         } else {
           const credit = data["credit" + i]!;
@@ -156,7 +178,7 @@ export default function AddModal() {
             relatedId: related_id,
             debitAccount: debit,
             creditAccount: credit,
-            amount: parseInt(amount),
+            amount: amount.trim(),
           });
         }
 
@@ -196,7 +218,7 @@ export default function AddModal() {
         }
       } catch (err: any) {
         console.error("Mutation async add transaction error:", err);
-        setAlertErr((err || "Unknown error").toString());
+        setAlertErr(httpErrMessage(err));
 
         throw err;
       }
@@ -1,8 +1,10 @@
 import Decimal from "decimal.js";
 
+import { Amount } from "@/client";
+
 export default function Numberify(props: {
   t: { commodityDecimal: number; commodityUnit: string };
-  amount: number;
+  amount: Amount;
 }) {
   let n = Decimal(props.amount);
 
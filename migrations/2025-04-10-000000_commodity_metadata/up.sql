@@ -0,0 +1,8 @@
+ALTER TABLE commodities
+ADD COLUMN symbol TEXT;
+
+ALTER TABLE commodities
+ADD COLUMN "name" TEXT;
+
+ALTER TABLE commodities
+ADD COLUMN iso_code TEXT;
@@ -0,0 +1,17 @@
+CREATE TABLE
+  commodities (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    unit TEXT NOT NULL,
+    decimal_place INTEGER DEFAULT 0 NOT NULL,
+    CONSTRAINT uk_commodities_unit UNIQUE (unit)
+  );
+
+CREATE TABLE
+  accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT NOT NULL,
+    tb_id VARCHAR(31) NOT NULL,
+    commodities_id INTEGER NOT NULL,
+    CONSTRAINT fk_commodities FOREIGN KEY (commodities_id) REFERENCES commodities (id),
+    CONSTRAINT uk_accounts_name_commodities UNIQUE ("name", commodities_id)
+  );
@@ -0,0 +1,8 @@
+ALTER TABLE commodities
+DROP COLUMN iso_code;
+
+ALTER TABLE commodities
+DROP COLUMN "name";
+
+ALTER TABLE commodities
+DROP COLUMN symbol;
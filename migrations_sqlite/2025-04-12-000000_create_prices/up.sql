@@ -0,0 +1,11 @@
+CREATE TABLE
+  prices (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    commodities_id INTEGER NOT NULL,
+    quote_commodities_id INTEGER NOT NULL,
+    "date" BIGINT NOT NULL,
+    price TEXT NOT NULL,
+    CONSTRAINT fk_prices_commodities FOREIGN KEY (commodities_id) REFERENCES commodities (id),
+    CONSTRAINT fk_prices_quote_commodities FOREIGN KEY (quote_commodities_id) REFERENCES commodities (id),
+    CONSTRAINT uk_prices_commodities_date UNIQUE (commodities_id, quote_commodities_id, "date")
+  );
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use regex::Regex;
+use validator::ValidationError;
+
+use crate::hledger::{self, JournalTransaction, ParseError, Posting};
+use crate::responses::{AddTransactions, Transaction};
+
+static RE_COMMODITY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Z]([A-Z0-9'\._-]{0,22}[A-Z0-9])?$").expect("invalid regex"));
+
+/// Dated directives that do not change the transactions.
+const IGNORED_DIRECTIVES: [&str; 9] = [
+    "open",
+    "close",
+    "commodity",
+    "balance",
+    "note",
+    "document",
+    "event",
+    "query",
+    "price",
+];
+
+/// Undated directives that do not change the transactions.
+const IGNORED_OPTIONS: [&str; 6] = [
+    "option", "plugin", "pushtag", "poptag", "pushmeta", "popmeta",
+];
+
+/// Maps account names like `a:bank:checking` to `Assets:Bank:Checking`.
+pub fn account_name(name: &str) -> Result<String, ValidationError> {
+    let invalid = || {
+        ValidationError::new("invalid_account")
+            .with_message(format!("account {} can not be written to beancount", name).into())
+    };
+    let mut segments = name.split(':');
+    let root = match segments.next() {
+        Some("a") => "Assets",
+        Some("l") => "Liabilities",
+        Some("e") => "Equity",
+        Some("r") => "Income",
+        Some("x") => "Expenses",
+        _ => return Err(invalid()),
+    };
+    let segments = segments
+        .map(|s| {
+            let mut chars = s.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .ok_or_else(invalid)
+        })
+        .collect::<Result<Vec<String>, ValidationError>>()?;
+    Ok(std::iter::once(String::from(root))
+        .chain(segments)
+        .join(":"))
+}
+
+pub fn commodity(unit: &str) -> Result<&str, ValidationError> {
+    if RE_COMMODITY.is_match(unit) {
+        Ok(unit)
+    } else {
+        Err(ValidationError::new("invalid_commodity").with_message(
+            format!(
+                "commodity {} can not be written to beancount, beancount commodities are uppercase letters",
+                unit
+            )
+            .into(),
+        ))
+    }
+}
+
+/// Opens every account on the date of its first transaction with the commodities it holds.
+pub fn open_directives(transactions: &[Transaction]) -> Result<String, ValidationError> {
+    let mut accounts: Vec<(&str, i64, Vec<&str>)> = Vec::new();
+    for t in transactions.iter() {
+        for account in [t.debit_account.as_str(), t.credit_account.as_str()] {
+            match accounts.iter_mut().find(|(a, _, _)| *a == account) {
+                Some((_, date, units)) => {
+                    *date = (*date).min(t.full_date);
+                    if !units.contains(&t.commodity_unit.as_str()) {
+                        units.push(t.commodity_unit.as_str());
+                    }
+                }
+                None => accounts.push((account, t.full_date, vec![t.commodity_unit.as_str()])),
+            }
+        }
+    }
+
+    accounts
+        .iter()
+        .sorted_by_key(|(account, date, _)| (*date, *account))
+        .map(|(account, date, units)| {
+            let date = DateTime::<Utc>::from_timestamp_millis(*date)
+                .ok_or(ValidationError::new("invalid full_date"))?;
+            Ok(format!(
+                "{} open {} {}\n",
+                date.format("%Y-%m-%d"),
+                account_name(account)?,
+                units
+                    .iter()
+                    .map(|unit| commodity(unit))
+                    .collect::<Result<Vec<&str>, ValidationError>>()?
+                    .join(","),
+            ))
+        })
+        .collect::<Result<String, ValidationError>>()
+}
+
+/// Parses a beancount file into one `AddTransactions` per transaction.
+///
+/// Amounts are scaled by the decimal place of their commodity, `transfer_id`, `related_id` and
+/// `code` are read from the transaction metadata.
+///
+/// see: https://beancount.github.io/docs/beancount_language_syntax.html
+pub fn parse_file(
+    file: &str,
+    decimal_places: &HashMap<String, i32>,
+) -> Result<Vec<AddTransactions>, Vec<ParseError>> {
+    let mut errors: Vec<ParseError> = Vec::new();
+    let mut add_transactions_arr: Vec<AddTransactions> = Vec::new();
+    let mut current: Option<JournalTransaction> = None;
+
+    let mut finish = |current: &mut Option<JournalTransaction>, errors: &mut Vec<ParseError>| {
+        if let Some(t) = current.take().filter(|t| !t.failed) {
+            match hledger::to_add_transactions(t) {
+                Ok(v) => add_transactions_arr.push(v),
+                Err(err) => errors.push(err),
+            }
+        }
+    };
+
+    for (i, raw) in file.lines().enumerate() {
+        let n = i + 1;
+        let line = raw.split(';').next().unwrap_or_default().trim_end();
+        if line.is_empty() {
+            if raw.trim().is_empty() {
+                finish(&mut current, &mut errors);
+            }
+            continue;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            // metadata and postings of ignored directives are skipped
+            let Some(t) = current.as_mut() else {
+                continue;
+            };
+            if let Err(message) = parse_indented(t, line.trim_start(), decimal_places) {
+                t.failed = true;
+                errors.push(ParseError { line: n, message });
+            }
+            continue;
+        }
+
+        finish(&mut current, &mut errors);
+        if line.starts_with('*') {
+            // org mode heading
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let first = tokens.next().unwrap_or_default();
+        if !first.starts_with(|c: char| c.is_ascii_digit()) {
+            if !IGNORED_OPTIONS.contains(&first) {
+                errors.push(ParseError {
+                    line: n,
+                    message: format!("unsupported directive {}", first),
+                });
+            }
+            continue;
+        }
+
+        let full_date2 = match hledger::parse_date(first) {
+            Ok(v) => v,
+            Err(message) => {
+                errors.push(ParseError { line: n, message });
+                continue;
+            }
+        };
+        match tokens.next().unwrap_or_default() {
+            "txn" | "*" => current = Some(JournalTransaction::new(n, full_date2)),
+            // pending entries are not booked, exported pending transfers are comments
+            "!" => errors.push(ParseError {
+                line: n,
+                message: String::from("pending transactions flagged ! can not be imported"),
+            }),
+            directive if IGNORED_DIRECTIVES.contains(&directive) => {}
+            directive => errors.push(ParseError {
+                line: n,
+                message: format!("unsupported directive {}", directive),
+            }),
+        }
+    }
+    finish(&mut current, &mut errors);
+
+    if errors.is_empty() {
+        Ok(add_transactions_arr)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parses transaction metadata `key: value` or a posting `[FLAG] ACCOUNT [AMOUNT]`.
+fn parse_indented(
+    t: &mut JournalTransaction,
+    content: &str,
+    decimal_places: &HashMap<String, i32>,
+) -> Result<(), String> {
+    if content.starts_with(|c: char| c.is_ascii_lowercase()) {
+        let (key, value) = content
+            .split_once(':')
+            .ok_or(format!("invalid metadata {}", content))?;
+        // metadata of postings is ignored
+        if t.postings.is_empty() {
+            hledger::parse_tag(t, key.trim(), value.trim().trim_matches('"'))?;
+        }
+        return Ok(());
+    }
+
+    let content = content
+        .strip_prefix(['*', '!'])
+        .unwrap_or(content)
+        .trim_start();
+    let (account, amount) = content
+        .split_once(char::is_whitespace)
+        .unwrap_or((content, ""));
+    if amount.contains(['{', '@']) {
+        return Err(String::from("costs and prices are not supported"));
+    }
+    let amount = match amount.trim() {
+        "" => None,
+        amount => Some(hledger::parse_amount(amount, decimal_places)?),
+    };
+    t.postings.push(Posting {
+        account: hledger::account_name(account)?,
+        amount,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{account_name, parse_file};
+    use crate::responses::{Amount, Transaction};
+    use itertools::Itertools;
+    use std::collections::HashMap;
+
+    #[test]
+    fn account_names() {
+        assert_eq!(
+            account_name("a:bank:checking").unwrap(),
+            "Assets:Bank:Checking"
+        );
+        assert_eq!(account_name("r:2024:salary").unwrap(), "Income:2024:Salary");
+        assert!(account_name("bank").is_err());
+    }
+
+    #[test]
+    fn export() {
+        let t = Transaction {
+            commodity_unit: String::from("EUR"),
+            commodity_decimal: 2,
+            code: 1,
+            full_date: 1743633624679,
+            related_id: String::from("1f"),
+            transfer_id: String::from("195fb004cbf19e9d857488e47bb0af1"),
+            debit_account: String::from("x:food"),
+            credit_account: String::from("a:bank"),
+            debit_amount: Amount::Integer(1050),
+            credit_amount: Amount::Integer(-1050),
+            ..Default::default()
+        };
+        assert_eq!(
+            super::open_directives(std::slice::from_ref(&t)).unwrap(),
+            "2025-04-02 open Assets:Bank EUR\n2025-04-02 open Expenses:Food EUR\n"
+        );
+        assert_eq!(
+            t.to_beancount_string().unwrap(),
+            "2025-04-02 * \"\"\n  transfer_id: \"195fb004cbf19e9d857488e47bb0af1\"\n  related_id: \"1f\"\n  code: 1\n  Expenses:Food  10.50 EUR\n  Assets:Bank  -10.50 EUR\n"
+        );
+
+        let add_transactions_arr = parse_file(
+            &t.to_beancount_string().unwrap(),
+            &HashMap::from([(String::from("EUR"), 2)]),
+        )
+        .unwrap();
+        let add = &add_transactions_arr[0].transactions[0];
+        assert_eq!(add.transfer_id.as_deref(), Some(t.transfer_id.as_str()));
+        assert_eq!(add.related_id, t.related_id);
+        assert_eq!(add.debit_account, t.debit_account);
+        assert_eq!(add.credit_account, t.credit_account);
+        assert_eq!(add.amount, Amount::Integer(1050));
+    }
+
+    #[test]
+    fn import() {
+        let file = "\
+option \"title\" \"Books\"
+2024-01-01 open Assets:Bank EUR
+  description: \"checking\"
+
+2024-01-31 * \"Shop\" \"Groceries\" #food
+  code: 12
+  Expenses:Food       10.5 EUR
+    note: \"posting metadata\"
+  Assets:Bank
+
+2024-02-01 txn \"Shop\"
+  Assets:Bank         10 EUR @ 1.1 USD
+  Income:Salary
+2024-02-02 pad Assets:Bank Equity:Opening
+2024-02-03 ! \"Reserved\"
+  Assets:Bank         10 EUR
+  Income:Salary
+";
+        let decimal_places = HashMap::from([(String::from("EUR"), 2)]);
+        let errors = parse_file(file, &decimal_places).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.line).collect::<Vec<usize>>(),
+            vec![12, 14, 15]
+        );
+
+        let add_transactions_arr =
+            parse_file(&file.lines().take(10).join("\n"), &decimal_places).unwrap();
+        assert_eq!(add_transactions_arr.len(), 1);
+        let t = &add_transactions_arr[0].transactions[0];
+        assert_eq!(t.code, 12);
+        assert_eq!(t.debit_account, "x:food");
+        assert_eq!(t.credit_account, "a:bank");
+        assert_eq!(t.amount, Amount::Integer(1050));
+    }
+}
@@ -1,71 +1,203 @@
 #[cfg(test)]
 mod tests {
     use axum_test::TestServer;
-    use dotenvy::dotenv;
+    use serde::Serialize;
     use tokio::time::sleep;
 
-    use crate::{models, responses, router};
+    use crate::{app, http_err, ledger, ledger::Ledger, models, responses, store, AppState};
     use axum::http::StatusCode;
     use models::TB_MAX_BATCH_SIZE;
-    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use std::{
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    /// Server running on an in-memory ledger and sqlite database, needs no running services.
+    async fn test_server() -> TestServer {
+        let app_state = AppState {
+            store: store::connect("sqlite://:memory:").await,
+            ledger: Arc::new(ledger::MemoryLedger::new()),
+            allow_add: true,
+            allow_migrate: true,
+        };
+        TestServer::new(app(app_state)).unwrap()
+    }
+
+    fn now_millis() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as i64
+    }
+
+    #[derive(Serialize)]
+    struct QueryTransactionsBody {
+        date_newest: i64,
+        date_oldest: i64,
+        accounts_glob: String,
+    }
+
+    async fn add_transactions(server: &TestServer, transactions: Vec<responses::AddTransaction>) {
+        let response = server
+            .put("/mutate/add")
+            .json(&responses::AddTransactions {
+                full_date2: now_millis(),
+                transactions,
+            })
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "unable to add transaction: {}",
+            response.text(),
+        );
+    }
+
+    async fn account_balance(server: &TestServer, account: &str) -> responses::Balance {
+        let response = server
+            .post("/query/account-balances")
+            .json(&QueryTransactionsBody {
+                date_newest: 0,
+                date_oldest: 0,
+                accounts_glob: String::from(account),
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let mut json = response.json::<responses::ResponseBalances>();
+        assert_eq!(json.len(), 1, "one balance of {account}");
+        json.remove(0)
+    }
+
+    fn transaction(related_id: String, debit: &str, credit: &str) -> responses::AddTransaction {
+        responses::AddTransaction {
+            transfer_id: None,
+            commodity_unit: String::from("TEST"),
+            code: 9999,
+            related_id,
+            debit_account: String::from(debit),
+            credit_account: String::from(credit),
+            amount: 1.into(),
+        }
+    }
 
     #[tokio::test]
     async fn test_e2e_accountnames() {
-        dotenv().ok();
-        let server = TestServer::new(router().await).unwrap();
-        let response = server.get("/accountnames").await;
+        let server = test_server().await;
+        add_transactions(
+            &server,
+            vec![transaction(
+                String::from("1f"),
+                "a:test:cash",
+                "l:test:loan",
+            )],
+        )
+        .await;
+
+        let response = server.post("/query/account-names-all").await;
         let json = response.json::<responses::ResponseAccountNames>();
-        assert!(json.iter().any(|v| v.starts_with("assets:")));
+        assert!(json.iter().any(|v| v.starts_with("a:")));
     }
 
     #[tokio::test]
     async fn test_e2e_commodities() {
-        dotenv().ok();
-        let server = TestServer::new(router().await).unwrap();
-        let response = server.get("/commodities").await;
+        let server = test_server().await;
+        add_transactions(
+            &server,
+            vec![transaction(
+                String::from("1f"),
+                "a:test:cash",
+                "l:test:loan",
+            )],
+        )
+        .await;
+
+        let response = server.post("/query/commodities-all").await;
         let json = response.json::<responses::ResponseCommodities>();
-        assert!(json.iter().any(|v| v == "TEST"));
+        assert!(json
+            .iter()
+            .any(|v| v.unit == "TEST" && v.decimal_place == 0));
     }
 
     #[tokio::test]
-    async fn test_e2e_one_transaction() {
-        dotenv().ok();
-        let server = TestServer::new(router().await).unwrap();
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis() as i64;
+    async fn test_e2e_save_commodity() {
+        let server = test_server().await;
+        let eur = |decimal_place: i32, iso_code: &str| responses::SaveCommodity {
+            unit: String::from("EUR"),
+            decimal_place,
+            symbol: Some(String::from("€")),
+            name: Some(String::from("Euro")),
+            iso_code: Some(String::from(iso_code)),
+        };
 
-        let transactions = vec![responses::AddTransaction {
-            commodity_unit: String::from("TEST"),
-            code: 9999,
-            related_id: format!("{}f", 1),
-            debit_account: format!("liabilities:test:{now}:debit"),
-            credit_account: format!("liabilities:test:{now}:credit"),
-            amount: 1,
-        }];
+        let response = server.put("/mutate/commodity").json(&eur(2, "eur")).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server.put("/mutate/commodity").json(&eur(2, "EUR")).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let commodity = response.json::<responses::ResponseCommodity>();
+        assert_eq!(commodity.decimal_place, 2);
 
-        // println!("send add transaction");
+        // without transactions the decimal place may still change
+        let response = server.put("/mutate/commodity").json(&eur(3, "EUR")).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server.put("/mutate/commodity").json(&eur(2, "EUR")).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<responses::ResponseCommodity>().id,
+            commodity.id
+        );
+
+        add_transactions(
+            &server,
+            vec![responses::AddTransaction {
+                commodity_unit: String::from("EUR"),
+                ..transaction(String::from("1f"), "a:test:cash", "r:test:salary")
+            }],
+        )
+        .await;
+        let response = server.put("/mutate/commodity").json(&eur(3, "EUR")).await;
+        assert_eq!(response.status_code(), StatusCode::CONFLICT);
         let response = server
-            .put("/add")
-            .json(&responses::AddTransactions {
-                full_date2: now,
-                transactions,
+            .put("/mutate/commodity")
+            .json(&responses::SaveCommodity {
+                name: Some(String::from("Euro (cash)")),
+                ..eur(2, "EUR")
             })
             .await;
-        assert_eq!(
-            response.status_code(),
-            StatusCode::OK,
-            "unable to add transaction",
-        );
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server.post("/query/commodities-all").await;
+        let json = response.json::<responses::ResponseCommodities>();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0].name.as_deref(), Some("Euro (cash)"));
+        assert_eq!(json[0].symbol.as_deref(), Some("€"));
+        assert_eq!(json[0].decimal_place, 2);
+    }
+
+    #[tokio::test]
+    async fn test_e2e_one_transaction() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        let debit_account = format!("l:test:{now}:debit");
+        let credit_account = format!("l:test:{now}:credit");
+        add_transactions(
+            &server,
+            vec![transaction(
+                format!("{}f", 1),
+                &debit_account,
+                &credit_account,
+            )],
+        )
+        .await;
 
         {
-            let now2 = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_millis() as i64;
             let response = server
-                .get(format!("/accounttransactions/liabilities:test:{}:debit?date_newest={}&date_oldest={}", now, now2, now).as_str())
+                .post("/query/account-transactions")
+                .json(&QueryTransactionsBody {
+                    date_newest: now_millis() + 1,
+                    date_oldest: now,
+                    accounts_glob: debit_account,
+                })
                 .await;
             assert_eq!(response.status_code(), StatusCode::OK);
             let json = response.json::<responses::ResponseTransactions>();
@@ -73,57 +205,1924 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_e2e_add_idempotent() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        let debit_account = format!("l:test:{now}:debit");
+        let credit_account = format!("l:test:{now}:credit");
+        let body = responses::AddTransactions {
+            full_date2: now,
+            transactions: vec![
+                transaction(String::from("1"), &debit_account, &credit_account),
+                transaction(String::from("2"), &debit_account, &credit_account),
+            ],
+        };
+        let add = |route: &'static str| {
+            server
+                .put(route)
+                .add_header("Idempotency-Key", "add-1")
+                .json(&body)
+        };
+
+        // the same key resubmitted returns the same ids and adds nothing
+        let response = add("/mutate/add").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let ids = response.json::<responses::ResponseAdd>();
+        assert_eq!(ids.len(), 2);
+        let response = add("/mutate/add").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<responses::ResponseAdd>(), ids);
+        let balance = account_balance(&server, &debit_account).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(2));
+
+        // resubmitted transfer_ids return the same ids and add nothing
+        let resubmit = responses::AddTransactions {
+            full_date2: now,
+            transactions: body
+                .transactions
+                .iter()
+                .zip(&ids)
+                .map(|(t, id)| responses::AddTransaction {
+                    transfer_id: Some(id.clone()),
+                    ..t.clone()
+                })
+                .collect(),
+        };
+        let response = server.put("/mutate/add").json(&resubmit).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<responses::ResponseAdd>(), ids);
+        let balance = account_balance(&server, &debit_account).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(2));
+
+        // the same key on another route derives other ids
+        let response = server
+            .put("/mutate/pending")
+            .add_header("Idempotency-Key", "add-1")
+            .json(&responses::AddPendingTransactions {
+                full_date2: now,
+                timeout: 0,
+                transactions: body.transactions.clone(),
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let pending_ids = response.json::<responses::ResponseAdd>();
+        assert_eq!(pending_ids.len(), 2);
+        assert!(pending_ids.iter().all(|id| !ids.contains(id)));
+        let balance = account_balance(&server, &debit_account).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(2));
+        assert_eq!(balance.debits_pending, responses::Amount::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn test_e2e_pending_post_void() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        let debit_account = format!("l:test:{now}:debit");
+        let credit_account = format!("l:test:{now}:credit");
+        let pending = |amount: i128| responses::AddTransaction {
+            amount: amount.into(),
+            ..transaction(String::from("1"), &debit_account, &credit_account)
+        };
+        let response = server
+            .put("/mutate/pending")
+            .json(&responses::AddPendingTransactions {
+                full_date2: now,
+                timeout: 0,
+                transactions: vec![pending(10), pending(5)],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let pending_ids = response.json::<responses::ResponseAdd>();
+        assert_eq!(pending_ids.len(), 2);
+
+        let query_pending = || {
+            server
+                .post("/query/pending-transactions")
+                .text(format!(r#"{{"accounts_glob":"{debit_account}"}}"#))
+                .content_type("application/json")
+        };
+        let json = query_pending()
+            .await
+            .json::<responses::ResponseTransactions>();
+        assert_eq!(json.len(), 2);
+        assert!(json
+            .iter()
+            .all(|t| t.status == responses::TransferStatus::Pending));
+        let balance = account_balance(&server, &debit_account).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(0));
+        assert_eq!(balance.debits_pending, responses::Amount::Integer(15));
+
+        // a partial post releases the rest of the pending amount
+        let post = |pending_id: &str, amount: Option<i128>| responses::PostPendingTransactions {
+            transactions: vec![responses::PostPendingTransaction {
+                pending_id: pending_id.to_string(),
+                amount: amount.map(responses::Amount::Integer),
+            }],
+        };
+        let response = server
+            .put("/mutate/post")
+            .json(&post(&pending_ids[0], Some(4)))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let post_ids = response.json::<responses::ResponseAdd>();
+        // a retried post returns the id of the first attempt
+        let response = server
+            .put("/mutate/post")
+            .json(&post(&pending_ids[0], Some(4)))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<responses::ResponseAdd>(), post_ids);
+        let balance = account_balance(&server, &debit_account).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(4));
+        assert_eq!(balance.debits_pending, responses::Amount::Integer(5));
+
+        let response = server
+            .put("/mutate/post")
+            .json(&post(&pending_ids[0], None))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::CONFLICT);
+        let response = server
+            .put("/mutate/post")
+            .json(&post(&pending_ids[1], Some(6)))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let json = query_pending()
+            .await
+            .json::<responses::ResponseTransactions>();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0].transfer_id, pending_ids[1]);
+
+        let void = || {
+            server
+                .put("/mutate/void")
+                .json(&responses::VoidPendingTransactions {
+                    pending_ids: vec![pending_ids[1].clone()],
+                })
+        };
+        let response = void().await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let void_ids = response.json::<responses::ResponseAdd>();
+        let response = void().await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<responses::ResponseAdd>(), void_ids);
+        let json = query_pending()
+            .await
+            .json::<responses::ResponseTransactions>();
+        assert!(json.is_empty());
+        let balance = account_balance(&server, &debit_account).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(4));
+        assert_eq!(balance.debits_pending, responses::Amount::Integer(0));
+
+        let response = server
+            .put("/mutate/void")
+            .json(&responses::VoidPendingTransactions {
+                pending_ids: vec![String::from("ff")],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+
+        // exports book only the posted amount, pending and voided transfers move no funds
+        for (export, import) in [
+            ("/query/export-hledger", "/mutate/import-hledger"),
+            ("/query/export-beancount", "/mutate/import-beancount"),
+            ("/query/export-csv", "/mutate/import-csv"),
+        ] {
+            let response = server
+                .post(export)
+                .json(&QueryTransactionsBody {
+                    date_newest: now_millis() + 1,
+                    date_oldest: now,
+                    accounts_glob: debit_account.clone(),
+                })
+                .await;
+            assert_eq!(response.status_code(), StatusCode::OK, "{export}");
+            let file = response.text();
+            if export == "/query/export-csv" {
+                assert!(file.lines().next().unwrap().ends_with(",status,pending_id"));
+                assert!(file.contains(&format!(",voided,{}", pending_ids[1])));
+            }
+
+            let other_server = test_server().await;
+            let response = other_server.put(import).text(file).await;
+            assert_eq!(response.status_code(), StatusCode::OK, "{import}");
+            let balance = account_balance(&other_server, &debit_account).await;
+            assert_eq!(balance.amount, responses::Amount::Integer(4), "{import}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e2e_reverse() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        let debit_account = format!("l:test:{now}:debit");
+        let credit_account = format!("l:test:{now}:credit");
+        let response = server
+            .put("/mutate/add")
+            .json(&responses::AddTransactions {
+                full_date2: now,
+                transactions: vec![transaction(
+                    String::from("abc"),
+                    &debit_account,
+                    &credit_account,
+                )],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let transfer_ids = response.json::<responses::ResponseAdd>();
+
+        let reverse = |transfer_ids: Vec<String>| {
+            server
+                .put("/mutate/reverse")
+                .json(&responses::ReverseTransactions {
+                    full_date2: now,
+                    transfer_ids,
+                })
+        };
+        let response = reverse(transfer_ids.clone()).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        for account in [&debit_account, &credit_account] {
+            let balance = account_balance(&server, account).await;
+            assert_eq!(balance.amount, responses::Amount::Integer(0));
+        }
+
+        let response = server
+            .post("/query/account-transactions")
+            .json(&QueryTransactionsBody {
+                date_newest: 0,
+                date_oldest: 0,
+                accounts_glob: debit_account.clone(),
+            })
+            .await;
+        let json = response.json::<responses::ResponseTransactions>();
+        assert_eq!(json.len(), 2);
+        assert_eq!(json[1].reversal_of, transfer_ids[0]);
+        assert_eq!(json[1].related_id, "abc");
+        assert_eq!(json[1].debit_account, credit_account);
+
+        let response = reverse(transfer_ids.clone()).await;
+        assert_eq!(response.status_code(), StatusCode::CONFLICT);
+        let response = reverse(vec![String::from("ff")]).await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+
+        // pending transfers are voided instead, voids can not be reversed either
+        let response = server
+            .put("/mutate/pending")
+            .json(&responses::AddPendingTransactions {
+                full_date2: now,
+                timeout: 0,
+                transactions: vec![transaction(
+                    String::from("1"),
+                    &debit_account,
+                    &credit_account,
+                )],
+            })
+            .await;
+        let pending_ids = response.json::<responses::ResponseAdd>();
+        let response = reverse(pending_ids.clone()).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server
+            .put("/mutate/void")
+            .json(&responses::VoidPendingTransactions { pending_ids })
+            .await;
+        let void_ids = response.json::<responses::ResponseAdd>();
+        let response = reverse(void_ids).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn test_e2e_huge_batch_transactions() {
-        dotenv().ok();
-        let server = TestServer::new(router().await).unwrap();
+        let server = test_server().await;
         let amount = TB_MAX_BATCH_SIZE;
         let iterations = 2;
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis() as i64;
+        let now = now_millis();
 
+        let debit_account = format!("l:test:{now}:debit");
+        let credit_account = format!("l:test:{now}:credit");
         for iteration in 0..iterations {
             let transactions = (1..amount)
-                .map(|i| responses::AddTransaction {
-                    commodity_unit: String::from("TEST"),
-                    code: 9999,
-                    related_id: format!("{}f{}", i, iteration),
-                    debit_account: format!("liabilities:test:{now}:debit"),
-                    credit_account: format!("liabilities:test:{now}:credit"),
-                    amount: 1,
+                .map(|i| {
+                    transaction(
+                        format!("{}f{}", i, iteration),
+                        &debit_account,
+                        &credit_account,
+                    )
                 })
                 .collect();
 
-            // println!("send add transaction");
+            add_transactions(&server, transactions).await;
+            sleep(Duration::from_millis(20)).await;
+        }
+
+        {
             let response = server
-                .put("/add")
-                .json(&responses::AddTransactions {
-                    full_date2: now,
-                    transactions,
+                .post("/query/account-transactions")
+                .json(&QueryTransactionsBody {
+                    date_newest: now_millis() + 1,
+                    date_oldest: now,
+                    accounts_glob: debit_account.clone(),
+                })
+                .await;
+            assert_eq!(response.status_code(), StatusCode::OK);
+            let json = response.json::<responses::ResponseTransactions>();
+            assert!(json.len() > TB_MAX_BATCH_SIZE as usize);
+        }
+
+        {
+            // exports are streamed in pages of tigerbeetle batches
+            let response = server
+                .post("/query/export-csv")
+                .json(&QueryTransactionsBody {
+                    date_newest: now_millis() + 1,
+                    date_oldest: now,
+                    accounts_glob: debit_account,
                 })
                 .await;
+            assert_eq!(response.status_code(), StatusCode::OK);
+            let file = response.text();
+            assert_eq!(file.lines().count(), 1 + iterations * (amount as usize - 1));
             assert_eq!(
-                response.status_code(),
-                StatusCode::OK,
-                "unable to add transaction at iteration: {}",
-                iteration
+                file.lines()
+                    .filter(|l| l.starts_with("commodity_unit"))
+                    .count(),
+                1
             );
-            sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e2e_account_transactions_filters() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        let account = |name: &str| format!("l:test:{now}:{name}");
+        let with = |t: responses::AddTransaction, code: i32, amount: i128, unit: &str| {
+            responses::AddTransaction {
+                code,
+                amount: amount.into(),
+                commodity_unit: String::from(unit),
+                ..t
+            }
+        };
+        add_transactions(
+            &server,
+            vec![
+                with(
+                    transaction(String::from("1"), &account("a"), &account("b")),
+                    1,
+                    5,
+                    "TEST",
+                ),
+                with(
+                    transaction(String::from("2"), &account("a"), &account("c")),
+                    2,
+                    10,
+                    "TEST",
+                ),
+                with(
+                    transaction(String::from("1"), &account("b"), &account("a")),
+                    3,
+                    20,
+                    "TEST",
+                ),
+                with(
+                    transaction(String::from("3"), &account("c"), &account("a")),
+                    2,
+                    1,
+                    "OTHER",
+                ),
+            ],
+        )
+        .await;
+
+        let a = account("a");
+        let b = account("b");
+        let query = |filters: String| {
+            server
+                .post("/query/account-transactions")
+                .text(format!(
+                    r#"{{"date_newest":0,"date_oldest":{now},"accounts_glob":"{a}"{filters}}}"#
+                ))
+                .content_type("application/json")
+        };
+        for (filters, len) in [
+            ("", 4),
+            (r#","codes":[2]"#, 2),
+            (r#","codes":[1,3]"#, 2),
+            (r#","code_min":2,"code_max":3"#, 3),
+            (r#","related_id":"1""#, 2),
+            (r#","related_id":"1","codes":[3]"#, 1),
+            (r#","commodity_unit":"OTHER""#, 1),
+            (r#","amount_min":5,"amount_max":10"#, 2),
+            (r#","side":"debit""#, 2),
+            (r#","side":"credit""#, 2),
+        ] {
+            let response = query(String::from(filters)).await;
+            assert_eq!(response.status_code(), StatusCode::OK, "{filters}");
+            let json = response.json::<responses::ResponseTransactions>();
+            assert_eq!(json.len(), len, "{filters}");
         }
 
-        {
-            let now2 = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_millis() as i64;
+        let response = query(format!(r#","counterparty_glob":"{b}","side":"credit""#)).await;
+        let json = response.json::<responses::ResponseTransactions>();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0].debit_account, b);
+        assert_eq!(json[0].code, 3);
+
+        // filters apply before the page is cut
+        let response = query(String::from(r#","codes":[2],"limit":1"#)).await;
+        assert_eq!(response.json::<responses::ResponseTransactions>().len(), 1);
+        let cursor = response.header("next-cursor");
+        let cursor = cursor.to_str().unwrap();
+        let response = query(format!(r#","codes":[2],"limit":1,"cursor":"{cursor}""#)).await;
+        assert_eq!(response.json::<responses::ResponseTransactions>().len(), 1);
+        assert_eq!(response.maybe_header("next-cursor"), None);
+
+        for filters in [
+            r#","related_id":"zz""#,
+            r#","commodity_unit":"NONE""#,
+            r#","code_min":3,"code_max":2"#,
+            r#","amount_min":-1"#,
+        ] {
+            let response = query(String::from(filters)).await;
+            assert_eq!(response.status_code(), StatusCode::BAD_REQUEST, "{filters}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e2e_account_transactions_decimal_bounds() {
+        let server = test_server().await;
+        let now = now_millis();
+        let response = server
+            .put("/mutate/commodity")
+            .json(&responses::SaveCommodity {
+                unit: String::from("EUR"),
+                decimal_place: 2,
+                symbol: None,
+                name: None,
+                iso_code: None,
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let a = format!("l:test:{now}:a");
+        let b = format!("l:test:{now}:b");
+        let with = |related_id: &str, amount: i128, unit: &str| responses::AddTransaction {
+            amount: amount.into(),
+            commodity_unit: String::from(unit),
+            ..transaction(String::from(related_id), &a, &b)
+        };
+        add_transactions(
+            &server,
+            vec![
+                with("1", 150, "EUR"),
+                with("2", 250, "EUR"),
+                with("3", 1, "TEST"),
+                with("4", 2, "TEST"),
+            ],
+        )
+        .await;
+
+        // EUR has 2 decimal places and TEST none, bounds round inwards per commodity so
+        // 1.01 to 1.99 still matches 1.50 EUR but no TEST amount
+        for (filters, related_ids) in [
+            (r#","amount_min":"1.5""#, vec!["1", "2", "4"]),
+            (r#","amount_max":"2.49""#, vec!["1", "3", "4"]),
+            (r#","amount_min":"1.5","amount_max":"2.49""#, vec!["1", "4"]),
+            (r#","amount_min":"1.01","amount_max":"1.99""#, vec!["1"]),
+        ] {
+            let response = server
+                .post("/query/account-transactions")
+                .add_query_param("amount_format", "decimal")
+                .text(format!(
+                    r#"{{"date_newest":0,"date_oldest":{now},"accounts_glob":"{a}"{filters}}}"#
+                ))
+                .content_type("application/json")
+                .await;
+            assert_eq!(response.status_code(), StatusCode::OK, "{filters}");
+            let mut json = response
+                .json::<responses::ResponseTransactions>()
+                .into_iter()
+                .map(|t| t.related_id)
+                .collect::<Vec<String>>();
+            json.sort();
+            assert_eq!(json, related_ids, "{filters}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e2e_export_ndjson() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        let debit_account = format!("l:test:{now}:debit");
+        let credit_account = format!("l:test:{now}:credit");
+        add_transactions(
+            &server,
+            (0..3)
+                .map(|i| transaction(format!("{i}e"), &debit_account, &credit_account))
+                .collect(),
+        )
+        .await;
+        let body = QueryTransactionsBody {
+            date_newest: now_millis() + 1,
+            date_oldest: now,
+            accounts_glob: debit_account.clone(),
+        };
+
+        let response = server
+            .post("/query/export-ndjson")
+            .add_query_param("amount_format", "decimal")
+            .json(&body)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.header("content-type"), "application/x-ndjson");
+        assert_eq!(
+            response.header("content-disposition"),
+            "attachment; filename=\"transactions.ndjson\""
+        );
+        let file = response.text();
+        let lines = file.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines
+            .iter()
+            .all(|l| l.starts_with('{') && l.contains(&debit_account)));
+
+        let response = server.post("/query/export-hledger").json(&body).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.header("content-disposition"),
+            "attachment; filename=\"transactions.journal\""
+        );
+        assert_eq!(response.text().matches(&credit_account).count(), 3);
+
+        let response = server
+            .post("/query/export-csv")
+            .text(format!(
+                r#"{{"date_newest":0,"date_oldest":{now},"accounts_glob":"{debit_account}","limit":1}}"#
+            ))
+            .content_type("application/json")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_e2e_account_transactions_pages() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        // transfers between matched accounts appear once, interleaved with the other accounts
+        let account = |name: &str| format!("l:test:{now}:{name}");
+        for i in 0..3 {
+            add_transactions(
+                &server,
+                vec![
+                    transaction(format!("{i}a"), &account("a"), &account("b")),
+                    transaction(format!("{i}b"), &account("b"), &account("c")),
+                    transaction(format!("{i}c"), &account("c"), &account("other")),
+                    transaction(format!("{i}d"), &account("a"), &account("c")),
+                ],
+            )
+            .await;
+        }
+        let glob = format!("l:test:{now}:a|l:test:{now}:b|l:test:{now}:c");
+        let body = |page: &str| {
+            format!(r#"{{"date_newest":0,"date_oldest":{now},"accounts_glob":"{glob}"{page}}}"#)
+        };
+
+        let all = server
+            .post("/query/account-transactions")
+            .text(body(""))
+            .content_type("application/json")
+            .await;
+        assert_eq!(all.status_code(), StatusCode::OK);
+        assert_eq!(all.maybe_header("next-cursor"), None);
+        let all = all.json::<responses::ResponseTransactions>();
+        assert_eq!(all.len(), 12);
+
+        let mut pages: Vec<responses::Transaction> = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = match &cursor {
+                Some(cursor) => format!(r#","limit":5,"cursor":"{cursor}""#),
+                None => String::from(r#","limit":5"#),
+            };
             let response = server
-                .get(format!("/accounttransactions/liabilities:test:{}:debit?date_newest={}&date_oldest={}", now, now2, now).as_str())
+                .post("/query/account-transactions")
+                .text(body(&page))
+                .content_type("application/json")
                 .await;
             assert_eq!(response.status_code(), StatusCode::OK);
             let json = response.json::<responses::ResponseTransactions>();
-            assert!(json.len() > TB_MAX_BATCH_SIZE as usize);
+            assert!(json.len() <= 5);
+            pages.extend(json);
+            cursor = response
+                .maybe_header("next-cursor")
+                .map(|v| v.to_str().unwrap().to_string());
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(
+            pages.iter().map(|t| &t.transfer_id).collect::<Vec<_>>(),
+            all.iter().map(|t| &t.transfer_id).collect::<Vec<_>>(),
+        );
+
+        for page in [
+            r#","cursor":"00""#,
+            r#","limit":5,"cursor":"zz""#,
+            r#","limit":0"#,
+        ] {
+            let response = server
+                .post("/query/account-transactions")
+                .text(body(page))
+                .content_type("application/json")
+                .await;
+            assert_eq!(response.status_code(), StatusCode::BAD_REQUEST, "{page}");
         }
     }
+
+    #[tokio::test]
+    async fn test_e2e_import_hledger() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        let response = server
+            .put("/mutate/import-hledger")
+            .text(format!(
+                "2025-04-03 * Paycheck\n    assets:test:{now}  10 TEST\n    revenues:test:{now}\n"
+            ))
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        assert_eq!(response.json::<responses::ResponseAdd>().len(), 1);
+
+        let response = server
+            .post("/query/account-transactions")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: now,
+                accounts_glob: format!("a:test:{now}"),
+            })
+            .await;
+        let json = response.json::<responses::ResponseTransactions>();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0].credit_account, format!("r:test:{now}"));
+        assert_eq!(json[0].debit_amount, responses::Amount::Integer(10));
+
+        // a resubmitted journal with the same key adds nothing
+        let journal = format!(
+            "2025-04-04 * Paycheck\n    assets:test:{now}  5 TEST\n    revenues:test:{now}\n\n2025-04-05 * Paycheck\n    assets:test:{now}  5 TEST\n    revenues:test:{now}\n"
+        );
+        let import = || {
+            server
+                .put("/mutate/import-hledger")
+                .add_header("Idempotency-Key", "journal-1")
+                .text(journal.clone())
+        };
+        let response = import().await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let transfer_ids = response.json::<responses::ResponseAdd>();
+        assert_eq!(transfer_ids.len(), 2);
+        let response = import().await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<responses::ResponseAdd>(), transfer_ids);
+        let balance = account_balance(&server, &format!("a:test:{now}")).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(20));
+
+        // without a key a corrected journal skips the transactions added before the rejection
+        let journal = |amount: i128| {
+            format!(
+                "2025-04-06 * Paycheck\n    assets:test:{now}  5 TEST\n    revenues:test:{now}\n\n2025-04-07 * Refund\n    assets:test:{now}:empty  {amount} TEST\n    revenues:test:{now}\n"
+            )
+        };
+        let response = server.put("/mutate/import-hledger").text(journal(-5)).await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        let balance = account_balance(&server, &format!("a:test:{now}")).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(25));
+        let response = server.put("/mutate/import-hledger").text(journal(5)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server.put("/mutate/import-hledger").text(journal(5)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let balance = account_balance(&server, &format!("a:test:{now}")).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(25));
+        let balance = account_balance(&server, &format!("a:test:{now}:empty")).await;
+        assert_eq!(balance.amount, responses::Amount::Integer(5));
+
+        let response = server
+            .put("/mutate/import-hledger")
+            .text("2025-04-03 * Paycheck\n    budget:food  10 TEST\n    revenues:test\n")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("line 2"));
+    }
+
+    #[tokio::test]
+    async fn test_e2e_beancount_round_trip() {
+        let server = test_server().await;
+        let now = now_millis();
+        add_transactions(
+            &server,
+            vec![transaction(
+                String::from("1f"),
+                "a:test:cash",
+                "l:test:loan",
+            )],
+        )
+        .await;
+
+        let response = server
+            .post("/query/export-beancount")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: now,
+                accounts_glob: String::from("a:test:cash"),
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let file = response.text();
+        assert!(file.contains("open Assets:Test:Cash TEST"));
+
+        let other_server = test_server().await;
+        let response = other_server
+            .put("/mutate/import-beancount")
+            .text(file)
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+
+        let response = other_server
+            .post("/query/account-transactions")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: now,
+                accounts_glob: String::from("a:test:cash"),
+            })
+            .await;
+        let json = response.json::<responses::ResponseTransactions>();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0].related_id, "1f");
+        assert_eq!(json[0].credit_account, "l:test:loan");
+    }
+
+    #[tokio::test]
+    async fn test_e2e_csv_round_trip() {
+        let server = test_server().await;
+        let now = now_millis();
+        add_transactions(
+            &server,
+            vec![transaction(
+                String::from("1f"),
+                "a:test:cash",
+                "l:test:loan",
+            )],
+        )
+        .await;
+
+        let response = server
+            .post("/query/export-csv")
+            .add_query_param("delimiter", ";")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: now,
+                accounts_glob: String::from("a:test:cash"),
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let file = response.text();
+        assert!(file.starts_with("commodity_unit;commodity_decimal;code"));
+
+        let other_server = test_server().await;
+        let response = other_server
+            .put("/mutate/import-csv")
+            .add_query_param("delimiter", ";")
+            .text(file)
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+
+        let response = other_server
+            .post("/query/account-transactions")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: now,
+                accounts_glob: String::from("a:test:cash"),
+            })
+            .await;
+        let json = response.json::<responses::ResponseTransactions>();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0].credit_account, "l:test:loan");
+    }
+
+    #[tokio::test]
+    async fn test_e2e_import_csv_grouped() {
+        let server = test_server().await;
+        let header =
+            "commodity_unit,code,full_date2,related_id,debit_account,credit_account,amount";
+
+        // the second leg overdraws a:test:cash, so neither leg is added
+        let response = server
+            .put("/mutate/import-csv")
+            .text(format!(
+                "{header}\nTEST,1,1,a,a:test:cash,r:test:salary,10\nTEST,1,1,a,x:test:food,a:test:cash,20\n"
+            ))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let file = format!(
+            "{header}\nTEST,1,1,a,a:test:cash,r:test:salary,10\nTEST,1,1,a,x:test:food,a:test:cash,5\nTEST,1,2,b,x:test:food,a:test:cash,5\n"
+        );
+        let response = server
+            .put("/mutate/import-csv")
+            .add_header("Idempotency-Key", "import-1")
+            .text(file.clone())
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        let summary = response.json::<responses::ResponseImportCsv>();
+        assert_eq!(summary.imported, 3);
+        assert!(summary.skipped.is_empty());
+
+        let response = server
+            .put("/mutate/import-csv")
+            .add_header("Idempotency-Key", "import-1")
+            .text(file.clone())
+            .await;
+        let summary = response.json::<responses::ResponseImportCsv>();
+        assert_eq!(summary.imported, 0);
+        assert_eq!(
+            summary
+                .skipped
+                .iter()
+                .map(|s| s.row)
+                .collect::<Vec<usize>>(),
+            vec![2, 3, 4]
+        );
+
+        // an imported row that changed is a conflict, not skipped
+        let response = server
+            .put("/mutate/import-csv")
+            .add_header("Idempotency-Key", "import-1")
+            .text(file.replace(",5\nTEST,1,2", ",4\nTEST,1,2"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::CONFLICT);
+        assert!(response.text().contains("row 3"), "{}", response.text());
+
+        // accounts are not created when a row is invalid
+        let response = server
+            .put("/mutate/import-csv")
+            .text(format!(
+                "{header}\nTEST,1,3,c,a:test:new,r:test:salary,1\nTEST,1,3,xyz,a:test:cash,r:test:salary,1\n"
+            ))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("row 3"), "{}", response.text());
+        let response = server.post("/query/account-names-all").await;
+        let json = response.json::<responses::ResponseAccountNames>();
+        assert!(!json.iter().any(|v| v == "a:test:new"));
+
+        let response = server
+            .post("/query/account-balances")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: 0,
+                accounts_glob: String::from("a:test:cash"),
+            })
+            .await;
+        let json = response.json::<responses::ResponseBalances>();
+        assert_eq!(json[0].amount, responses::Amount::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_e2e_decimal_amounts() {
+        let server = test_server().await;
+        let response = server
+            .put("/mutate/migrate")
+            .json(&responses::RequestMigrate {
+                commodities: vec![responses::MigrateCommodity {
+                    id: 2,
+                    unit: String::from("EUR"),
+                    decimal_place: 2,
+                }],
+                accounts: vec![responses::MigrateAccount {
+                    t: String::from("1"),
+                    n: String::from("e:test:migrated"),
+                    c: 2,
+                }],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let add = |amount: &str| responses::AddTransactions {
+            full_date2: now_millis(),
+            transactions: vec![responses::AddTransaction {
+                commodity_unit: String::from("EUR"),
+                amount: responses::Amount::Decimal(String::from(amount)),
+                ..transaction(String::from("1f"), "a:test:cash", "r:test:salary")
+            }],
+        };
+        let response = server
+            .put("/mutate/add")
+            .add_query_param("amount_format", "decimal")
+            .json(&add("1.234"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server.put("/mutate/add").json(&add("12.34")).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server
+            .put("/mutate/add")
+            .add_query_param("amount_format", "decimal")
+            .json(&add("12.34"))
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+
+        let body = QueryTransactionsBody {
+            date_newest: now_millis() + 1,
+            date_oldest: 0,
+            accounts_glob: String::from("a:test:cash"),
+        };
+        let response = server.post("/query/account-balances").json(&body).await;
+        let json = response.json::<responses::ResponseBalances>();
+        assert_eq!(json[0].amount, responses::Amount::Integer(1234));
+        let response = server
+            .post("/query/account-balances")
+            .add_query_param("amount_format", "decimal")
+            .json(&body)
+            .await;
+        let json = response.json::<responses::ResponseBalances>();
+        assert_eq!(
+            json[0].amount,
+            responses::Amount::Decimal(String::from("12.34"))
+        );
+
+        // csv amounts are integers unless amount_format=decimal
+        let header =
+            "commodity_unit,code,full_date2,related_id,debit_account,credit_account,amount";
+        let response = server
+            .put("/mutate/import-csv")
+            .text(format!(
+                "{header}\nEUR,1,1,2f,a:test:cash,r:test:salary,100\n"
+            ))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server
+            .put("/mutate/import-csv")
+            .add_query_param("amount_format", "decimal")
+            .text(format!(
+                "{header}\nEUR,1,1,3f,a:test:cash,r:test:salary,1.00\n"
+            ))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server.post("/query/account-balances").json(&body).await;
+        let json = response.json::<responses::ResponseBalances>();
+        assert_eq!(json[0].amount, responses::Amount::Integer(1434));
+    }
+
+    #[tokio::test]
+    async fn test_e2e_large_amounts() {
+        let server = test_server().await;
+        let large = i64::MAX as i128 * 1000;
+        let response = server
+            .put("/mutate/add")
+            .text(format!(
+                r#"{{"fullDate2":{},"transactions":[
+                    {{"commodityUnit":"TEST","code":1,"relatedId":"1f","debitAccount":"a:test:cash","creditAccount":"r:test:salary","amount":"{}"}},
+                    {{"commodityUnit":"TEST","code":1,"relatedId":"1f","debitAccount":"a:test:cash","creditAccount":"r:test:salary","amount":1}}
+                ]}}"#,
+                now_millis(),
+                large
+            ))
+            .content_type("application/json")
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+
+        let response = server
+            .post("/query/account-balances")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: 0,
+                accounts_glob: String::from("a:test:cash"),
+            })
+            .await;
+        // amounts are strings so they are not rounded by json parsers
+        assert!(response
+            .text()
+            .contains(&format!("\"amount\":\"{}\"", large + 1)));
+        let json = response.json::<responses::ResponseBalances>();
+        assert_eq!(json[0].amount, responses::Amount::Integer(large + 1));
+    }
+
+    #[tokio::test]
+    async fn test_e2e_prices() {
+        let server = test_server().await;
+        let now = now_millis();
+
+        // EUR priced in TEST by a json request, USD in EUR by a P directive
+        let response = server
+            .put("/mutate/prices")
+            .json(&responses::RequestPrices {
+                prices: vec![responses::Price {
+                    commodity_unit: String::from("EUR"),
+                    quote_unit: String::from("TEST"),
+                    date: now - 1000,
+                    price: String::from("2.5"),
+                }],
+            })
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        // prices of a rejected journal are not saved
+        let response = server
+            .put("/mutate/import-hledger")
+            .text(format!(
+                "P 2025-04-01 GBP 1.2 EUR\n\n2025-04-03 * Groceries\n    expenses:test:{now}  4 EUR\n    assets:test:{now}:empty\n"
+            ))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = server
+            .post("/query/prices")
+            .text(r#"{"commodity_unit":"GBP"}"#)
+            .content_type("application/json")
+            .await;
+        assert!(response.json::<responses::ResponsePrices>().is_empty());
+
+        let response = server
+            .put("/mutate/import-hledger")
+            .text(format!(
+                "P 2025-04-01 USD 0.9 EUR\n\n2025-04-03 * Paycheck\n    assets:test:{now}  4 EUR\n    revenues:test:{now}\n"
+            ))
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+
+        let response = server
+            .post("/query/prices")
+            .text(r#"{"commodity_unit":"EUR"}"#)
+            .content_type("application/json")
+            .await;
+        let json = response.json::<responses::ResponsePrices>();
+        assert_eq!(json.len(), 2);
+        assert_eq!(json[0].commodity_unit, "USD");
+        assert_eq!(json[1].price, "2.5");
+
+        let response = server
+            .post("/query/account-balances")
+            .text(format!(
+                r#"{{"accounts_glob":"a:test:{now}","value_in":"TEST"}}"#
+            ))
+            .content_type("application/json")
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        let json = response.json::<responses::ResponseBalances>();
+        let value = json[0].value.as_ref().expect("value requested");
+        assert_eq!(value.commodity_unit, "TEST");
+        assert_eq!(value.amount, responses::Amount::Integer(10));
+
+        // the P directive price is inverted, 4 EUR at 0.9 is 4.44 USD rounded to 4
+        let response = server
+            .post("/query/account-balances")
+            .text(format!(
+                r#"{{"accounts_glob":"a:test:{now}","value_in":"USD"}}"#
+            ))
+            .content_type("application/json")
+            .await;
+        let json = response.json::<responses::ResponseBalances>();
+        let value = json[0].value.as_ref().expect("value requested");
+        assert_eq!(value.amount, responses::Amount::Integer(4));
+
+        let response = server
+            .post("/query/account-balances")
+            .text(format!(
+                r#"{{"accounts_glob":"a:test:{now}","value_in":"UNKNOWN"}}"#
+            ))
+            .content_type("application/json")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_e2e_exchange() {
+        let server = test_server().await;
+        let exchange = responses::AddExchange {
+            full_date2: now_millis(),
+            code: 1,
+            related_id: String::from("1f"),
+            debit_account: String::from("a:bank:eur"),
+            debit_commodity_unit: String::from("EUR"),
+            debit_amount: responses::Amount::Integer(92),
+            credit_account: String::from("a:bank:usd"),
+            credit_commodity_unit: String::from("USD"),
+            credit_amount: responses::Amount::Integer(100),
+        };
+
+        add_transactions(
+            &server,
+            vec![responses::AddTransaction {
+                commodity_unit: String::from("USD"),
+                amount: responses::Amount::Integer(100),
+                ..transaction(String::from("2f"), "a:bank:usd", "r:test:salary")
+            }],
+        )
+        .await;
+
+        let response = server.put("/mutate/exchange").json(&exchange).await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        assert_eq!(response.json::<responses::ResponseAdd>().len(), 2);
+
+        let response = server
+            .post("/query/account-balances")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: 0,
+                accounts_glob: String::from("a:bank:**|e:conversion:**"),
+            })
+            .await;
+        let balances = response
+            .json::<responses::ResponseBalances>()
+            .into_iter()
+            .map(|b| (b.account_name, b.commodity_unit, b.amount))
+            .collect::<Vec<_>>();
+        for balance in [
+            ("a:bank:usd", "USD", 0),
+            ("e:conversion:usd", "USD", 100),
+            ("a:bank:eur", "EUR", 92),
+            ("e:conversion:eur", "EUR", -92),
+        ] {
+            assert!(
+                balances.contains(&(
+                    String::from(balance.0),
+                    String::from(balance.1),
+                    responses::Amount::Integer(balance.2)
+                )),
+                "{:?}",
+                balances
+            );
+        }
+
+        for debit_commodity_unit in ["USD", "$"] {
+            let response = server
+                .put("/mutate/exchange")
+                .json(&responses::AddExchange {
+                    debit_commodity_unit: String::from(debit_commodity_unit),
+                    ..exchange.clone()
+                })
+                .await;
+            assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        }
+
+        // an invalid related id is rejected before any account is created
+        let response = server
+            .put("/mutate/exchange")
+            .json(&responses::AddExchange {
+                related_id: String::from("xyz"),
+                debit_account: String::from("a:bank:new"),
+                ..exchange.clone()
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server
+            .put("/mutate/pending")
+            .json(&responses::AddPendingTransactions {
+                full_date2: now_millis(),
+                timeout: 0,
+                transactions: vec![transaction(String::from("xyz"), "a:bank:new", "r:salary")],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server.post("/query/account-names-all").await;
+        let json = response.json::<responses::ResponseAccountNames>();
+        assert!(!json.iter().any(|v| v == "a:bank:new"));
+    }
+
+    #[tokio::test]
+    async fn test_e2e_revalue() {
+        let server = test_server().await;
+        let now = now_millis();
+        let usd_in_eur = |date: i64, price: &str| responses::Price {
+            commodity_unit: String::from("USD"),
+            quote_unit: String::from("EUR"),
+            date,
+            price: String::from(price),
+        };
+
+        let response = server
+            .put("/mutate/prices")
+            .json(&responses::RequestPrices {
+                prices: vec![usd_in_eur(now - 1000, "0.9")],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        add_transactions(
+            &server,
+            vec![responses::AddTransaction {
+                commodity_unit: String::from("USD"),
+                amount: responses::Amount::Integer(100),
+                ..transaction(String::from("1f"), "a:bank:usd", "r:test:salary")
+            }],
+        )
+        .await;
+
+        let revalue = |date: i64, price: &str| responses::Revalue {
+            date,
+            reporting_unit: String::from("EUR"),
+            code: 1,
+            prices: vec![usd_in_eur(date, price)],
+            gain_account: None,
+            loss_account: None,
+        };
+        let revalue_adjustment = |response: axum_test::TestResponse| {
+            assert_eq!(
+                response.status_code(),
+                StatusCode::OK,
+                "{}",
+                response.text()
+            );
+            let json = response.json::<responses::ResponseRevalue>();
+            assert_eq!(json.commodities.len(), 1);
+            assert_eq!(json.commodities[0].commodity_unit, "USD");
+            assert_eq!(
+                json.commodities[0].historical_cost,
+                responses::Amount::Integer(90)
+            );
+            json.commodities[0].adjustment.clone()
+        };
+
+        // the transaction was entered in the ledger after this date
+        let response = server
+            .put("/mutate/revalue")
+            .json(&revalue(now - 500, "0.95"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let json = response.json::<responses::ResponseRevalue>();
+        assert_eq!(json.commodities[0].balance, responses::Amount::Integer(0));
+        assert_eq!(
+            json.commodities[0].adjustment,
+            responses::Amount::Integer(0)
+        );
+
+        // 100 USD bought at 0.9 is now worth 95 EUR
+        let period = revalue(now + 1000, "0.95");
+        let response = server.put("/mutate/revalue").json(&period).await;
+        assert_eq!(revalue_adjustment(response), responses::Amount::Integer(5));
+        let response = server.put("/mutate/revalue").json(&period).await;
+        assert_eq!(revalue_adjustment(response), responses::Amount::Integer(0));
+
+        // only the difference to the earlier period is booked
+        let response = server
+            .put("/mutate/revalue")
+            .json(&revalue(now + 2000, "0.92"))
+            .await;
+        assert_eq!(revalue_adjustment(response), responses::Amount::Integer(-3));
+
+        // a period between revalued periods would change what the later period booked
+        let response = server
+            .put("/mutate/revalue")
+            .json(&revalue(now + 1500, "0.99"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server.put("/mutate/revalue").json(&period).await;
+        assert_eq!(revalue_adjustment(response), responses::Amount::Integer(0));
+
+        let response = server
+            .post("/query/account-balances")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: 0,
+                accounts_glob: String::from("r:fx:unrealized|x:fx:unrealized"),
+            })
+            .await;
+        let balances = response
+            .json::<responses::ResponseBalances>()
+            .into_iter()
+            .map(|b| (b.account_name, b.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            balances,
+            vec![
+                (
+                    String::from("r:fx:unrealized"),
+                    responses::Amount::Integer(-5)
+                ),
+                (
+                    String::from("x:fx:unrealized"),
+                    responses::Amount::Integer(3)
+                ),
+            ]
+        );
+
+        // US-D and USD would both be revalued against e:conversion:usd
+        add_transactions(
+            &server,
+            vec![responses::AddTransaction {
+                commodity_unit: String::from("US-D"),
+                ..transaction(String::from("1f"), "a:bank:usd", "r:test:salary")
+            }],
+        )
+        .await;
+        let response = server
+            .put("/mutate/revalue")
+            .json(&revalue(now + 3000, "0.92"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("e:conversion:usd"));
+    }
+
+    #[tokio::test]
+    async fn test_e2e_balance_sheet() {
+        let server = test_server().await;
+        let amount = |amount: i128, t: responses::AddTransaction| responses::AddTransaction {
+            amount: responses::Amount::Integer(amount),
+            ..t
+        };
+        add_transactions(
+            &server,
+            vec![
+                amount(
+                    10,
+                    transaction(String::from("1f"), "a:bank:eur", "r:salary"),
+                ),
+                amount(3, transaction(String::from("1f"), "x:food", "l:card")),
+                amount(
+                    1,
+                    transaction(String::from("1f"), "e:owner:draw", "a:bank:eur"),
+                ),
+                amount(2, transaction(String::from("1f"), "a:cash", "r:salary")),
+            ],
+        )
+        .await;
+
+        let response = server
+            .post("/query/balance-sheet")
+            .text(r#"{"depth":2}"#)
+            .content_type("application/json")
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        let json = response.json::<responses::ResponseBalanceSheet>();
+        let rows = |index: usize| {
+            json.sections[index]
+                .rows
+                .iter()
+                .map(|r| (r.account_name.as_str(), r.depth, r.amount.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(json.sections[0].account_type, models::AccountType::Assets);
+        assert_eq!(
+            rows(0),
+            vec![
+                ("a", 1, responses::Amount::Integer(11)),
+                ("a:bank", 2, responses::Amount::Integer(9)),
+                ("a:cash", 2, responses::Amount::Integer(2)),
+            ]
+        );
+        // liabilities and equity are positive when credited
+        assert_eq!(
+            rows(1),
+            vec![
+                ("l", 1, responses::Amount::Integer(3)),
+                ("l:card", 2, responses::Amount::Integer(3)),
+            ]
+        );
+        assert_eq!(rows(2)[0], ("e", 1, responses::Amount::Integer(-1)));
+        assert_eq!(json.checks.len(), 1);
+        assert_eq!(json.checks[0].net_income, responses::Amount::Integer(9));
+        assert!(json.checks[0].balanced);
+
+        let response = server
+            .post("/query/balance-sheet")
+            .text(r#"{"as_of":1000}"#)
+            .content_type("application/json")
+            .await;
+        let json = response.json::<responses::ResponseBalanceSheet>();
+        assert!(json.sections[0]
+            .rows
+            .iter()
+            .all(|r| r.amount == responses::Amount::Integer(0)));
+    }
+
+    #[tokio::test]
+    async fn test_e2e_income_statement() {
+        let server = test_server().await;
+        let day = 24 * 60 * 60 * 1000;
+        let before = now_millis();
+        add_transactions(
+            &server,
+            vec![
+                transaction(String::from("1f"), "a:bank", "r:salary:base"),
+                transaction(String::from("1f"), "a:bank", "r:salary:bonus"),
+            ],
+        )
+        .await;
+        // the full_date2 of a transaction does not move it to another period
+        let response = server
+            .put("/mutate/add")
+            .json(&responses::AddTransactions {
+                full_date2: before - 10 * day,
+                transactions: vec![transaction(String::from("2f"), "x:food", "a:bank")],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let after = now_millis() + 1;
+
+        // Etc/GMT-2 is two hours ahead of UTC all year
+        let income_statement = |start: i64, end: i64| {
+            server
+                .post("/query/income-statement")
+                .text(format!(
+                    r#"{{"period":"daily","start":{},"end":{},"timezone":"Etc/GMT-2"}}"#,
+                    start, end
+                ))
+                .content_type("application/json")
+        };
+        let response = income_statement(before - day, after).await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        let json = response.json::<responses::ResponseIncomeStatement>();
+        assert_eq!(json.timezone, "Etc/GMT-2");
+        assert!(json.periods.len() >= 2);
+        assert_eq!(
+            (json.periods[0].start + 2 * 60 * 60 * 1000).rem_euclid(day),
+            0
+        );
+        let row = |index: usize, name: &str| {
+            json.sections[index]
+                .rows
+                .iter()
+                .find(|r| r.account_name == name)
+                .map(|r| (r.amounts[0].clone(), r.total.clone()))
+        };
+        let zero = responses::Amount::from(0);
+        assert_eq!(json.sections[0].account_type, models::AccountType::Revenues);
+        assert_eq!(
+            row(0, "r"),
+            Some((zero.clone(), responses::Amount::from(2)))
+        );
+        assert_eq!(
+            row(0, "r:salary:bonus"),
+            Some((zero.clone(), responses::Amount::from(1)))
+        );
+        assert_eq!(
+            row(1, "x"),
+            Some((zero.clone(), responses::Amount::from(1)))
+        );
+        assert_eq!(json.net_income[0].total, responses::Amount::from(1));
+
+        // transfers added after the last period are not read
+        let response = income_statement(before - 2 * day, before - day).await;
+        let json = response.json::<responses::ResponseIncomeStatement>();
+        assert!(json
+            .sections
+            .iter()
+            .flat_map(|s| s.rows.iter())
+            .all(|r| r.total == zero));
+
+        let response = server
+            .post("/query/income-statement")
+            .text(r#"{"period":"daily","start":0,"end":1,"timezone":"Mars/Olympus"}"#)
+            .content_type("application/json")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_e2e_cash_flow() {
+        let server = test_server().await;
+        let now = now_millis();
+        let checking = "a:bank:checking";
+        add_transactions(
+            &server,
+            vec![
+                transaction(String::from("1f"), checking, "r:salary"),
+                transaction(String::from("1f"), checking, "r:salary"),
+                transaction(String::from("1f"), checking, "r:salary"),
+                transaction(String::from("1f"), checking, "l:loan"),
+                transaction(String::from("1f"), "x:food", checking),
+                transaction(String::from("1f"), "a:stocks", checking),
+                transaction(String::from("1f"), "x:interest", checking),
+                transaction(String::from("1f"), "a:bank:savings", checking),
+            ],
+        )
+        .await;
+
+        let response = server
+            .post("/query/cash-flow")
+            .text(format!(
+                r#"{{"date_newest":{},"date_oldest":{},"cash_accounts_glob":"a:bank:**","rules":[{{"prefix":"x:interest","activity":"financing"}}]}}"#,
+                now_millis() + 1,
+                now
+            ))
+            .content_type("application/json")
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        let json = response.json::<responses::ResponseCashFlow>();
+        let flows = |amounts: &[responses::CashFlowAmounts]| {
+            amounts
+                .iter()
+                .map(|a| (a.inflow.clone(), a.outflow.clone(), a.net.clone()))
+                .collect::<Vec<_>>()
+        };
+        let flow = |inflow: i128, outflow: i128, net: i128| {
+            vec![(inflow.into(), outflow.into(), net.into())]
+        };
+        assert_eq!(
+            json.sections[0].activity,
+            responses::CashFlowActivity::Operating
+        );
+        assert_eq!(flows(&json.sections[0].amounts), flow(3, 1, 2));
+        assert_eq!(flows(&json.sections[1].amounts), flow(0, 1, -1));
+        assert_eq!(flows(&json.sections[2].amounts), flow(1, 1, 0));
+        assert_eq!(flows(&json.totals), flow(4, 3, 1));
+
+        let response = server
+            .post("/query/cash-flow")
+            .text(r#"{"date_newest":1,"date_oldest":0,"cash_accounts_glob":"a:bank:**","rules":[{"prefix":"bank","activity":"financing"}]}"#)
+            .content_type("application/json")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_e2e_trial_balance() {
+        let ledger = Arc::new(ledger::MemoryLedger::new());
+        let server = TestServer::new(app(AppState {
+            store: store::connect("sqlite://:memory:").await,
+            ledger: ledger.clone(),
+            allow_add: true,
+            allow_migrate: true,
+        }))
+        .unwrap();
+        add_transactions(
+            &server,
+            vec![
+                transaction(String::from("1f"), "a:bank", "r:salary"),
+                transaction(String::from("1f"), "x:food", "a:bank"),
+            ],
+        )
+        .await;
+        // an account row without a tigerbeetle account and the reverse
+        let response = server
+            .put("/mutate/migrate")
+            .json(&responses::RequestMigrate {
+                commodities: vec![responses::MigrateCommodity {
+                    id: 2,
+                    unit: String::from("EUR"),
+                    decimal_place: 2,
+                }],
+                accounts: vec![responses::MigrateAccount {
+                    t: String::from("ff"),
+                    n: String::from("a:lost"),
+                    c: 2,
+                }],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        ledger
+            .create_accounts(vec![tigerbeetle_unofficial::Account::new(0xee, 2, 1)])
+            .await
+            .unwrap();
+
+        let response = server.post("/query/trial-balance").await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        let json = response.json::<responses::ResponseTrialBalance>();
+        let accounts = json
+            .accounts
+            .iter()
+            .map(|a| (a.account_name.as_str(), a.debits.clone(), a.credits.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            accounts,
+            vec![
+                ("a:bank", 1.into(), 1.into()),
+                ("a:lost", 0.into(), 0.into()),
+                ("r:salary", 0.into(), 1.into()),
+                ("x:food", 1.into(), 0.into()),
+            ]
+        );
+        assert_eq!(json.ledgers.len(), 2);
+        assert_eq!(json.ledgers[0].commodity_unit.as_deref(), Some("TEST"));
+        assert_eq!(json.ledgers[0].debits, responses::Amount::Integer(2));
+        assert!(json.ledgers.iter().all(|l| l.balanced));
+        let orphans = json
+            .orphans
+            .iter()
+            .map(|o| (o.kind, o.tb_id.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            orphans,
+            vec![
+                (responses::OrphanKind::MissingInLedger, "ff"),
+                (responses::OrphanKind::MissingInStore, "ee"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_e2e_account_register() {
+        let server = test_server().await;
+        add_transactions(
+            &server,
+            vec![transaction(String::from("1f"), "a:bank", "r:salary")],
+        )
+        .await;
+        sleep(Duration::from_millis(2)).await;
+        let after_first = now_millis();
+        sleep(Duration::from_millis(2)).await;
+        add_transactions(
+            &server,
+            vec![
+                transaction(String::from("2f"), "a:bank", "r:salary"),
+                transaction(String::from("2f"), "x:food", "a:bank"),
+            ],
+        )
+        .await;
+
+        // the first transfer is outside the dates but still counts in the balance
+        let response = server
+            .post("/query/account-register")
+            .json(&QueryTransactionsBody {
+                date_newest: now_millis() + 1,
+                date_oldest: after_first,
+                accounts_glob: String::from("a:bank"),
+            })
+            .await;
+        assert_eq!(
+            response.status_code(),
+            StatusCode::OK,
+            "{}",
+            response.text()
+        );
+        let json = response.json::<responses::ResponseRegister>();
+        let postings = json
+            .iter()
+            .map(|p| {
+                (
+                    p.counterpart_account.as_str(),
+                    p.amount.clone(),
+                    p.balance.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            postings,
+            vec![
+                ("r:salary", 1.into(), 2.into()),
+                ("x:food", (-1).into(), 1.into()),
+            ]
+        );
+
+        // balances carry across pages
+        let mut postings: Vec<(String, responses::Amount)> = Vec::new();
+        let mut cursor: Option<String> = None;
+        for page in 0..2 {
+            let cursor_field = cursor
+                .as_ref()
+                .map(|c| format!(r#","cursor":"{c}""#))
+                .unwrap_or_default();
+            let response = server
+                .post("/query/account-register")
+                .text(format!(
+                    r#"{{"date_newest":0,"date_oldest":0,"accounts_glob":"a:bank","limit":2{cursor_field}}}"#
+                ))
+                .content_type("application/json")
+                .await;
+            assert_eq!(
+                response.status_code(),
+                StatusCode::OK,
+                "{}",
+                response.text()
+            );
+            let json = response.json::<responses::ResponseRegister>();
+            assert_eq!(json.len(), 2 - page);
+            postings.extend(json.into_iter().map(|p| (p.counterpart_account, p.balance)));
+            cursor = response
+                .maybe_header("next-cursor")
+                .map(|v| String::from(v.to_str().unwrap()));
+            assert_eq!(cursor.is_some(), page == 0);
+        }
+        assert_eq!(
+            postings,
+            vec![
+                (String::from("r:salary"), 1.into()),
+                (String::from("r:salary"), 2.into()),
+                (String::from("x:food"), 1.into()),
+            ]
+        );
+
+        // pending and voided rows move nothing, so every balance adds up
+        let response = server
+            .put("/mutate/pending")
+            .json(&responses::AddPendingTransactions {
+                full_date2: now_millis(),
+                timeout: 0,
+                transactions: vec![
+                    responses::AddTransaction {
+                        amount: 3.into(),
+                        ..transaction(String::from("3f"), "a:bank", "r:salary")
+                    },
+                    transaction(String::from("3f"), "a:bank", "r:salary"),
+                ],
+            })
+            .await;
+        let pending_ids = response.json::<responses::ResponseAdd>();
+        let response = server
+            .put("/mutate/post")
+            .json(&responses::PostPendingTransactions {
+                transactions: vec![responses::PostPendingTransaction {
+                    pending_id: pending_ids[0].clone(),
+                    amount: Some(2.into()),
+                }],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server
+            .put("/mutate/void")
+            .json(&responses::VoidPendingTransactions {
+                pending_ids: vec![pending_ids[1].clone()],
+            })
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server
+            .post("/query/account-register")
+            .json(&QueryTransactionsBody {
+                date_newest: 0,
+                date_oldest: 0,
+                accounts_glob: String::from("a:bank"),
+            })
+            .await;
+        let json = response.json::<responses::ResponseRegister>();
+        assert_eq!(
+            json.iter()
+                .map(|p| (p.status, p.amount.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (responses::TransferStatus::Posted, 1.into()),
+                (responses::TransferStatus::Posted, 1.into()),
+                (responses::TransferStatus::Posted, (-1).into()),
+                (responses::TransferStatus::Pending, 0.into()),
+                (responses::TransferStatus::Pending, 0.into()),
+                (responses::TransferStatus::Posted, 2.into()),
+                (responses::TransferStatus::Voided, 0.into()),
+            ]
+        );
+        let mut balance = 0;
+        for posting in json {
+            balance += posting.amount.integer().unwrap();
+            assert_eq!(posting.balance, balance.into());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_e2e_error_envelope() {
+        let server = test_server().await;
+        let assert_error = |response: axum_test::TestResponse, code: http_err::ErrorCode| {
+            assert_eq!(response.status_code(), code.status(), "{}", response.text());
+            let json = response.json::<http_err::HttpErr>();
+            assert_eq!(json.code, code);
+            json
+        };
+
+        // rejected json bodies use the envelope too
+        let response = server
+            .put("/mutate/add")
+            .text("{")
+            .content_type("application/json")
+            .await;
+        assert_error(response, http_err::ErrorCode::Validation);
+        let response = server
+            .put("/mutate/add")
+            .text(r#"{"full_date2":"now","transactions":[]}"#)
+            .content_type("application/json")
+            .await;
+        assert_error(response, http_err::ErrorCode::Validation);
+        let response = server
+            .put("/mutate/add")
+            .json(&responses::AddTransactions {
+                full_date2: now_millis(),
+                transactions: vec![],
+            })
+            .await;
+        assert_error(response, http_err::ErrorCode::Validation);
+
+        let response = server
+            .put("/mutate/void")
+            .json(&responses::VoidPendingTransactions {
+                pending_ids: vec![String::from("ff")],
+            })
+            .await;
+        assert_error(response, http_err::ErrorCode::NotFound);
+
+        let response = server
+            .put("/mutate/add")
+            .json(&responses::AddTransactions {
+                full_date2: now_millis(),
+                transactions: vec![transaction(String::from("1"), "a:bank", "r:salary")],
+            })
+            .await;
+        let transfer_ids = response.json::<responses::ResponseAdd>();
+        let reverse = || {
+            server
+                .put("/mutate/reverse")
+                .json(&responses::ReverseTransactions {
+                    full_date2: now_millis(),
+                    transfer_ids: transfer_ids.clone(),
+                })
+        };
+        assert_eq!(reverse().await.status_code(), StatusCode::OK);
+        assert_error(reverse().await, http_err::ErrorCode::Conflict);
+
+        // a:bank is empty again, so it can not be credited
+        let response = server
+            .put("/mutate/add")
+            .json(&responses::AddTransactions {
+                full_date2: now_millis(),
+                transactions: vec![transaction(String::from("2"), "x:food", "a:bank")],
+            })
+            .await;
+        let json = assert_error(response, http_err::ErrorCode::LedgerRejected);
+        assert_eq!(json.errors.len(), 1);
+        assert_eq!(json.errors[0].credit_account, "a:bank");
+
+        let server = TestServer::new(app(AppState {
+            store: store::connect("sqlite://:memory:").await,
+            ledger: Arc::new(ledger::MemoryLedger::new()),
+            allow_add: false,
+            allow_migrate: false,
+        }))
+        .unwrap();
+        let response = server
+            .put("/mutate/add")
+            .json(&responses::AddTransactions {
+                full_date2: now_millis(),
+                transactions: vec![transaction(String::from("1"), "a:bank", "r:salary")],
+            })
+            .await;
+        assert_error(response, http_err::ErrorCode::DisabledByConfig);
+    }
 }
@@ -0,0 +1,701 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use crate::responses::{parse_decimal, AddTransaction, AddTransactions, Price};
+use crate::tb_utils;
+
+/// Error found on a line of the journal, line numbers start at 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Directives that do not change the transactions and are skipped together with their
+/// indented sub directives.
+const IGNORED_DIRECTIVES: [&str; 7] = ["account", "commodity", "D", "Y", "year", "payee", "tag"];
+
+/// Transaction of a plain text accounting journal, shared with the beancount parser
+pub struct JournalTransaction {
+    pub line: usize,
+    pub full_date2: i64,
+    pub code: i32,
+    pub transfer_id: Option<String>,
+    pub related_id: Option<String>,
+    pub postings: Vec<Posting>,
+    /// a line of the transaction could not be parsed and is already reported
+    pub failed: bool,
+}
+
+impl JournalTransaction {
+    pub fn new(line: usize, full_date2: i64) -> Self {
+        JournalTransaction {
+            line,
+            full_date2,
+            // tigerbeetle does not accept a zero code
+            code: 1,
+            transfer_id: None,
+            related_id: None,
+            postings: Vec::new(),
+            failed: false,
+        }
+    }
+}
+
+/// Transactions and market prices of a journal
+#[derive(Debug)]
+pub struct Journal {
+    pub transactions: Vec<AddTransactions>,
+    pub prices: Vec<Price>,
+}
+
+pub struct Posting {
+    pub account: String,
+    /// commodity unit and amount in the smallest unit of the commodity
+    pub amount: Option<(String, i128)>,
+}
+
+/// Parses an hledger journal into one `AddTransactions` per journal transaction and the
+/// market prices of its `P` directives.
+///
+/// Amounts are scaled by the decimal place of their commodity, commodities not found in
+/// `decimal_places` have no decimals. Every line that can not be imported is reported.
+///
+/// see: https://hledger.org/hledger.html#journal
+pub fn parse_journal(
+    journal: &str,
+    decimal_places: &HashMap<String, i32>,
+) -> Result<Journal, Vec<ParseError>> {
+    let mut errors: Vec<ParseError> = Vec::new();
+    let mut prices: Vec<Price> = Vec::new();
+    let mut add_transactions_arr: Vec<AddTransactions> = Vec::new();
+    let mut current: Option<JournalTransaction> = None;
+    let mut skip_indented = false;
+    let mut in_comment_block = false;
+
+    let mut finish = |current: &mut Option<JournalTransaction>, errors: &mut Vec<ParseError>| {
+        if let Some(t) = current.take().filter(|t| !t.failed) {
+            match to_add_transactions(t) {
+                Ok(v) => add_transactions_arr.push(v),
+                Err(err) => errors.push(err),
+            }
+        }
+    };
+
+    for (i, raw) in journal.lines().enumerate() {
+        let n = i + 1;
+        let line = raw.trim_end();
+        if in_comment_block {
+            if line == "end comment" {
+                in_comment_block = false;
+            }
+            continue;
+        }
+
+        if line.is_empty() {
+            finish(&mut current, &mut errors);
+            skip_indented = false;
+            continue;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            let content = line.trim_start();
+            if let Some(comment) = content.strip_prefix([';', '#']) {
+                if let Some(t) = current.as_mut().filter(|t| t.postings.is_empty()) {
+                    if let Err(message) = parse_tags(t, comment) {
+                        t.failed = true;
+                        errors.push(ParseError { line: n, message });
+                    }
+                }
+            } else if let Some(t) = current.as_mut() {
+                match parse_posting(content, decimal_places) {
+                    Ok(posting) => t.postings.push(posting),
+                    Err(message) => {
+                        t.failed = true;
+                        errors.push(ParseError { line: n, message });
+                    }
+                }
+            } else if !skip_indented {
+                errors.push(ParseError {
+                    line: n,
+                    message: String::from("posting outside of a transaction"),
+                });
+            }
+            continue;
+        }
+
+        finish(&mut current, &mut errors);
+        skip_indented = true;
+        if line.starts_with([';', '#', '*', '%', '|']) {
+            continue;
+        }
+        if line == "comment" {
+            in_comment_block = true;
+            continue;
+        }
+        if line.starts_with(|c: char| c.is_ascii_digit()) {
+            match parse_header(n, line) {
+                Ok(t) => current = Some(t),
+                Err(message) => errors.push(ParseError { line: n, message }),
+            }
+            continue;
+        }
+
+        let directive = line.split_whitespace().next().unwrap_or_default();
+        if directive == "P" {
+            match parse_price(line) {
+                Ok(price) => prices.push(price),
+                Err(message) => errors.push(ParseError { line: n, message }),
+            }
+        } else if !IGNORED_DIRECTIVES.contains(&directive) {
+            errors.push(ParseError {
+                line: n,
+                message: format!("unsupported directive {}", directive),
+            });
+        }
+    }
+    finish(&mut current, &mut errors);
+
+    if errors.is_empty() {
+        Ok(Journal {
+            transactions: add_transactions_arr,
+            prices,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parses `P DATE [TIME] COMMODITY UNITPRICE`, the time of day is not stored.
+fn parse_price(line: &str) -> Result<Price, String> {
+    let (line, _) = split_comment(line);
+    let mut rest = line.strip_prefix('P').unwrap_or(line).trim_start();
+    let (date, after_date) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let date = parse_date(date)?;
+    rest = after_date.trim_start();
+    if let Some((time, after_time)) = rest.split_once(char::is_whitespace) {
+        if time.contains(':') {
+            rest = after_time.trim_start();
+        }
+    }
+
+    let (commodity_unit, unit_price) = parse_unit(rest)?;
+    if commodity_unit.is_empty() {
+        return Err(format!("price {} is missing a commodity", line));
+    }
+    let (quote_unit, negative, number) = split_amount(unit_price.trim())?;
+    let price = number.replace(',', "");
+    // validates the number, the price keeps all of its decimals
+    let scale = price
+        .split_once('.')
+        .map(|(_, frac)| frac.len())
+        .unwrap_or(0);
+    if negative || parse_decimal(&price, scale as i32)? <= 0 {
+        return Err(format!("price {} must be positive", unit_price.trim()));
+    }
+    Ok(Price {
+        commodity_unit,
+        quote_unit,
+        date,
+        price,
+    })
+}
+
+/// Parses `DATE[=DATE2] [STATUS] [(CODE)] DESCRIPTION [; COMMENT]`
+fn parse_header(line_number: usize, line: &str) -> Result<JournalTransaction, String> {
+    let (line, comment) = split_comment(line);
+    let (date, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    // the secondary date is not stored
+    let date = date.split('=').next().unwrap_or_default();
+    let full_date2 = parse_date(date)?;
+
+    let rest = rest.trim_start();
+    // pending entries are not booked, exported pending transfers are comments
+    if rest.starts_with('!') {
+        return Err(String::from(
+            "pending transactions marked ! can not be imported",
+        ));
+    }
+    let rest = rest.strip_prefix('*').unwrap_or(rest).trim_start();
+
+    // the description is not stored, exported journals repeat the transfer id in a tag
+    let mut t = JournalTransaction::new(line_number, full_date2);
+    if let Some(code_rest) = rest.strip_prefix('(') {
+        let (code_str, _) = code_rest.split_once(')').ok_or(String::from(
+            "transaction code is missing a closing bracket",
+        ))?;
+        t.code = code_str
+            .trim()
+            .parse::<i32>()
+            .map_err(|_| format!("transaction code {} must be a number", code_str))?;
+    }
+
+    if let Some(comment) = comment {
+        parse_tags(&mut t, comment)?;
+    }
+    Ok(t)
+}
+
+/// Reads `related_id`, `code` and `transfer_id` from `name: value` tags, the `name value`
+/// form written by the hledger export is read as well.
+fn parse_tags(t: &mut JournalTransaction, comment: &str) -> Result<(), String> {
+    for item in comment.split(',') {
+        let item = item.trim();
+        let Some((name, value)) = item
+            .split_once(':')
+            .or_else(|| item.rsplit_once(char::is_whitespace))
+        else {
+            continue;
+        };
+        parse_tag(
+            t,
+            &name.trim().to_lowercase().replace(' ', "_"),
+            value.trim(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Sets the transaction field of a `related_id`, `code` or `transfer_id` tag, other tags are
+/// ignored.
+pub fn parse_tag(t: &mut JournalTransaction, name: &str, value: &str) -> Result<(), String> {
+    match name {
+        "related_id" => {
+            tb_utils::u128::try_from_hex_string(value)
+                .map_err(|_| format!("related id {} must be hexadecimal", value))?;
+            t.related_id = Some(String::from(value));
+        }
+        "transfer_id" => {
+            tb_utils::u128::try_from_hex_string(value)
+                .map_err(|_| format!("transfer id {} must be hexadecimal", value))?;
+            t.transfer_id = Some(String::from(value));
+        }
+        "code" => {
+            t.code = value
+                .parse::<i32>()
+                .map_err(|_| format!("transaction code {} must be a number", value))?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+pub fn parse_date(date: &str) -> Result<i64, String> {
+    let parts = date.split(['-', '/', '.']).collect::<Vec<&str>>();
+    let invalid = || format!("invalid date {}, expected YYYY-MM-DD", date);
+    let [y, m, d] = parts[..] else {
+        return Err(invalid());
+    };
+    let date = NaiveDate::from_ymd_opt(
+        y.parse().map_err(|_| invalid())?,
+        m.parse().map_err(|_| invalid())?,
+        d.parse().map_err(|_| invalid())?,
+    )
+    .ok_or_else(invalid)?;
+    Ok(date
+        .and_hms_opt(0, 0, 0)
+        .ok_or_else(invalid)?
+        .and_utc()
+        .timestamp_millis())
+}
+
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    match line.split_once(';') {
+        Some((line, comment)) => (line, Some(comment)),
+        None => (line, None),
+    }
+}
+
+/// Parses `ACCOUNT  [AMOUNT] [= ASSERTION] [; COMMENT]`, the account and amount are separated by
+/// at least two spaces or a tab.
+fn parse_posting(content: &str, decimal_places: &HashMap<String, i32>) -> Result<Posting, String> {
+    let (content, _) = split_comment(content);
+    if content.starts_with(['(', '[']) {
+        return Err(String::from("virtual postings are not supported"));
+    }
+    let (account, amount) = match (content.find("  "), content.find('\t')) {
+        (Some(a), Some(b)) => content.split_at(a.min(b)),
+        (Some(i), None) | (None, Some(i)) => content.split_at(i),
+        (None, None) => (content, ""),
+    };
+    let account = account_name(account.trim())?;
+
+    // balance assertions are not checked
+    let amount = amount.split('=').next().unwrap_or_default().trim();
+    if amount.contains('@') {
+        return Err(String::from("costs are not supported"));
+    }
+    let amount = match amount {
+        "" => None,
+        amount => Some(parse_amount(amount, decimal_places)?),
+    };
+
+    Ok(Posting { account, amount })
+}
+
+/// Maps hledger account names like `Assets:Bank Account` to `a:bankaccount`.
+pub fn account_name(name: &str) -> Result<String, String> {
+    let mut segments = name.split(':');
+    let top = segments.next().unwrap_or_default().trim().to_lowercase();
+    let prefix = match top.as_str() {
+        "a" | "asset" | "assets" => "a",
+        "l" | "liability" | "liabilities" => "l",
+        "e" | "equity" => "e",
+        "r" | "revenue" | "revenues" | "income" => "r",
+        "x" | "expense" | "expenses" => "x",
+        _ => {
+            return Err(format!(
+                "account {} must start with assets, liabilities, equity, revenues or expenses",
+                name
+            ))
+        }
+    };
+
+    let segments = segments
+        .map(|s| {
+            s.to_lowercase()
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>();
+    if segments.is_empty() {
+        return Err(format!("account {} must have a sub account", name));
+    }
+    if segments.iter().any(|s| s.is_empty()) {
+        return Err(format!("account {} has an empty sub account", name));
+    }
+    Ok(format!("{}:{}", prefix, segments.join(":")))
+}
+
+/// Parses amounts like `10 EUR`, `-$1,000.50` or `"AAPL 2" 3` into the commodity unit and the
+/// amount in the smallest unit of the commodity.
+pub fn parse_amount(
+    amount: &str,
+    decimal_places: &HashMap<String, i32>,
+) -> Result<(String, i128), String> {
+    let (unit, negative, number) = split_amount(amount)?;
+    let decimal_place = decimal_places.get(&unit).copied().unwrap_or(0);
+    let value = parse_decimal(&number.replace(',', ""), decimal_place)
+        .map_err(|err| format!("{} of {}", err, unit))?;
+
+    Ok((unit, if negative { -value } else { value }))
+}
+
+/// Splits an amount into its commodity, whether it is negative and its number.
+fn split_amount(amount: &str) -> Result<(String, bool, &str), String> {
+    let (mut negative, rest) = match amount.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, amount.strip_prefix('+').unwrap_or(amount)),
+    };
+    let rest = rest.trim_start();
+
+    let (unit, number) = if rest.starts_with(|c: char| c.is_ascii_digit()) {
+        let end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(rest.len());
+        let (number, unit) = rest.split_at(end);
+        (parse_unit(unit.trim())?.0, number)
+    } else {
+        let (unit, number) = parse_unit(rest)?;
+        let number = number.trim_start();
+        let number = match number.strip_prefix('-') {
+            Some(number) => {
+                negative = !negative;
+                number
+            }
+            None => number,
+        };
+        (unit, number)
+    };
+    if unit.is_empty() {
+        return Err(format!("amount {} is missing a commodity", amount));
+    }
+
+    Ok((unit, negative, number))
+}
+
+/// Splits a quoted or unquoted commodity from the start of `s`.
+fn parse_unit(s: &str) -> Result<(String, &str), String> {
+    if let Some(quoted) = s.strip_prefix('"') {
+        let (unit, rest) = quoted
+            .split_once('"')
+            .ok_or(format!("commodity {} is missing a closing quote", s))?;
+        return Ok((String::from(unit), rest));
+    }
+    let end = s
+        .find(|c: char| c.is_ascii_digit() || c.is_whitespace() || c == '-' || c == '+')
+        .unwrap_or(s.len());
+    let (unit, rest) = s.split_at(end);
+    Ok((String::from(unit), rest))
+}
+
+/// Balances the postings and pairs debits with credits of the same commodity.
+pub fn to_add_transactions(t: JournalTransaction) -> Result<AddTransactions, ParseError> {
+    let err = |message: String| ParseError {
+        line: t.line,
+        message,
+    };
+
+    let mut legs: Vec<(String, String, i128)> = Vec::new();
+    let mut elided: Option<String> = None;
+    for posting in t.postings.iter() {
+        match &posting.amount {
+            Some((unit, amount)) => legs.push((posting.account.clone(), unit.clone(), *amount)),
+            None if elided.is_none() => elided = Some(posting.account.clone()),
+            None => {
+                return Err(err(String::from(
+                    "only one posting may leave out its amount",
+                )))
+            }
+        }
+    }
+    if legs.is_empty() {
+        return Err(err(String::from("transaction has no amounts")));
+    }
+
+    let mut sums: Vec<(String, i128)> = Vec::new();
+    for (_, unit, amount) in legs.iter() {
+        match sums.iter_mut().find(|(u, _)| u == unit) {
+            Some((_, sum)) => {
+                *sum = sum
+                    .checked_add(*amount)
+                    .ok_or_else(|| err(format!("sum of {} overflows", unit)))?
+            }
+            None => sums.push((unit.clone(), *amount)),
+        }
+    }
+    for (unit, sum) in sums.iter().filter(|(_, sum)| *sum != 0) {
+        match &elided {
+            Some(account) => legs.push((
+                account.clone(),
+                unit.clone(),
+                sum.checked_neg()
+                    .ok_or_else(|| err(format!("sum of {} overflows", unit)))?,
+            )),
+            None => {
+                return Err(err(format!(
+                    "transaction does not balance, {} is off by {}",
+                    unit, sum
+                )))
+            }
+        }
+    }
+
+    // without a related_id tag the related id is set when the transactions are added
+    let related_id = t.related_id.clone().unwrap_or_default();
+    let mut transactions: Vec<AddTransaction> = Vec::new();
+    for (unit, _) in sums.iter() {
+        let mut debits = legs
+            .iter()
+            .filter(|(_, u, amount)| u == unit && *amount > 0)
+            .map(|(account, _, amount)| (account, *amount))
+            .collect::<Vec<_>>();
+        let mut credits = legs
+            .iter()
+            .filter(|(_, u, amount)| u == unit && *amount < 0)
+            .map(|(account, _, amount)| (account, -amount))
+            .collect::<Vec<_>>();
+
+        let (mut d, mut c) = (0, 0);
+        while d < debits.len() && c < credits.len() {
+            let amount = debits[d].1.min(credits[c].1);
+            transactions.push(AddTransaction {
+                transfer_id: None,
+                commodity_unit: unit.clone(),
+                code: t.code,
+                related_id: related_id.clone(),
+                debit_account: debits[d].0.clone(),
+                credit_account: credits[c].0.clone(),
+                amount: amount.into(),
+            });
+            debits[d].1 -= amount;
+            credits[c].1 -= amount;
+            if debits[d].1 == 0 {
+                d += 1;
+            }
+            if credits[c].1 == 0 {
+                c += 1;
+            }
+        }
+    }
+
+    if let Some(transfer_id) = t.transfer_id {
+        match transactions.as_mut_slice() {
+            [transaction] => transaction.transfer_id = Some(transfer_id),
+            _ => {
+                return Err(err(String::from(
+                    "a transfer id can only be set on a transaction with two postings",
+                )))
+            }
+        }
+    }
+
+    Ok(AddTransactions {
+        full_date2: t.full_date2,
+        transactions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{account_name, parse_journal, ParseError};
+    use crate::responses::Amount;
+    use std::collections::HashMap;
+
+    fn decimal_places() -> HashMap<String, i32> {
+        HashMap::from([(String::from("EUR"), 2), (String::from("$"), 2)])
+    }
+
+    #[test]
+    fn account_names() {
+        assert_eq!(
+            account_name("Assets:Bank Account"),
+            Ok(String::from("a:bankaccount"))
+        );
+        assert_eq!(account_name("income:salary"), Ok(String::from("r:salary")));
+        assert_eq!(
+            account_name("x:food:lunch"),
+            Ok(String::from("x:food:lunch"))
+        );
+        assert!(account_name("assets").is_err());
+        assert!(account_name("budget:food").is_err());
+    }
+
+    #[test]
+    fn transactions() {
+        let journal = "\
+; opening balances
+account assets:bank
+
+2024-01-31 * (12) Groceries ; related_id: abc
+    expenses:food          10.50 EUR
+    expenses:drinks         EUR 2
+    assets:bank
+
+2024/02/01 Paycheck
+    assets:bank          $1,000.00  = $1,000.00
+    income:salary       -$1,000.00
+";
+        let add_transactions_arr = parse_journal(journal, &decimal_places())
+            .unwrap()
+            .transactions;
+        assert_eq!(add_transactions_arr.len(), 2);
+
+        let groceries = &add_transactions_arr[0];
+        assert_eq!(groceries.full_date2, 1706659200000);
+        assert_eq!(groceries.transactions.len(), 2);
+        let t = &groceries.transactions[0];
+        assert_eq!(t.debit_account, "x:food");
+        assert_eq!(t.credit_account, "a:bank");
+        assert_eq!(t.amount, Amount::Integer(1050));
+        assert_eq!(t.code, 12);
+        assert_eq!(t.related_id, "abc");
+        assert_eq!(groceries.transactions[1].debit_account, "x:drinks");
+        assert_eq!(groceries.transactions[1].amount, Amount::Integer(200));
+
+        let paycheck = &add_transactions_arr[1].transactions;
+        assert_eq!(paycheck.len(), 1);
+        assert_eq!(paycheck[0].commodity_unit, "$");
+        assert_eq!(paycheck[0].debit_account, "a:bank");
+        assert_eq!(paycheck[0].credit_account, "r:salary");
+        assert_eq!(paycheck[0].amount, Amount::Integer(100000));
+    }
+
+    #[test]
+    fn exported_transaction() {
+        let journal = "\
+2025-04-03 * 195fb004cbf19e9d857488e47bb0af1 ; transfer_id: 195fb004cbf19e9d857488e47bb0af1, related id 1f, code 9999
+      a:test:cash          1 TEST
+      l:test:loan         -1 TEST
+
+2025-04-03 * deadbeefdeadbeefdeadbeef ; related id 1f
+      a:test:cash          1 TEST
+      l:test:loan         -1 TEST
+";
+        let add_transactions_arr = parse_journal(journal, &HashMap::new())
+            .unwrap()
+            .transactions;
+        let t = &add_transactions_arr[0].transactions[0];
+        assert_eq!(
+            t.transfer_id,
+            Some(String::from("195fb004cbf19e9d857488e47bb0af1"))
+        );
+        assert_eq!(t.related_id, "1f");
+        assert_eq!(t.code, 9999);
+        assert_eq!(t.amount, Amount::Integer(1));
+        // a hexadecimal description is not a transfer id
+        assert_eq!(add_transactions_arr[1].transactions[0].transfer_id, None);
+    }
+
+    #[test]
+    fn prices() {
+        let journal = "\
+P 2024-01-01 USD 0.92 EUR
+P 2024-01-02 12:00:00 \"AAPL 2\" $1,185.50 ; closing price
+P 2024-01-03 EUR -1 USD
+";
+        let errors = parse_journal(journal, &decimal_places()).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![ParseError {
+                line: 3,
+                message: String::from("price -1 USD must be positive"),
+            }]
+        );
+
+        let prices = parse_journal(&journal.replace("-1 USD", "1.087 USD"), &decimal_places())
+            .unwrap()
+            .prices;
+        assert_eq!(prices.len(), 3);
+        assert_eq!(prices[0].commodity_unit, "USD");
+        assert_eq!(prices[0].quote_unit, "EUR");
+        assert_eq!(prices[0].date, 1704067200000);
+        assert_eq!(prices[0].price, "0.92");
+        assert_eq!(prices[1].commodity_unit, "AAPL 2");
+        assert_eq!(prices[1].quote_unit, "$");
+        assert_eq!(prices[1].price, "1185.50");
+        // prices keep decimals beyond the decimal place of the commodity
+        assert_eq!(prices[2].price, "1.087");
+    }
+
+    #[test]
+    fn line_numbered_errors() {
+        let journal = "\
+2024-13-01 bad date
+    assets:bank  1 EUR
+
+2024-01-01 unbalanced
+    assets:bank  1 EUR
+    income:salary  -2 EUR
+
+2024-01-02 precision
+    assets:bank  1.001 EUR
+    budget:food
+
+2024-01-03 ! pending
+    assets:bank  1 EUR
+    income:salary
+";
+        let errors = parse_journal(journal, &decimal_places()).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.line).collect::<Vec<usize>>(),
+            vec![1, 4, 9, 10, 12]
+        );
+        assert_eq!(
+            errors[1],
+            ParseError {
+                line: 4,
+                message: String::from("transaction does not balance, EUR is off by -100"),
+            }
+        );
+    }
+}
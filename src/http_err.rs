@@ -1,24 +1,172 @@
-use axum::http::StatusCode;
+use axum::{
+    extract::rejection::JsonRejection,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use axum_macros::FromRequest;
+use serde::{Deserialize, Serialize};
+use tigerbeetle_unofficial as tb;
+use utoipa::ToSchema;
+
+use crate::{responses, tb_utils};
 
 pub type HttpResult<T> = Result<T, HttpErr>;
-pub type HttpErr = (StatusCode, String);
+
+/// Machine readable reason of an error response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// request body or parameters are invalid
+    Validation,
+    /// a referenced transaction or account does not exist
+    NotFound,
+    /// the request conflicts with existing state
+    Conflict,
+    /// tigerbeetle rejected the transfers
+    LedgerRejected,
+    /// the action is disabled by the server configuration
+    DisabledByConfig,
+    /// the database or tigerbeetle could not be reached
+    StorageUnavailable,
+    /// unexpected server error
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::Validation => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::LedgerRejected => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::DisabledByConfig => StatusCode::FORBIDDEN,
+            ErrorCode::StorageUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Error envelope returned by every route
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpErr {
+    pub code: ErrorCode,
+    pub message: String,
+    /// transfers rejected by tigerbeetle, only set on ledger errors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<responses::TransferError>,
+}
+
+impl HttpErr {
+    pub fn new<E>(code: ErrorCode, err: E) -> Self
+    where
+        E: ToString,
+    {
+        Self {
+            code,
+            message: err.to_string(),
+            errors: vec![],
+        }
+    }
+}
+
+impl IntoResponse for HttpErr {
+    fn into_response(self) -> Response {
+        (self.code.status(), axum::Json(self)).into_response()
+    }
+}
+
+impl From<JsonRejection> for HttpErr {
+    fn from(rejection: JsonRejection) -> Self {
+        bad_error(rejection.body_text())
+    }
+}
+
+/// `axum::Json` that returns its rejections in the error envelope
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(HttpErr))]
+pub struct Json<T>(pub T);
+
+impl<T> std::ops::Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> IntoResponse for Json<T>
+where
+    axum::Json<T>: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
 pub fn internal_error<E>(err: E) -> HttpErr
 where
     E: ToString,
 {
-    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    HttpErr::new(ErrorCode::Internal, err)
 }
 
 pub fn bad_error<E>(err: E) -> HttpErr
 where
     E: ToString + std::fmt::Debug + std::fmt::Display,
 {
-    (StatusCode::BAD_REQUEST, err.to_string())
+    HttpErr::new(ErrorCode::Validation, err)
 }
 
-pub fn teapot_error<E>(err: E) -> HttpErr
+pub fn not_found_error<E>(err: E) -> HttpErr
 where
-    E: ToString + std::fmt::Debug + std::fmt::Display,
+    E: ToString,
+{
+    HttpErr::new(ErrorCode::NotFound, err)
+}
+
+pub fn conflict_error<E>(err: E) -> HttpErr
+where
+    E: ToString,
+{
+    HttpErr::new(ErrorCode::Conflict, err)
+}
+
+pub fn disabled_error<E>(err: E) -> HttpErr
+where
+    E: ToString,
+{
+    HttpErr::new(ErrorCode::DisabledByConfig, err)
+}
+
+pub fn storage_error<E>(err: E) -> HttpErr
+where
+    E: ToString,
 {
-    (StatusCode::IM_A_TEAPOT, err.to_string())
+    HttpErr::new(ErrorCode::StorageUnavailable, err)
+}
+
+/// Transfers rejected by tigerbeetle are a client error, transfers that could not be sent are not.
+pub fn transfers_error(
+    err: &tb::error::CreateTransfersError,
+    errors: Vec<responses::TransferError>,
+) -> HttpErr {
+    let code = match err {
+        tb::error::CreateTransfersError::Api(err) => {
+            if tb_utils::is_create_transfers_conflict(err) {
+                ErrorCode::Conflict
+            } else {
+                ErrorCode::LedgerRejected
+            }
+        }
+        _ => ErrorCode::StorageUnavailable,
+    };
+    HttpErr {
+        code,
+        message: format!(
+            "error on adding transfers to tigerbeetle: {}",
+            tb_utils::create_transfers_error_name(err)
+        ),
+        errors,
+    }
 }
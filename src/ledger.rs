@@ -0,0 +1,113 @@
+use std::{future::Future, pin::Pin};
+
+use tigerbeetle_unofficial as tb;
+
+mod memory;
+
+pub use memory::MemoryLedger;
+
+pub type LedgerFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Operations used on the ledger, implemented by the tigerbeetle client and an in-memory ledger.
+///
+/// Results and errors are the same types tigerbeetle returns so that handlers do not care which
+/// backend is in use.
+pub trait Ledger: Send + Sync {
+    fn create_accounts(
+        &self,
+        accounts: Vec<tb::Account>,
+    ) -> LedgerFuture<'_, Result<(), tb::error::CreateAccountsError>>;
+
+    fn create_transfers(
+        &self,
+        transfers: Vec<tb::Transfer>,
+    ) -> LedgerFuture<'_, Result<(), tb::error::CreateTransfersError>>;
+
+    fn lookup_accounts(
+        &self,
+        ids: Vec<u128>,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Account>, tb::error::SendError>>;
+
+    fn lookup_transfers(
+        &self,
+        ids: Vec<u128>,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>>;
+
+    fn get_account_transfers(
+        &self,
+        filter: tb::account::Filter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>>;
+
+    fn get_account_balances(
+        &self,
+        filter: tb::account::Filter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::account::Balance>, tb::error::SendError>>;
+
+    fn query_accounts(
+        &self,
+        filter: tb::QueryFilter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Account>, tb::error::SendError>>;
+
+    fn query_transfers(
+        &self,
+        filter: tb::QueryFilter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>>;
+}
+
+impl Ledger for tb::Client {
+    fn create_accounts(
+        &self,
+        accounts: Vec<tb::Account>,
+    ) -> LedgerFuture<'_, Result<(), tb::error::CreateAccountsError>> {
+        Box::pin(tb::Client::create_accounts(self, accounts))
+    }
+
+    fn create_transfers(
+        &self,
+        transfers: Vec<tb::Transfer>,
+    ) -> LedgerFuture<'_, Result<(), tb::error::CreateTransfersError>> {
+        Box::pin(tb::Client::create_transfers(self, transfers))
+    }
+
+    fn lookup_accounts(
+        &self,
+        ids: Vec<u128>,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Account>, tb::error::SendError>> {
+        Box::pin(tb::Client::lookup_accounts(self, ids))
+    }
+
+    fn lookup_transfers(
+        &self,
+        ids: Vec<u128>,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>> {
+        Box::pin(tb::Client::lookup_transfers(self, ids))
+    }
+
+    fn get_account_transfers(
+        &self,
+        filter: tb::account::Filter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>> {
+        Box::pin(tb::Client::get_account_transfers(self, Box::new(filter)))
+    }
+
+    fn get_account_balances(
+        &self,
+        filter: tb::account::Filter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::account::Balance>, tb::error::SendError>> {
+        Box::pin(tb::Client::get_account_balances(self, Box::new(filter)))
+    }
+
+    fn query_accounts(
+        &self,
+        filter: tb::QueryFilter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Account>, tb::error::SendError>> {
+        Box::pin(tb::Client::query_accounts(self, Box::new(filter)))
+    }
+
+    fn query_transfers(
+        &self,
+        filter: tb::QueryFilter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>> {
+        Box::pin(tb::Client::query_transfers(self, Box::new(filter)))
+    }
+}
@@ -0,0 +1,1060 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tigerbeetle_unofficial as tb;
+
+use tb::error::{CreateAccountErrorKind as AccountKind, CreateTransferErrorKind as TransferKind};
+
+use super::{Ledger, LedgerFuture};
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Ledger kept in memory that follows the tigerbeetle rules used by this crate:
+/// linked chains, pending/post/void with timeouts, balancing and closing transfers,
+/// the must not exceed account flags and account history.
+///
+/// Nothing is persisted, it is meant for tests and the demo mode. Imported events are not supported.
+#[derive(Default)]
+pub struct MemoryLedger {
+    state: Mutex<State>,
+}
+
+impl MemoryLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut State) -> T) -> T {
+        let mut state = self.state.lock().expect("memory ledger lock poisoned");
+        f(&mut state)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    Posted,
+    Voided,
+    Expired,
+}
+
+#[derive(Clone, Copy)]
+struct HistoryEntry {
+    balance: tb::account::Balance,
+    is_debit: bool,
+}
+
+/// Changes made by a linked chain, reverted when one of its events fails.
+enum Undo {
+    Account(u128, Option<tb::Account>),
+    Transfer,
+    History(u128),
+    Resolved(u128),
+}
+
+#[derive(Default)]
+struct State {
+    accounts: HashMap<u128, tb::Account>,
+    /// ordered by timestamp
+    transfers: Vec<tb::Transfer>,
+    transfer_index: HashMap<u128, usize>,
+    history: HashMap<u128, Vec<HistoryEntry>>,
+    /// pending transfers that are no longer pending
+    resolved: HashMap<u128, Resolution>,
+    /// pending transfers with a timeout, checked on every batch
+    timeouts: Vec<u128>,
+    timestamp: u64,
+}
+
+impl State {
+    fn next_timestamp(&mut self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        self.timestamp = now.max(self.timestamp + 1);
+        self.timestamp
+    }
+
+    fn transfer(&self, id: u128) -> Option<&tb::Transfer> {
+        self.transfer_index.get(&id).map(|i| &self.transfers[*i])
+    }
+
+    fn put_account(&mut self, account: tb::Account, undo: &mut Vec<Undo>) {
+        let previous = self.accounts.insert(account.id(), account);
+        undo.push(Undo::Account(account.id(), previous));
+    }
+
+    fn push_transfer(&mut self, transfer: tb::Transfer, undo: &mut Vec<Undo>) {
+        self.transfer_index
+            .insert(transfer.id(), self.transfers.len());
+        self.transfers.push(transfer);
+        undo.push(Undo::Transfer);
+
+        for (account_id, is_debit) in [
+            (transfer.debit_account_id(), true),
+            (transfer.credit_account_id(), false),
+        ] {
+            let Some(account) = self.accounts.get(&account_id) else {
+                continue;
+            };
+            if !account.flags().contains(tb::account::Flags::HISTORY) {
+                continue;
+            }
+            let balance = tb::account::Balance::from_raw(tb::account::RawBalance {
+                debits_pending: account.debits_pending(),
+                debits_posted: account.debits_posted(),
+                credits_pending: account.credits_pending(),
+                credits_posted: account.credits_posted(),
+                timestamp: transfer.as_raw().timestamp,
+                reserved: [0; 56],
+            });
+            self.history
+                .entry(account_id)
+                .or_default()
+                .push(HistoryEntry { balance, is_debit });
+            undo.push(Undo::History(account_id));
+        }
+    }
+
+    fn resolve(&mut self, pending_id: u128, resolution: Resolution, undo: &mut Vec<Undo>) {
+        self.resolved.insert(pending_id, resolution);
+        undo.push(Undo::Resolved(pending_id));
+    }
+
+    fn rollback(&mut self, undo: Vec<Undo>) {
+        for change in undo.into_iter().rev() {
+            match change {
+                Undo::Account(id, Some(account)) => {
+                    self.accounts.insert(id, account);
+                }
+                Undo::Account(id, None) => {
+                    self.accounts.remove(&id);
+                }
+                Undo::Transfer => {
+                    if let Some(transfer) = self.transfers.pop() {
+                        self.transfer_index.remove(&transfer.id());
+                    }
+                }
+                Undo::History(id) => {
+                    if let Some(history) = self.history.get_mut(&id) {
+                        history.pop();
+                    }
+                }
+                Undo::Resolved(id) => {
+                    self.resolved.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// Applies events chain by chain, a failing event fails every event linked to it.
+    fn apply_chains<T, K: Copy>(
+        &mut self,
+        events: &[T],
+        is_linked: impl Fn(&T) -> bool,
+        apply: impl Fn(&mut State, &T, &mut Vec<Undo>) -> Result<(), K>,
+        linked_event_failed: K,
+        linked_event_chain_open: K,
+    ) -> Vec<(u32, K)> {
+        let mut results = Vec::new();
+        let mut start = 0;
+        while start < events.len() {
+            let mut end = start;
+            while end < events.len() - 1 && is_linked(&events[end]) {
+                end += 1;
+            }
+            let chain_open = is_linked(&events[end]);
+
+            let mut undo = Vec::new();
+            let mut failed = None;
+            for (i, event) in events.iter().enumerate().take(end + 1).skip(start) {
+                if chain_open && i == end {
+                    failed = Some((i, linked_event_chain_open));
+                    break;
+                }
+                if let Err(kind) = apply(self, event, &mut undo) {
+                    failed = Some((i, kind));
+                    break;
+                }
+            }
+            if let Some((failed_index, kind)) = failed {
+                self.rollback(undo);
+                for i in start..=end {
+                    let kind = if i == failed_index {
+                        kind
+                    } else {
+                        linked_event_failed
+                    };
+                    results.push((i as u32, kind));
+                }
+            }
+            start = end + 1;
+        }
+        results
+    }
+
+    fn create_account(&mut self, a: &tb::Account, undo: &mut Vec<Undo>) -> Result<(), AccountKind> {
+        use tb::account::Flags;
+
+        let raw = a.as_raw();
+        if raw.timestamp != 0 {
+            return Err(AccountKind::TimestampMustBeZero);
+        }
+        if raw.reserved != 0 {
+            return Err(AccountKind::ReservedField);
+        }
+        if Flags::from_bits(raw.flags).is_none() {
+            return Err(AccountKind::ReservedFlag);
+        }
+        if a.flags().contains(Flags::IMPORTED) {
+            return Err(AccountKind::ImportedEventNotExpected);
+        }
+        if a.id() == 0 {
+            return Err(AccountKind::IdMustNotBeZero);
+        }
+        if a.id() == u128::MAX {
+            return Err(AccountKind::IdMustNotBeIntMax);
+        }
+        if a.flags()
+            .contains(Flags::DEBITS_MUST_NOT_EXCEED_CREDITS | Flags::CREDITS_MUST_NOT_EXCEED_DEBITS)
+        {
+            return Err(AccountKind::FlagsAreMutuallyExclusive);
+        }
+        if a.debits_pending() != 0 {
+            return Err(AccountKind::DebitsPendingMustBeZero);
+        }
+        if a.debits_posted() != 0 {
+            return Err(AccountKind::DebitsPostedMustBeZero);
+        }
+        if a.credits_pending() != 0 {
+            return Err(AccountKind::CreditsPendingMustBeZero);
+        }
+        if a.credits_posted() != 0 {
+            return Err(AccountKind::CreditsPostedMustBeZero);
+        }
+        if a.ledger() == 0 {
+            return Err(AccountKind::LedgerMustNotBeZero);
+        }
+        if a.code() == 0 {
+            return Err(AccountKind::CodeMustNotBeZero);
+        }
+
+        if let Some(e) = self.accounts.get(&a.id()) {
+            return Err(if e.flags() != a.flags() {
+                AccountKind::ExistsWithDifferentFlags
+            } else if e.user_data_128() != a.user_data_128() {
+                AccountKind::ExistsWithDifferentUserData128
+            } else if e.user_data_64() != a.user_data_64() {
+                AccountKind::ExistsWithDifferentUserData64
+            } else if e.user_data_32() != a.user_data_32() {
+                AccountKind::ExistsWithDifferentUserData32
+            } else if e.ledger() != a.ledger() {
+                AccountKind::ExistsWithDifferentLedger
+            } else if e.code() != a.code() {
+                AccountKind::ExistsWithDifferentCode
+            } else {
+                AccountKind::Exists
+            });
+        }
+
+        let mut account = *a;
+        account.as_raw_mut().timestamp = self.next_timestamp();
+        self.put_account(account, undo);
+        Ok(())
+    }
+
+    fn create_transfer(
+        &mut self,
+        t: &tb::Transfer,
+        undo: &mut Vec<Undo>,
+    ) -> Result<(), TransferKind> {
+        use tb::transfer::Flags;
+
+        let raw = t.as_raw();
+        if raw.timestamp != 0 {
+            return Err(TransferKind::TimestampMustBeZero);
+        }
+        if Flags::from_bits(raw.flags).is_none() {
+            return Err(TransferKind::ReservedFlag);
+        }
+        let flags = t.flags();
+        if flags.contains(Flags::IMPORTED) {
+            return Err(TransferKind::ImportedEventNotExpected);
+        }
+        if t.id() == 0 {
+            return Err(TransferKind::IdMustNotBeZero);
+        }
+        if t.id() == u128::MAX {
+            return Err(TransferKind::IdMustNotBeIntMax);
+        }
+        if let Some(e) = self.transfer(t.id()) {
+            return Err(transfer_exists_kind(t, e));
+        }
+
+        let post_or_void = Flags::POST_PENDING_TRANSFER | Flags::VOID_PENDING_TRANSFER;
+        let balancing = Flags::BALANCING_DEBIT | Flags::BALANCING_CREDIT;
+        let closing = Flags::CLOSING_DEBIT | Flags::CLOSING_CREDIT;
+        if (flags.contains(Flags::PENDING) && flags.intersects(post_or_void))
+            || flags.contains(post_or_void)
+            || (flags.intersects(balancing) && flags.intersects(post_or_void))
+            || (flags.intersects(closing) && flags.intersects(post_or_void))
+        {
+            return Err(TransferKind::FlagsAreMutuallyExclusive);
+        }
+        if flags.intersects(post_or_void) {
+            return self.post_or_void_pending_transfer(t, undo);
+        }
+
+        if t.debit_account_id() == 0 {
+            return Err(TransferKind::DebitAccountIdMustNotBeZero);
+        }
+        if t.debit_account_id() == u128::MAX {
+            return Err(TransferKind::DebitAccountIdMustNotBeIntMax);
+        }
+        if t.credit_account_id() == 0 {
+            return Err(TransferKind::CreditAccountIdMustNotBeZero);
+        }
+        if t.credit_account_id() == u128::MAX {
+            return Err(TransferKind::CreditAccountIdMustNotBeIntMax);
+        }
+        if t.debit_account_id() == t.credit_account_id() {
+            return Err(TransferKind::AccountsMustBeDifferent);
+        }
+        if t.pending_id() != 0 {
+            return Err(TransferKind::PendingIdMustBeZero);
+        }
+        if !flags.contains(Flags::PENDING) {
+            if t.timeout() != 0 {
+                return Err(TransferKind::TimeoutReservedForPendingTransfer);
+            }
+            if flags.intersects(closing) {
+                return Err(TransferKind::ClosingTransferMustBePending);
+            }
+        }
+        if t.ledger() == 0 {
+            return Err(TransferKind::LedgerMustNotBeZero);
+        }
+        if t.code() == 0 {
+            return Err(TransferKind::CodeMustNotBeZero);
+        }
+
+        let mut dr = *self
+            .accounts
+            .get(&t.debit_account_id())
+            .ok_or(TransferKind::DebitAccountNotFound)?;
+        let mut cr = *self
+            .accounts
+            .get(&t.credit_account_id())
+            .ok_or(TransferKind::CreditAccountNotFound)?;
+        if dr.ledger() != cr.ledger() {
+            return Err(TransferKind::AccountsMustHaveTheSameLedger);
+        }
+        if t.ledger() != dr.ledger() {
+            return Err(TransferKind::TransferMustHaveTheSameLedgerAsAccounts);
+        }
+        if dr.flags().contains(tb::account::Flags::CLOSED) {
+            return Err(TransferKind::DebitAccountAlreadyClosed);
+        }
+        if cr.flags().contains(tb::account::Flags::CLOSED) {
+            return Err(TransferKind::CreditAccountAlreadyClosed);
+        }
+
+        let mut amount = t.amount();
+        if flags.contains(Flags::BALANCING_DEBIT) {
+            let available = dr
+                .credits_posted()
+                .saturating_sub(dr.debits_posted().saturating_add(dr.debits_pending()));
+            amount = amount.min(available);
+        }
+        if flags.contains(Flags::BALANCING_CREDIT) {
+            let available = cr
+                .debits_posted()
+                .saturating_sub(cr.credits_posted().saturating_add(cr.credits_pending()));
+            amount = amount.min(available);
+        }
+
+        let is_pending = flags.contains(Flags::PENDING);
+        if is_pending {
+            dr.debits_pending()
+                .checked_add(amount)
+                .ok_or(TransferKind::OverflowsDebitsPending)?;
+            cr.credits_pending()
+                .checked_add(amount)
+                .ok_or(TransferKind::OverflowsCreditsPending)?;
+        } else {
+            dr.debits_posted()
+                .checked_add(amount)
+                .ok_or(TransferKind::OverflowsDebitsPosted)?;
+            cr.credits_posted()
+                .checked_add(amount)
+                .ok_or(TransferKind::OverflowsCreditsPosted)?;
+        }
+        let dr_debits = dr
+            .debits_pending()
+            .checked_add(dr.debits_posted())
+            .and_then(|v| v.checked_add(amount))
+            .ok_or(TransferKind::OverflowsDebits)?;
+        let cr_credits = cr
+            .credits_pending()
+            .checked_add(cr.credits_posted())
+            .and_then(|v| v.checked_add(amount))
+            .ok_or(TransferKind::OverflowsCredits)?;
+
+        let timestamp = self.next_timestamp();
+        if is_pending {
+            (t.timeout() as u64)
+                .checked_mul(NANOS_PER_SECOND)
+                .and_then(|v| v.checked_add(timestamp))
+                .ok_or(TransferKind::OverflowsTimeout)?;
+        }
+
+        if dr
+            .flags()
+            .contains(tb::account::Flags::DEBITS_MUST_NOT_EXCEED_CREDITS)
+            && dr_debits > dr.credits_posted()
+        {
+            return Err(TransferKind::ExceedsCredits);
+        }
+        if cr
+            .flags()
+            .contains(tb::account::Flags::CREDITS_MUST_NOT_EXCEED_DEBITS)
+            && cr_credits > cr.debits_posted()
+        {
+            return Err(TransferKind::ExceedsDebits);
+        }
+
+        if is_pending {
+            dr.as_raw_mut().debits_pending += amount;
+            cr.as_raw_mut().credits_pending += amount;
+        } else {
+            dr.as_raw_mut().debits_posted += amount;
+            cr.as_raw_mut().credits_posted += amount;
+        }
+        if flags.contains(Flags::CLOSING_DEBIT) {
+            dr.set_flags(dr.flags() | tb::account::Flags::CLOSED);
+        }
+        if flags.contains(Flags::CLOSING_CREDIT) {
+            cr.set_flags(cr.flags() | tb::account::Flags::CLOSED);
+        }
+        self.put_account(dr, undo);
+        self.put_account(cr, undo);
+
+        let mut transfer = t.with_amount(amount);
+        transfer.as_raw_mut().timestamp = timestamp;
+        self.push_transfer(transfer, undo);
+        if is_pending && t.timeout() != 0 {
+            self.timeouts.push(t.id());
+        }
+        Ok(())
+    }
+
+    fn post_or_void_pending_transfer(
+        &mut self,
+        t: &tb::Transfer,
+        undo: &mut Vec<Undo>,
+    ) -> Result<(), TransferKind> {
+        use tb::transfer::Flags;
+
+        let is_post = t.flags().contains(Flags::POST_PENDING_TRANSFER);
+        if t.pending_id() == 0 {
+            return Err(TransferKind::PendingIdMustNotBeZero);
+        }
+        if t.pending_id() == u128::MAX {
+            return Err(TransferKind::PendingIdMustNotBeIntMax);
+        }
+        if t.pending_id() == t.id() {
+            return Err(TransferKind::PendingIdMustBeDifferent);
+        }
+        if t.timeout() != 0 {
+            return Err(TransferKind::TimeoutReservedForPendingTransfer);
+        }
+
+        let p = *self
+            .transfer(t.pending_id())
+            .ok_or(TransferKind::PendingTransferNotFound)?;
+        if !p.flags().contains(Flags::PENDING) {
+            return Err(TransferKind::PendingTransferNotPending);
+        }
+        if t.debit_account_id() != 0 && t.debit_account_id() != p.debit_account_id() {
+            return Err(TransferKind::PendingTransferHasDifferentDebitAccountId);
+        }
+        if t.credit_account_id() != 0 && t.credit_account_id() != p.credit_account_id() {
+            return Err(TransferKind::PendingTransferHasDifferentCreditAccountId);
+        }
+        if t.ledger() != 0 && t.ledger() != p.ledger() {
+            return Err(TransferKind::PendingTransferHasDifferentLedger);
+        }
+        if t.code() != 0 && t.code() != p.code() {
+            return Err(TransferKind::PendingTransferHasDifferentCode);
+        }
+
+        let amount = if is_post {
+            let amount = if t.amount() == u128::MAX {
+                p.amount()
+            } else {
+                t.amount()
+            };
+            if amount > p.amount() {
+                return Err(TransferKind::ExceedsPendingTransferAmount);
+            }
+            amount
+        } else {
+            if t.amount() != 0 && t.amount() != u128::MAX && t.amount() != p.amount() {
+                return Err(TransferKind::PendingTransferHasDifferentAmount);
+            }
+            p.amount()
+        };
+
+        match self.resolved.get(&p.id()) {
+            Some(Resolution::Posted) => return Err(TransferKind::PendingTransferAlreadyPosted),
+            Some(Resolution::Voided) => return Err(TransferKind::PendingTransferAlreadyVoided),
+            Some(Resolution::Expired) => return Err(TransferKind::PendingTransferExpired),
+            None => {}
+        }
+
+        let mut dr = *self
+            .accounts
+            .get(&p.debit_account_id())
+            .ok_or(TransferKind::DebitAccountNotFound)?;
+        let mut cr = *self
+            .accounts
+            .get(&p.credit_account_id())
+            .ok_or(TransferKind::CreditAccountNotFound)?;
+        dr.as_raw_mut().debits_pending -= p.amount();
+        cr.as_raw_mut().credits_pending -= p.amount();
+        if is_post {
+            dr.as_raw_mut().debits_posted = dr
+                .debits_posted()
+                .checked_add(amount)
+                .ok_or(TransferKind::OverflowsDebitsPosted)?;
+            cr.as_raw_mut().credits_posted = cr
+                .credits_posted()
+                .checked_add(amount)
+                .ok_or(TransferKind::OverflowsCreditsPosted)?;
+        } else {
+            if p.flags().contains(Flags::CLOSING_DEBIT) {
+                dr.set_flags(dr.flags() - tb::account::Flags::CLOSED);
+            }
+            if p.flags().contains(Flags::CLOSING_CREDIT) {
+                cr.set_flags(cr.flags() - tb::account::Flags::CLOSED);
+            }
+        }
+        self.put_account(dr, undo);
+        self.put_account(cr, undo);
+
+        let mut transfer = t
+            .with_debit_account_id(p.debit_account_id())
+            .with_credit_account_id(p.credit_account_id())
+            .with_ledger(p.ledger())
+            .with_code(p.code())
+            .with_amount(amount);
+        if t.user_data_128() == 0 {
+            transfer.set_user_data_128(p.user_data_128());
+        }
+        if t.user_data_64() == 0 {
+            transfer.set_user_data_64(p.user_data_64());
+        }
+        if t.user_data_32() == 0 {
+            transfer.set_user_data_32(p.user_data_32());
+        }
+        transfer.as_raw_mut().timestamp = self.next_timestamp();
+        self.push_transfer(transfer, undo);
+        self.resolve(
+            p.id(),
+            if is_post {
+                Resolution::Posted
+            } else {
+                Resolution::Voided
+            },
+            undo,
+        );
+        Ok(())
+    }
+
+    /// Releases the amounts of pending transfers whose timeout has passed.
+    fn expire_pending_transfers(&mut self) {
+        let now = self.next_timestamp();
+        let mut expired = Vec::new();
+        self.timeouts.retain(|id| {
+            let Some(p) = self.transfer_index.get(id).map(|i| self.transfers[*i]) else {
+                return false;
+            };
+            if self.resolved.contains_key(id) {
+                return false;
+            }
+            let expires_at = p.as_raw().timestamp + p.timeout() as u64 * NANOS_PER_SECOND;
+            if expires_at > now {
+                return true;
+            }
+            expired.push(p);
+            false
+        });
+
+        for p in expired {
+            for (account_id, is_debit) in
+                [(p.debit_account_id(), true), (p.credit_account_id(), false)]
+            {
+                if let Some(account) = self.accounts.get_mut(&account_id) {
+                    if is_debit {
+                        account.as_raw_mut().debits_pending -= p.amount();
+                    } else {
+                        account.as_raw_mut().credits_pending -= p.amount();
+                    }
+                    if p.flags().contains(tb::transfer::Flags::CLOSING_DEBIT) && is_debit
+                        || p.flags().contains(tb::transfer::Flags::CLOSING_CREDIT) && !is_debit
+                    {
+                        account.set_flags(account.flags() - tb::account::Flags::CLOSED);
+                    }
+                }
+            }
+            self.resolved.insert(p.id(), Resolution::Expired);
+        }
+    }
+}
+
+fn transfer_exists_kind(t: &tb::Transfer, e: &tb::Transfer) -> TransferKind {
+    // post and void transfers may leave fields empty that are copied from the pending transfer
+    let differs = |requested: u128, existing: u128| {
+        requested != existing
+            && !(requested == 0
+                && t.flags().intersects(
+                    tb::transfer::Flags::POST_PENDING_TRANSFER
+                        | tb::transfer::Flags::VOID_PENDING_TRANSFER,
+                ))
+    };
+    if t.flags() != e.flags() {
+        TransferKind::ExistsWithDifferentFlags
+    } else if t.pending_id() != e.pending_id() {
+        TransferKind::ExistsWithDifferentPendingId
+    } else if t.timeout() != e.timeout() {
+        TransferKind::ExistsWithDifferentTimeout
+    } else if differs(t.debit_account_id(), e.debit_account_id()) {
+        TransferKind::ExistsWithDifferentDebitAccountId
+    } else if differs(t.credit_account_id(), e.credit_account_id()) {
+        TransferKind::ExistsWithDifferentCreditAccountId
+    } else if t.amount() != e.amount()
+        && !t.flags().intersects(
+            tb::transfer::Flags::BALANCING_DEBIT
+                | tb::transfer::Flags::BALANCING_CREDIT
+                | tb::transfer::Flags::POST_PENDING_TRANSFER
+                | tb::transfer::Flags::VOID_PENDING_TRANSFER,
+        )
+    {
+        TransferKind::ExistsWithDifferentAmount
+    } else if differs(t.user_data_128(), e.user_data_128()) {
+        TransferKind::ExistsWithDifferentUserData128
+    } else if differs(t.user_data_64() as u128, e.user_data_64() as u128) {
+        TransferKind::ExistsWithDifferentUserData64
+    } else if differs(t.user_data_32() as u128, e.user_data_32() as u128) {
+        TransferKind::ExistsWithDifferentUserData32
+    } else if differs(t.ledger() as u128, e.ledger() as u128) {
+        TransferKind::ExistsWithDifferentLedger
+    } else if differs(t.code() as u128, e.code() as u128) {
+        TransferKind::ExistsWithDifferentCode
+    } else {
+        TransferKind::Exists
+    }
+}
+
+/// Timestamp range of a filter where zero means unbounded.
+fn in_range(timestamp: u64, min: u64, max: u64) -> bool {
+    timestamp >= min && (max == 0 || timestamp <= max)
+}
+
+fn matches_account_filter(filter: &tb::account::Filter, transfer: &tb::Transfer) -> bool {
+    let raw = filter.as_raw();
+    let flags = filter.flags();
+    let side = (flags.contains(tb::account::FilterFlags::DEBITS)
+        && transfer.debit_account_id() == raw.account_id)
+        || (flags.contains(tb::account::FilterFlags::CREDITS)
+            && transfer.credit_account_id() == raw.account_id);
+    side && in_range(
+        transfer.as_raw().timestamp,
+        raw.timestamp_min,
+        raw.timestamp_max,
+    ) && (raw.user_data_128 == 0 || raw.user_data_128 == transfer.user_data_128())
+        && (raw.user_data_64 == 0 || raw.user_data_64 == transfer.user_data_64())
+        && (raw.user_data_32 == 0 || raw.user_data_32 == transfer.user_data_32())
+        && (raw.code == 0 || raw.code == transfer.code())
+}
+
+fn ordered<'a, T>(
+    items: impl DoubleEndedIterator<Item = T> + 'a,
+    reversed: bool,
+) -> Box<dyn Iterator<Item = T> + 'a> {
+    if reversed {
+        Box::new(items.rev())
+    } else {
+        Box::new(items)
+    }
+}
+
+impl Ledger for MemoryLedger {
+    fn create_accounts(
+        &self,
+        accounts: Vec<tb::Account>,
+    ) -> LedgerFuture<'_, Result<(), tb::error::CreateAccountsError>> {
+        let results = self.with_state(|state| {
+            state.apply_chains(
+                &accounts,
+                |a| a.flags().contains(tb::account::Flags::LINKED),
+                State::create_account,
+                AccountKind::LinkedEventFailed,
+                AccountKind::LinkedEventChainOpen,
+            )
+        });
+        let errors = results
+            .into_iter()
+            .filter_map(|(index, kind)| {
+                tb::error::CreateAccountsIndividualApiError::from_raw_result(
+                    tb::error::RawCreateAccountsIndividualApiResult {
+                        index,
+                        result: tb::error::CreateAccountError::from(kind).code().get(),
+                    },
+                )
+            })
+            .collect();
+        let result = match tb::error::CreateAccountsApiError::from_errors(errors) {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        };
+        Box::pin(std::future::ready(result))
+    }
+
+    fn create_transfers(
+        &self,
+        transfers: Vec<tb::Transfer>,
+    ) -> LedgerFuture<'_, Result<(), tb::error::CreateTransfersError>> {
+        let results = self.with_state(|state| {
+            state.expire_pending_transfers();
+            state.apply_chains(
+                &transfers,
+                |t| t.flags().contains(tb::transfer::Flags::LINKED),
+                State::create_transfer,
+                TransferKind::LinkedEventFailed,
+                TransferKind::LinkedEventChainOpen,
+            )
+        });
+        let errors = results
+            .into_iter()
+            .filter_map(|(index, kind)| {
+                tb::error::CreateTransfersIndividualApiError::from_raw_result(
+                    tb::error::RawCreateTransfersIndividualApiResult {
+                        index,
+                        result: tb::error::CreateTransferError::from(kind).code().get(),
+                    },
+                )
+            })
+            .collect();
+        let result = match tb::error::CreateTransfersApiError::from_errors(errors) {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        };
+        Box::pin(std::future::ready(result))
+    }
+
+    fn lookup_accounts(
+        &self,
+        ids: Vec<u128>,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Account>, tb::error::SendError>> {
+        let accounts = self.with_state(|state| {
+            state.expire_pending_transfers();
+            ids.iter()
+                .filter_map(|id| state.accounts.get(id).copied())
+                .collect()
+        });
+        Box::pin(std::future::ready(Ok(accounts)))
+    }
+
+    fn lookup_transfers(
+        &self,
+        ids: Vec<u128>,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>> {
+        let transfers = self.with_state(|state| {
+            ids.iter()
+                .filter_map(|id| state.transfer(*id).copied())
+                .collect()
+        });
+        Box::pin(std::future::ready(Ok(transfers)))
+    }
+
+    fn get_account_transfers(
+        &self,
+        filter: tb::account::Filter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>> {
+        let transfers = self.with_state(|state| {
+            let reversed = filter.flags().contains(tb::account::FilterFlags::REVERSED);
+            ordered(state.transfers.iter(), reversed)
+                .filter(|t| matches_account_filter(&filter, t))
+                .take(filter.limit() as usize)
+                .copied()
+                .collect()
+        });
+        Box::pin(std::future::ready(Ok(transfers)))
+    }
+
+    fn get_account_balances(
+        &self,
+        filter: tb::account::Filter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::account::Balance>, tb::error::SendError>> {
+        let balances = self.with_state(|state| {
+            state.expire_pending_transfers();
+            let raw = filter.as_raw();
+            let Some(history) = state.history.get(&raw.account_id) else {
+                return Vec::new();
+            };
+            let flags = filter.flags();
+            let reversed = flags.contains(tb::account::FilterFlags::REVERSED);
+            ordered(history.iter(), reversed)
+                .filter(|h| {
+                    let side = if h.is_debit {
+                        tb::account::FilterFlags::DEBITS
+                    } else {
+                        tb::account::FilterFlags::CREDITS
+                    };
+                    flags.contains(side)
+                        && in_range(
+                            h.balance.as_raw().timestamp,
+                            raw.timestamp_min,
+                            raw.timestamp_max,
+                        )
+                })
+                .take(filter.limit() as usize)
+                .map(|h| h.balance)
+                .collect()
+        });
+        Box::pin(std::future::ready(Ok(balances)))
+    }
+
+    fn query_accounts(
+        &self,
+        filter: tb::QueryFilter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Account>, tb::error::SendError>> {
+        let accounts = self.with_state(|state| {
+            let raw = filter.as_raw();
+            let reversed = filter
+                .flags()
+                .contains(tb::core::query_filter::Flags::REVERSED);
+            let mut accounts = state.accounts.values().copied().collect::<Vec<_>>();
+            accounts.sort_by_key(|a| a.as_raw().timestamp);
+            ordered(accounts.into_iter(), reversed)
+                .filter(|a| {
+                    in_range(a.as_raw().timestamp, raw.timestamp_min, raw.timestamp_max)
+                        && (raw.user_data_128 == 0 || raw.user_data_128 == a.user_data_128())
+                        && (raw.user_data_64 == 0 || raw.user_data_64 == a.user_data_64())
+                        && (raw.user_data_32 == 0 || raw.user_data_32 == a.user_data_32())
+                        && (raw.ledger == 0 || raw.ledger == a.ledger())
+                        && (raw.code == 0 || raw.code == a.code())
+                })
+                .take(filter.limit() as usize)
+                .collect()
+        });
+        Box::pin(std::future::ready(Ok(accounts)))
+    }
+
+    fn query_transfers(
+        &self,
+        filter: tb::QueryFilter,
+    ) -> LedgerFuture<'_, Result<Vec<tb::Transfer>, tb::error::SendError>> {
+        let transfers = self.with_state(|state| {
+            let raw = filter.as_raw();
+            let reversed = filter
+                .flags()
+                .contains(tb::core::query_filter::Flags::REVERSED);
+            ordered(state.transfers.iter(), reversed)
+                .filter(|t| {
+                    in_range(t.as_raw().timestamp, raw.timestamp_min, raw.timestamp_max)
+                        && (raw.user_data_128 == 0 || raw.user_data_128 == t.user_data_128())
+                        && (raw.user_data_64 == 0 || raw.user_data_64 == t.user_data_64())
+                        && (raw.user_data_32 == 0 || raw.user_data_32 == t.user_data_32())
+                        && (raw.ledger == 0 || raw.ledger == t.ledger())
+                        && (raw.code == 0 || raw.code == t.code())
+                })
+                .take(filter.limit() as usize)
+                .copied()
+                .collect()
+        });
+        Box::pin(std::future::ready(Ok(transfers)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEDGER: u32 = 1;
+
+    async fn ledger_with_accounts() -> MemoryLedger {
+        let ledger = MemoryLedger::new();
+        ledger
+            .create_accounts(vec![
+                tb::Account::new(1, LEDGER, 1).with_flags(tb::account::Flags::HISTORY),
+                tb::Account::new(2, LEDGER, 1).with_flags(
+                    tb::account::Flags::HISTORY
+                        | tb::account::Flags::DEBITS_MUST_NOT_EXCEED_CREDITS,
+                ),
+            ])
+            .await
+            .unwrap();
+        ledger
+    }
+
+    fn transfer(id: u128, debit: u128, credit: u128, amount: u128) -> tb::Transfer {
+        tb::Transfer::new(id)
+            .with_debit_account_id(debit)
+            .with_credit_account_id(credit)
+            .with_amount(amount)
+            .with_ledger(LEDGER)
+            .with_code(1)
+    }
+
+    fn transfer_error_kinds(err: tb::error::CreateTransfersError) -> Vec<(u32, TransferKind)> {
+        match err {
+            tb::error::CreateTransfersError::Api(err) => err
+                .as_slice()
+                .iter()
+                .map(|e| (e.index(), e.kind()))
+                .collect(),
+            _ => panic!("expected api error"),
+        }
+    }
+
+    async fn account(ledger: &MemoryLedger, id: u128) -> tb::Account {
+        ledger.lookup_accounts(vec![id]).await.unwrap()[0]
+    }
+
+    #[tokio::test]
+    async fn test_create_accounts_exists() {
+        let ledger = ledger_with_accounts().await;
+        let err = ledger
+            .create_accounts(vec![tb::Account::new(1, LEDGER, 1)])
+            .await
+            .unwrap_err();
+        let tb::error::CreateAccountsError::Api(err) = err else {
+            panic!("expected api error");
+        };
+        assert!(matches!(
+            err.as_slice()[0].kind(),
+            AccountKind::ExistsWithDifferentFlags
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_linked_transfers_rollback() {
+        let ledger = ledger_with_accounts().await;
+        let err = ledger
+            .create_transfers(vec![
+                transfer(10, 1, 2, 5).with_flags(tb::transfer::Flags::LINKED),
+                transfer(11, 2, 1, 6),
+            ])
+            .await
+            .unwrap_err();
+        let kinds = transfer_error_kinds(err);
+        assert!(matches!(
+            kinds.as_slice(),
+            [
+                (0, TransferKind::LinkedEventFailed),
+                (1, TransferKind::ExceedsCredits)
+            ]
+        ));
+        assert_eq!(account(&ledger, 1).await.debits_posted(), 0);
+        assert!(ledger.lookup_transfers(vec![10]).await.unwrap().is_empty());
+
+        let err = ledger
+            .create_transfers(vec![
+                transfer(12, 1, 2, 5),
+                transfer(13, 1, 2, 5).with_flags(tb::transfer::Flags::LINKED),
+            ])
+            .await
+            .unwrap_err();
+        let kinds = transfer_error_kinds(err);
+        assert!(matches!(
+            kinds.as_slice(),
+            [(1, TransferKind::LinkedEventChainOpen)]
+        ));
+        assert_eq!(account(&ledger, 2).await.credits_posted(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_pending_post_and_void() {
+        let ledger = ledger_with_accounts().await;
+        ledger
+            .create_transfers(vec![
+                transfer(20, 1, 2, 10).with_flags(tb::transfer::Flags::PENDING),
+                transfer(21, 1, 2, 7).with_flags(tb::transfer::Flags::PENDING),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(account(&ledger, 1).await.debits_pending(), 17);
+
+        ledger
+            .create_transfers(vec![
+                tb::Transfer::new(22)
+                    .with_pending_id(20)
+                    .with_amount(4)
+                    .with_flags(tb::transfer::Flags::POST_PENDING_TRANSFER),
+                tb::Transfer::new(23)
+                    .with_pending_id(21)
+                    .with_flags(tb::transfer::Flags::VOID_PENDING_TRANSFER),
+            ])
+            .await
+            .unwrap();
+        let debit = account(&ledger, 1).await;
+        assert_eq!(debit.debits_pending(), 0);
+        assert_eq!(debit.debits_posted(), 4);
+
+        let err = ledger
+            .create_transfers(vec![tb::Transfer::new(24)
+                .with_pending_id(20)
+                .with_flags(tb::transfer::Flags::VOID_PENDING_TRANSFER)])
+            .await
+            .unwrap_err();
+        let kinds = transfer_error_kinds(err);
+        assert!(matches!(
+            kinds.as_slice(),
+            [(0, TransferKind::PendingTransferAlreadyPosted)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_account_transfers_and_balances() {
+        let ledger = ledger_with_accounts().await;
+        ledger
+            .create_transfers(vec![
+                transfer(30, 1, 2, 1),
+                transfer(31, 1, 2, 2),
+                transfer(32, 1, 2, 3),
+            ])
+            .await
+            .unwrap();
+
+        let filter = tb::account::Filter::new(1, 2)
+            .with_flags(tb::account::FilterFlags::DEBITS | tb::account::FilterFlags::REVERSED);
+        let transfers = ledger.get_account_transfers(filter).await.unwrap();
+        assert_eq!(
+            transfers.iter().map(|t| t.id()).collect::<Vec<_>>(),
+            vec![32, 31]
+        );
+
+        let filter = tb::account::Filter::new(2, 10)
+            .with_flags(tb::account::FilterFlags::DEBITS | tb::account::FilterFlags::CREDITS);
+        let balances = ledger.get_account_balances(filter).await.unwrap();
+        assert_eq!(
+            balances
+                .iter()
+                .map(|b| b.credits_posted())
+                .collect::<Vec<_>>(),
+            vec![1, 3, 6]
+        );
+
+        let filter = tb::account::Filter::new(2, 10).with_flags(tb::account::FilterFlags::DEBITS);
+        assert!(ledger
+            .get_account_transfers(filter)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
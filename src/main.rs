@@ -1,21 +1,25 @@
 #![warn(clippy::unwrap_used)]
 
 use std::sync::{Arc, LazyLock};
+mod beancount;
+mod hledger;
 mod http_err;
+mod ledger;
 mod models;
+mod prices;
+mod reports;
 mod responses;
 
 mod e2e_test;
 mod routes;
 mod schema;
+mod store;
 mod tb_utils;
 
 use axum::{
     routing::{get, post, put},
     Router,
 };
-use deadpool_diesel::postgres::Pool;
-use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use dotenvy::dotenv;
 use regex::Regex;
 use tigerbeetle_unofficial as tb;
@@ -24,26 +28,45 @@ use utoipa::OpenApi;
 extern crate clap;
 
 #[derive(OpenApi)]
-#[openapi(paths(
-    routes::mutate_migrate,
-    routes::query_account_names_all,
-    routes::query_export_hledger,
-    routes::query_export_csv,
-    routes::mutate_import_csv,
-    routes::mutate_add,
-    routes::query_prepare_add_fcfs,
-    routes::query_account_transactions,
-    routes::query_commodities_all,
-    routes::query_account_balances,
-    routes::query_account_income_statement,
-    routes::get_openapi,
-    routes::get_version,
-))]
+#[openapi(
+    paths(
+        routes::mutate_migrate,
+        routes::query_account_names_all,
+        routes::query_export_hledger,
+        routes::query_export_ndjson,
+        routes::query_export_beancount,
+        routes::query_export_csv,
+        routes::mutate_import_csv,
+        routes::mutate_import_hledger,
+        routes::mutate_import_beancount,
+        routes::mutate_add,
+        routes::mutate_add_pending,
+        routes::mutate_post_pending,
+        routes::mutate_void_pending,
+        routes::mutate_reverse,
+        routes::query_prepare_add_fcfs,
+        routes::query_account_transactions,
+        routes::query_pending_transactions,
+        routes::query_commodities_all,
+        routes::mutate_commodity,
+        routes::mutate_prices,
+        routes::mutate_exchange,
+        routes::mutate_revalue,
+        routes::query_balance_sheet,
+        routes::query_income_statement,
+        routes::query_cash_flow,
+        routes::query_trial_balance,
+        routes::query_account_register,
+        routes::query_prices,
+        routes::query_account_balances,
+        routes::query_account_income_statement,
+        routes::get_openapi,
+        routes::get_version,
+    ),
+    components(schemas(responses::AmountFormat))
+)]
 struct ApiDoc;
 
-// this embeds the migrations into the application binary
-// the migration path is relative to the `CARGO_MANIFEST_DIR`
-pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/");
 static RE_ENV_TRUE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^(1|true|True|TRUE)$").expect("invalid regex"));
 
@@ -52,8 +75,8 @@ static RE_ENV_PORT: LazyLock<Regex> =
 
 #[derive(Clone)]
 pub struct AppState {
-    pub pool: Pool,
-    pub tb: Arc<tb::Client>,
+    pub store: Arc<dyn store::Store>,
+    pub ledger: Arc<dyn ledger::Ledger>,
     pub allow_add: bool,
     pub allow_migrate: bool,
 }
@@ -63,6 +86,7 @@ async fn main() {
     // setup dot env
     dotenv().ok();
 
+    #[allow(clippy::bind_instead_of_map)]
     let port = std::env::var("PORT")
         .and_then(|v| {
             if RE_ENV_PORT.is_match(v.as_str()) {
@@ -84,10 +108,6 @@ async fn main() {
 
 pub async fn router() -> Router {
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let tb_cluster_id = std::env::var("TB_CLIENT_ID")
-        .expect("TB_CLIENT_ID must be set")
-        .parse()
-        .expect("TB_CLIENT_ID must be a number");
     let tb_address = std::env::var("TB_ADDRESS").expect("TB_ADDRESS must be set");
     let allow_add =
         RE_ENV_TRUE.is_match(&std::env::var("ALLOW_ADD").expect("ALLOW_ADD must be set"));
@@ -97,36 +117,31 @@ pub async fn router() -> Router {
         panic!("ALLOW_ADD must be true if ALLOW_MIGRATE is true");
     }
 
-    // setup connection pool
-    let manager =
-        deadpool_diesel::postgres::Manager::new(database_url, deadpool_diesel::Runtime::Tokio1);
-    let pool = deadpool_diesel::postgres::Pool::builder(manager)
-        .build()
-        .expect("unable to connect to postgres");
-
-    // run the migrations on server startup
-    {
-        let conn = pool
-            .get()
-            .await
-            .expect("unable to connect to postgres pool");
-        conn.interact(|conn| conn.run_pending_migrations(MIGRATIONS).map(|_| ()))
-            .await
-            .expect("unable to send request to postgres pool")
-            .expect("error running migrations");
-    }
-
-    let tb = Arc::new(
-        tb::Client::new(tb_cluster_id, tb_address).expect("Unable to connect to tigerbeetle"),
-    );
+    // setup connection pool and run the migrations on server startup
+    let store = store::connect(&database_url).await;
+
+    // TB_ADDRESS=memory keeps the ledger in memory, nothing is persisted
+    let ledger: Arc<dyn ledger::Ledger> = if tb_address == "memory" {
+        Arc::new(ledger::MemoryLedger::new())
+    } else {
+        let tb_cluster_id = std::env::var("TB_CLIENT_ID")
+            .expect("TB_CLIENT_ID must be set")
+            .parse()
+            .expect("TB_CLIENT_ID must be a number");
+        Arc::new(
+            tb::Client::new(tb_cluster_id, tb_address).expect("Unable to connect to tigerbeetle"),
+        )
+    };
 
-    let app_state = AppState {
-        pool,
-        tb,
+    app(AppState {
+        store,
+        ledger,
         allow_add,
         allow_migrate,
-    };
+    })
+}
 
+pub fn app(app_state: AppState) -> Router {
     Router::new()
         .route("/mutate/migrate", put(routes::mutate_migrate))
         .route(
@@ -134,14 +149,48 @@ pub async fn router() -> Router {
             post(routes::query_account_names_all),
         )
         .route("/mutate/add", put(routes::mutate_add))
+        .route("/mutate/pending", put(routes::mutate_add_pending))
+        .route("/mutate/post", put(routes::mutate_post_pending))
+        .route("/mutate/void", put(routes::mutate_void_pending))
+        .route("/mutate/reverse", put(routes::mutate_reverse))
+        .route("/mutate/commodity", put(routes::mutate_commodity))
+        .route("/mutate/prices", put(routes::mutate_prices))
+        .route("/mutate/exchange", put(routes::mutate_exchange))
+        .route("/mutate/revalue", put(routes::mutate_revalue))
+        .route("/query/balance-sheet", post(routes::query_balance_sheet))
+        .route(
+            "/query/income-statement",
+            post(routes::query_income_statement),
+        )
+        .route("/query/cash-flow", post(routes::query_cash_flow))
+        .route("/query/trial-balance", post(routes::query_trial_balance))
+        .route(
+            "/query/account-register",
+            post(routes::query_account_register),
+        )
+        .route("/query/prices", post(routes::query_prices))
         .route("/query/export-hledger", post(routes::query_export_hledger))
+        .route("/query/export-ndjson", post(routes::query_export_ndjson))
+        .route(
+            "/query/export-beancount",
+            post(routes::query_export_beancount),
+        )
         .route("/query/export-csv", post(routes::query_export_csv))
         .route("/mutate/import-csv", put(routes::mutate_import_csv))
+        .route("/mutate/import-hledger", put(routes::mutate_import_hledger))
+        .route(
+            "/mutate/import-beancount",
+            put(routes::mutate_import_beancount),
+        )
         .route("/query/prepare-add", post(routes::query_prepare_add_fcfs))
         .route(
             "/query/account-transactions",
             post(routes::query_account_transactions),
         )
+        .route(
+            "/query/pending-transactions",
+            post(routes::query_pending_transactions),
+        )
         .route(
             "/query/commodities-all",
             post(routes::query_commodities_all),
@@ -1,12 +1,14 @@
-use crate::{http_err, responses, tb_utils::u128};
-use deadpool_diesel::postgres::Object;
-use diesel::{prelude::*, result::Error::NotFound};
+use crate::{http_err, ledger::Ledger, responses, store::Store, tb_utils::u128};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use std::sync::Arc;
 use tigerbeetle_unofficial as tb;
 use validator::ValidationError;
 
 pub static TB_MAX_BATCH_SIZE: u32 = 8190;
+/// Parent of the trading accounts that balance each commodity of an exchange.
+pub const CONVERSION_ACCOUNT: &str = "e:conversion";
 
 // #[derive(Queryable, Selectable)]
 // #[diesel(table_name = crate::schema::commodities)]
@@ -19,196 +21,78 @@ pub static TB_MAX_BATCH_SIZE: u32 = 8190;
 
 #[derive(Selectable, Queryable)]
 #[diesel(table_name = crate::schema::commodities)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 pub struct CommodityInsertResponse {
     pub id: i32,
 }
 
 #[derive(Selectable, Queryable)]
 #[diesel(table_name = crate::schema::commodities)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 pub struct CommodityQueryIdUnit {
     pub id: i32,
     pub unit: String,
 }
 
-pub async fn insert_commodities(
-    conn: &Object,
-    new_commodities: Vec<responses::MigrateCommodity>,
-) -> http_err::HttpResult<Vec<CommodityInsertResponse>> {
-    use crate::schema::commodities::dsl::*;
-
-    // Find commodities that already exist and if the ledger id and unit are equal, ignore from insertion script.
-    let commodities_id_filter: Vec<i32> = new_commodities
-        .clone()
-        .into_iter()
-        .map(|c| c.id.to_owned())
-        .collect();
-    let commodities_id_filter_clone = commodities_id_filter.clone();
-    let new_commodities = {
-        let old_commodities: Vec<CommodityQueryIdUnit> = conn
-            .interact(move |conn| {
-                commodities
-                    .select(CommodityQueryIdUnit::as_select())
-                    .filter(id.eq_any(commodities_id_filter_clone))
-                    .get_results::<CommodityQueryIdUnit>(conn)
-                    .map_err(http_err::internal_error)
-            })
-            .await
-            .map_err(http_err::internal_error)??;
-        new_commodities
-            .iter()
-            .filter_map(|c| {
-                let found_existing = old_commodities.iter().find(|old_c| old_c.id == c.id);
-                match found_existing {
-                    Some(found) => {
-                        if found.unit != c.unit {
-                            Some(Err(http_err::bad_error(ValidationError::new("invalid "))))
-                        } else {
-                            None
-                        }
-                    }
-                    None => Some(Ok(c.clone())),
-                }
-            })
-            .collect::<http_err::HttpResult<Vec<responses::MigrateCommodity>>>()
-    }?;
-
-    conn.interact(move |conn| {
-        diesel::insert_into(commodities)
-            .values(new_commodities)
-            .on_conflict_do_nothing()
-            .execute(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)??;
-
-    conn.interact(move |conn| {
-        commodities
-            .select(CommodityInsertResponse::as_select())
-            .filter(id.eq_any(commodities_id_filter))
-            .get_results::<CommodityInsertResponse>(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)?
-}
-
-pub async fn insert_accounts(
-    conn: &Object,
-    new_accounts: Vec<Account>,
-) -> http_err::HttpResult<()> {
-    use crate::schema::accounts::dsl::*;
-
-    conn.interact(move |conn| {
-        diesel::insert_into(accounts)
-            .values(&new_accounts)
-            .execute(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)??;
-
-    Ok(())
-}
-
 #[derive(Queryable, Selectable, Insertable, Clone)]
 #[diesel(table_name = crate::schema::accounts)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 pub struct Account {
     pub name: String,
     pub tb_id: String,
     pub commodities_id: i32,
 }
 
-pub async fn list_all_accounts(conn: &Object) -> Result<Vec<String>, http_err::HttpErr> {
-    use crate::schema::accounts::dsl::*;
-    conn.interact(|conn| {
-        accounts
-            .distinct()
-            .select(name)
-            .order(name)
-            .load::<String>(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)?
-}
-
 pub async fn find_or_create_account(
-    tb: Arc<tb::Client>,
-    conn: &Object,
+    ledger: &dyn Ledger,
+    store: &dyn Store,
     account_name: String,
     unit: String,
 ) -> http_err::HttpResult<(Account, Commodities)> {
-    use crate::schema::accounts::dsl::*;
-
-    let account_name_clone = account_name.clone();
-    let commodity = find_or_create_commodity(conn, unit.clone()).await?;
-    let commodity_id_clone = commodity.id;
-    let first_account = conn
-        .interact(move |conn| {
-            accounts
-                .select(Account::as_select())
-                .filter(name.eq(account_name))
-                .filter(commodities_id.eq(commodity_id_clone))
-                .first(conn)
-        })
-        .await
-        .map_err(http_err::internal_error)?;
+    let commodity = store.find_or_create_commodity(unit.clone()).await?;
+    let first_account = store
+        .find_account(account_name.clone(), commodity.id)
+        .await?;
 
     match first_account {
-        Ok(v) => Ok((v, commodity)),
-        Err(err) => {
-            if err != NotFound {
-                Err(http_err::internal_error(err))
-            } else {
-                // println!("account {} not found creating...", account_name_clone);
-                create_account(conn, tb, account_name_clone, unit.clone()).await
-            }
+        Some(v) => Ok((v, commodity)),
+        None => {
+            // println!("account {} not found creating...", account_name);
+            create_account(ledger, store, account_name, unit.clone()).await
         }
     }
 }
 
-#[derive(Insertable)]
-#[diesel(table_name =  crate::schema::accounts)]
-pub struct NewAccount<'a> {
-    pub name: &'a str,
-    pub commodities_id: &'a i32,
-    pub tb_id: &'a str,
-}
 async fn create_account(
-    conn: &Object,
-    tb: Arc<tb::Client>,
+    ledger: &dyn Ledger,
+    store: &dyn Store,
     account_name: String,
     unit: String,
 ) -> Result<(Account, Commodities), http_err::HttpErr> {
     // return Err(http_err::internal_error(ValidationError::new("stuff")));
-    let commodity = find_or_create_commodity(conn, unit).await?;
-    let account_name_clone = account_name.clone();
+    let commodity = store.find_or_create_commodity(unit).await?;
     let account_type = AccountType::read(account_name.as_str()).map_err(http_err::bad_error)?;
+    // trading accounts of exchanges are credited for one commodity and debited for the other
+    let is_conversion = account_name
+        .strip_prefix(CONVERSION_ACCOUNT)
+        .is_some_and(|rest| rest.starts_with(':'));
     let id = tb::id();
     // println!("creating account_name: {}", account_name);
-    let account = conn
-        .interact(move |conn| {
-            let new_account = NewAccount {
-                name: account_name_clone.as_str(),
-                commodities_id: &commodity.id,
-                tb_id: &u128::to_hex_string(id),
-            };
-            diesel::insert_into(crate::schema::accounts::table)
-                .values(&new_account)
-                .returning(Account::as_returning())
-                .get_result(conn)
-                .map_err(http_err::internal_error)
+    let account = store
+        .create_account(Account {
+            name: account_name,
+            tb_id: u128::to_hex_string(id),
+            commodities_id: commodity.id,
         })
-        .await
-        .map_err(http_err::internal_error)??;
+        .await?;
 
     let flags = {
         let mut flags = tb::account::Flags::HISTORY;
-        let (disallow_red, disallow_green) = account_type.must_not_exceed();
+        let (disallow_red, disallow_green) = if is_conversion {
+            (false, false)
+        } else {
+            account_type.must_not_exceed()
+        };
         if disallow_green {
             flags |= tb::account::Flags::DEBITS_MUST_NOT_EXCEED_CREDITS
         }
@@ -228,122 +112,35 @@ async fn create_account(
     )
     .with_flags(flags);
 
-    tb.create_accounts(vec![new_tb_account])
+    ledger
+        .create_accounts(vec![new_tb_account])
         .await
         .map_err(http_err::internal_error)?;
 
     Ok((account, commodity))
 }
 
-pub async fn find_accounts_re(
-    conn: &Object,
-    filter: String,
-) -> Result<Vec<Account>, http_err::HttpErr> {
-    use crate::schema::accounts::dsl::*;
-
-    conn.interact(move |conn| {
-        let filter = filter.replace("**", "%").replace("*", "_");
-        let mut q = accounts.into_boxed();
-        for (i, f) in filter.split("|").enumerate() {
-            if i == 0 {
-                q = q.filter(name.like(f));
-            } else {
-                q = q.or_filter(name.like(f));
-            }
-        }
-        let q = q.select(Account::as_select()).order((name, commodities_id));
-        q.get_results::<Account>(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)?
-}
-
-pub async fn find_accounts_re_by_commodity(
-    conn: &Object,
-    filter: String,
-    commodity_unit: String,
-) -> Result<Vec<Account>, http_err::HttpErr> {
-    use crate::schema::accounts::dsl::*;
-    use crate::schema::commodities::dsl::{id as commodities_table_id, *};
-
-    conn.interact(move |conn| {
-        let filter = filter.replace("**", "%").replace("*", "_");
-        let mut q = accounts
-            .inner_join(
-                commodities.on(commodities_table_id
-                    .eq(commodities_id)
-                    .and(unit.eq(commodity_unit))),
-            )
-            .into_boxed();
-        for (i, f) in filter.split("|").enumerate() {
-            if i == 0 {
-                q = q.filter(name.like(f));
-            } else {
-                q = q.or_filter(name.like(f));
-            }
-        }
-        let q = q.select(Account::as_select()).order((name, commodities_id));
-        q.get_results::<Account>(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)?
-}
-
-pub async fn find_accounts_by_tb_ids(
-    conn: &Object,
-    tb_ids: Vec<String>,
-) -> http_err::HttpResult<Vec<Account>> {
-    use crate::schema::accounts::dsl;
-
-    conn.interact(|conn| {
-        dsl::accounts
-            .select(Account::as_select())
-            .filter(dsl::tb_id.eq_any(tb_ids))
-            .get_results::<Account>(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)?
-}
-
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = crate::schema::commodities)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 pub struct Commodities {
     pub id: i32,
     pub unit: String,
     pub decimal_place: i32,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub iso_code: Option<String>,
 }
 
-async fn find_or_create_commodity(
-    conn: &Object,
-    commodity_unit: String,
-) -> Result<Commodities, http_err::HttpErr> {
-    use crate::schema::commodities::dsl::*;
-    let unit_clone = commodity_unit.clone();
-    let first_commodity = conn
-        .interact(|conn| {
-            commodities
-                .select(Commodities::as_select())
-                .filter(unit.eq(commodity_unit))
-                .first(conn)
-        })
-        .await
-        .map_err(http_err::teapot_error)?;
-
-    match first_commodity {
-        Ok(v) => Ok(v),
-        Err(err) => {
-            if err != NotFound {
-                println!("{}", err);
-                Err(http_err::internal_error(err))
-            } else {
-                create_commodity(conn, unit_clone).await
-            }
-        }
-    }
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::commodities)]
+#[diesel(treat_none_as_null = true)]
+pub struct CommodityChangeset {
+    pub unit: String,
+    pub decimal_place: i32,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub iso_code: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -351,43 +148,18 @@ async fn find_or_create_commodity(
 pub struct Newcommodity<'a> {
     pub unit: &'a str,
 }
-async fn create_commodity(conn: &Object, unit: String) -> Result<Commodities, http_err::HttpErr> {
-    conn.interact(move |conn| {
-        let new_commodity = Newcommodity {
-            unit: unit.as_str(),
-        };
-        diesel::insert_into(crate::schema::commodities::table)
-            .values(&new_commodity)
-            .returning(Commodities::as_returning())
-            .get_result(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)?
-}
 
-pub async fn list_all_commodity_units(conn: &Object) -> Result<Vec<String>, http_err::HttpErr> {
-    conn.interact(move |conn| {
-        use crate::schema::commodities::dsl::*;
-        commodities
-            .select(unit)
-            .order(unit)
-            .load(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)?
-}
-pub async fn list_all_commodities(conn: &Object) -> Result<Vec<Commodities>, http_err::HttpErr> {
-    conn.interact(move |conn| {
-        use crate::schema::commodities::dsl::*;
-        commodities
-            .select(commodities::all_columns())
-            .load(conn)
-            .map_err(http_err::internal_error)
-    })
-    .await
-    .map_err(http_err::internal_error)?
+/// Price of one `commodities_id` in `quote_commodities_id` from `date` on.
+#[derive(Queryable, Selectable, Insertable, Clone)]
+#[diesel(table_name = crate::schema::prices)]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
+pub struct Price {
+    pub commodities_id: i32,
+    pub quote_commodities_id: i32,
+    /// unix time milliseconds
+    pub date: i64,
+    /// decimal string, e.g. 1.0834
+    pub price: String,
 }
 
 const ACCOUNT_TYPE_ASSETS: &str = "a";
@@ -395,6 +167,8 @@ const ACCOUNT_TYPE_LIABILITIES: &str = "l";
 const ACCOUNT_TYPE_EQUITY: &str = "e";
 const ACCOUNT_TYPE_REVENUES: &str = "r";
 const ACCOUNT_TYPE_EXPENSES: &str = "x";
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub enum AccountType {
     Assets,
     Liabilities,
@@ -404,7 +178,7 @@ pub enum AccountType {
 }
 
 impl AccountType {
-    fn read(v: &str) -> Result<AccountType, ValidationError> {
+    pub fn read(v: &str) -> Result<AccountType, ValidationError> {
         responses::RE_ACCOUNT
             .captures(v)
             .and_then(|v| v.get(1))
@@ -435,6 +209,14 @@ impl AccountType {
     //     }
     // }
 
+    /// Returns 1 for accounts increased by debits and -1 for accounts increased by credits.
+    pub fn sign(self) -> i128 {
+        match self {
+            AccountType::Assets | AccountType::Expenses => 1,
+            AccountType::Liabilities | AccountType::Equity | AccountType::Revenues => -1,
+        }
+    }
+
     fn must_not_exceed(self) -> (bool, bool) {
         let disallow_red = match self {
             AccountType::Assets | AccountType::Equity | AccountType::Expenses => true,
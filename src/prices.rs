@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use crate::models::{Commodities, Price};
+use crate::responses::parse_decimal;
+
+/// Prices sorted by date, used to value amounts in another commodity.
+pub struct PriceBook {
+    prices: Vec<Price>,
+    decimal_places: HashMap<i32, i32>,
+}
+
+impl PriceBook {
+    pub fn new(mut prices: Vec<Price>, commodities: &[Commodities]) -> PriceBook {
+        prices.sort_by_key(|p| p.date);
+        PriceBook {
+            prices,
+            decimal_places: commodities
+                .iter()
+                .map(|c| (c.id, c.decimal_place))
+                .collect(),
+        }
+    }
+
+    /// Values `amount` of the commodity `from` in the commodity `to` using the latest price on
+    /// or before `date`, a price of `to` in `from` is used inverted.
+    pub fn value(&self, amount: i128, from: i32, to: i32, date: i64) -> Result<i128, String> {
+        if from == to || amount == 0 {
+            return Ok(amount);
+        }
+        let price = self
+            .prices
+            .iter()
+            .rev()
+            .skip_while(|p| p.date > date)
+            .find(|p| {
+                (p.commodities_id == from && p.quote_commodities_id == to)
+                    || (p.commodities_id == to && p.quote_commodities_id == from)
+            })
+            .ok_or(String::from("no price found"))?;
+
+        let (numerator, denominator) = rate(&price.price)?;
+        let (numerator, denominator) = if price.commodities_id == from {
+            (numerator, denominator)
+        } else {
+            (denominator, numerator)
+        };
+        let decimal_place = |id: i32| self.decimal_places.get(&id).copied().unwrap_or(0);
+        convert(
+            amount,
+            numerator,
+            denominator,
+            decimal_place(to) - decimal_place(from),
+        )
+        .ok_or(format!("value of {} overflows", amount))
+    }
+}
+
+/// Splits a decimal price into a numerator and a power of ten denominator.
+fn rate(price: &str) -> Result<(i128, i128), String> {
+    let scale = price
+        .split_once('.')
+        .map(|(_, frac)| frac.len())
+        .unwrap_or(0) as i32;
+    let numerator = parse_decimal(price, scale)?;
+    let denominator = 10i128
+        .checked_pow(scale as u32)
+        .ok_or(format!("price {} has too many decimals", price))?;
+    if numerator <= 0 {
+        return Err(format!("price {} must be positive", price));
+    }
+    Ok((numerator, denominator))
+}
+
+/// Returns `amount * numerator / denominator * 10^exponent` rounded half away from zero.
+fn convert(amount: i128, numerator: i128, denominator: i128, exponent: i32) -> Option<i128> {
+    let power = 10i128.checked_pow(exponent.unsigned_abs())?;
+    let (numerator, denominator) = if exponent >= 0 {
+        (
+            amount.checked_mul(numerator)?.checked_mul(power)?,
+            denominator,
+        )
+    } else {
+        (
+            amount.checked_mul(numerator)?,
+            denominator.checked_mul(power)?,
+        )
+    };
+    let quotient = numerator / denominator;
+    let remainder = (numerator % denominator).abs();
+    if remainder.checked_mul(2)? >= denominator.abs() {
+        Some(quotient + numerator.signum() * denominator.signum())
+    } else {
+        Some(quotient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PriceBook;
+    use crate::models::{Commodities, Price};
+
+    fn commodity(id: i32, unit: &str, decimal_place: i32) -> Commodities {
+        Commodities {
+            id,
+            unit: String::from(unit),
+            decimal_place,
+            symbol: None,
+            name: None,
+            iso_code: None,
+        }
+    }
+
+    fn price(commodities_id: i32, quote_commodities_id: i32, date: i64, price: &str) -> Price {
+        Price {
+            commodities_id,
+            quote_commodities_id,
+            date,
+            price: String::from(price),
+        }
+    }
+
+    #[test]
+    fn value() {
+        // 1 USD (2 decimals) in EUR (2 decimals), 1 BTC (8 decimals) in EUR
+        let book = PriceBook::new(
+            vec![
+                price(1, 2, 20, "0.95"),
+                price(1, 2, 10, "0.9"),
+                price(3, 2, 10, "60000"),
+            ],
+            &[
+                commodity(1, "USD", 2),
+                commodity(2, "EUR", 2),
+                commodity(3, "BTC", 8),
+            ],
+        );
+        assert_eq!(book.value(1000, 1, 2, 10), Ok(900));
+        assert_eq!(book.value(1000, 1, 2, 25), Ok(950));
+        assert_eq!(book.value(-1000, 1, 2, 15), Ok(-900));
+        assert!(book.value(1000, 1, 2, 5).is_err());
+        // inverted price, 9.00 EUR at 0.9 is 10.00 USD
+        assert_eq!(book.value(900, 2, 1, 15), Ok(1000));
+        // 0.5 BTC is 30000.00 EUR
+        assert_eq!(book.value(50_000_000, 3, 2, 10), Ok(3_000_000));
+        // 1.00 EUR is 0.00001667 BTC, rounded
+        assert_eq!(book.value(100, 2, 3, 10), Ok(1667));
+        assert_eq!(book.value(i128::MAX, 3, 2, 10).ok(), None);
+        assert_eq!(book.value(42, 2, 2, 0), Ok(42));
+    }
+}
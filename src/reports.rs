@@ -0,0 +1,302 @@
+use chrono::{Datelike, Days, Months, NaiveDate, TimeZone};
+use std::collections::BTreeMap;
+
+use crate::responses::{CashFlowActivity, CashFlowRule, Period};
+
+/// Most periods a report may span.
+pub const MAX_PERIODS: usize = 1000;
+
+/// Amount of an account, or of all accounts below it, in one commodity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RolledUp {
+    pub account_name: String,
+    /// number of `:` separated parts of the account name
+    pub depth: usize,
+    pub commodities_id: i32,
+    pub amount: i128,
+}
+
+/// Adds the amount of every account to each of its parents, e.g. `a:bank:eur` to `a:bank` and
+/// `a`. Accounts deeper than `depth` are only counted in their parents.
+pub fn roll_up<'a>(
+    amounts: impl IntoIterator<Item = (&'a str, i32, i128)>,
+    depth: Option<usize>,
+) -> Result<Vec<RolledUp>, String> {
+    let mut totals: BTreeMap<(String, i32), i128> = BTreeMap::new();
+    for (account_name, commodities_id, amount) in amounts {
+        let parts = account_name.split(':').collect::<Vec<&str>>();
+        let max_depth = depth.unwrap_or(parts.len()).min(parts.len());
+        for d in 1..=max_depth {
+            let total = totals
+                .entry((parts[..d].join(":"), commodities_id))
+                .or_insert(0);
+            *total = total
+                .checked_add(amount)
+                .ok_or(format!("total of {} overflows", parts[..d].join(":")))?;
+        }
+    }
+    Ok(totals
+        .into_iter()
+        .map(|((account_name, commodities_id), amount)| RolledUp {
+            depth: account_name.split(':').count(),
+            account_name,
+            commodities_id,
+            amount,
+        })
+        .collect())
+}
+
+/// Splits `start` to `end` into periods starting at midnight in `timezone`, the first period
+/// starts at the beginning of the period containing `start`. Each boundary uses the UTC offset of
+/// the zone on that date, so periods across a daylight saving change are an hour shorter or
+/// longer. Returns unix time milliseconds, start inclusive and end exclusive.
+pub fn periods<Tz: TimeZone>(
+    period: Period,
+    start: i64,
+    end: i64,
+    timezone: &Tz,
+) -> Result<Vec<(i64, i64)>, String> {
+    if start >= end {
+        return Err(String::from("start must be before end"));
+    }
+    let first = timezone
+        .timestamp_millis_opt(start)
+        .single()
+        .ok_or(format!("invalid start {}", start))?
+        .date_naive();
+    let first = match period {
+        Period::Daily => Some(first),
+        Period::Weekly => {
+            first.checked_sub_days(Days::new(first.weekday().num_days_from_monday() as u64))
+        }
+        Period::Monthly => first.with_day(1),
+        Period::Quarterly => NaiveDate::from_ymd_opt(first.year(), (first.month0() / 3) * 3 + 1, 1),
+        Period::Yearly => NaiveDate::from_ymd_opt(first.year(), 1, 1),
+    }
+    .ok_or(format!("invalid start {}", start))?;
+    let next = |date: NaiveDate| match period {
+        Period::Daily => date.checked_add_days(Days::new(1)),
+        Period::Weekly => date.checked_add_days(Days::new(7)),
+        Period::Monthly => date.checked_add_months(Months::new(1)),
+        Period::Quarterly => date.checked_add_months(Months::new(3)),
+        Period::Yearly => date.checked_add_months(Months::new(12)),
+    };
+    // a day starting in a daylight saving gap starts at the first hour that exists
+    let millis = |date: NaiveDate| {
+        (0..24)
+            .find_map(|hour| {
+                date.and_hms_opt(hour, 0, 0)
+                    .and_then(|d| timezone.from_local_datetime(&d).earliest())
+            })
+            .map(|d| d.timestamp_millis())
+            .ok_or(format!("invalid date {}", date))
+    };
+
+    let mut periods: Vec<(i64, i64)> = Vec::new();
+    let mut date = first;
+    let mut period_start = millis(date)?;
+    while period_start < end {
+        if periods.len() == MAX_PERIODS {
+            return Err(format!("more than {} periods", MAX_PERIODS));
+        }
+        date = next(date).ok_or(format!("invalid end {}", end))?;
+        let period_end = millis(date)?;
+        periods.push((period_start, period_end));
+        period_start = period_end;
+    }
+    Ok(periods)
+}
+
+/// Rules used after the rules of a request, revenues and expenses are operating, other assets
+/// are investing and liabilities and equity are financing.
+pub fn default_cash_flow_rules() -> Vec<CashFlowRule> {
+    [
+        ("r", CashFlowActivity::Operating),
+        ("x", CashFlowActivity::Operating),
+        ("a", CashFlowActivity::Investing),
+        ("l", CashFlowActivity::Financing),
+        ("e", CashFlowActivity::Financing),
+    ]
+    .into_iter()
+    .map(|(prefix, activity)| CashFlowRule {
+        prefix: String::from(prefix),
+        activity,
+    })
+    .collect()
+}
+
+/// Returns the activity of the longest prefix matching the account, the first rule wins between
+/// prefixes of the same length.
+pub fn classify(account_name: &str, rules: &[CashFlowRule]) -> Option<CashFlowActivity> {
+    rules
+        .iter()
+        .filter(|r| {
+            account_name
+                .strip_prefix(r.prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+        })
+        .rev()
+        .max_by_key(|r| r.prefix.len())
+        .map(|r| r.activity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, default_cash_flow_rules, periods, roll_up};
+    use crate::responses::{CashFlowActivity, CashFlowRule, Period};
+    use chrono::{NaiveDate, Utc};
+
+    fn millis(y: i32, m: u32, d: u32) -> i64 {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .unwrap()
+            .and_utc()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn cash_flow_activity() {
+        let mut rules = vec![
+            CashFlowRule {
+                prefix: String::from("x:interest"),
+                activity: CashFlowActivity::Financing,
+            },
+            CashFlowRule {
+                prefix: String::from("a"),
+                activity: CashFlowActivity::Operating,
+            },
+        ];
+        rules.extend(default_cash_flow_rules());
+        assert_eq!(
+            classify("x:interest:loan", &rules),
+            Some(CashFlowActivity::Financing)
+        );
+        assert_eq!(
+            classify("x:interests", &rules),
+            Some(CashFlowActivity::Operating)
+        );
+        assert_eq!(
+            classify("a:receivable", &rules),
+            Some(CashFlowActivity::Operating)
+        );
+        assert_eq!(
+            classify("a:stocks", &default_cash_flow_rules()),
+            Some(CashFlowActivity::Investing)
+        );
+        assert_eq!(
+            classify("l:loan", &rules),
+            Some(CashFlowActivity::Financing)
+        );
+        assert_eq!(classify("l:loan", &[]), None);
+    }
+
+    #[test]
+    fn period_bounds() {
+        let utc = &Utc;
+        // 2025-02-12 is a wednesday
+        let start = millis(2025, 2, 12) + 1;
+        let end = millis(2025, 4, 1);
+        assert_eq!(
+            periods(Period::Monthly, start, end, utc).unwrap(),
+            vec![
+                (millis(2025, 2, 1), millis(2025, 3, 1)),
+                (millis(2025, 3, 1), millis(2025, 4, 1)),
+            ]
+        );
+        assert_eq!(
+            periods(Period::Weekly, start, millis(2025, 2, 18), utc).unwrap(),
+            vec![
+                (millis(2025, 2, 10), millis(2025, 2, 17)),
+                (millis(2025, 2, 17), millis(2025, 2, 24)),
+            ]
+        );
+        assert_eq!(
+            periods(Period::Quarterly, start, end, utc).unwrap(),
+            vec![(millis(2025, 1, 1), millis(2025, 4, 1))]
+        );
+        assert_eq!(periods(Period::Yearly, start, end, utc).unwrap().len(), 1);
+        assert_eq!(periods(Period::Daily, start, end, utc).unwrap().len(), 48);
+
+        // midnight in Athens is 22:00 utc the day before in winter and 21:00 in summer
+        let athens = &chrono_tz::Europe::Athens;
+        let hours = 60 * 60 * 1000;
+        assert_eq!(
+            periods(
+                Period::Daily,
+                millis(2025, 2, 12),
+                millis(2025, 2, 12) + 1,
+                athens
+            )
+            .unwrap(),
+            vec![(
+                millis(2025, 2, 11) + 22 * hours,
+                millis(2025, 2, 12) + 22 * hours
+            )]
+        );
+        assert_eq!(
+            periods(
+                Period::Monthly,
+                millis(2025, 3, 2),
+                millis(2025, 4, 2),
+                athens
+            )
+            .unwrap(),
+            vec![
+                (
+                    millis(2025, 2, 28) + 22 * hours,
+                    millis(2025, 3, 31) + 21 * hours
+                ),
+                (
+                    millis(2025, 3, 31) + 21 * hours,
+                    millis(2025, 4, 30) + 21 * hours
+                ),
+            ]
+        );
+        // in Santiago the clocks went from midnight to 01:00 on 2024-09-08
+        let santiago = &chrono_tz::America::Santiago;
+        assert_eq!(
+            periods(
+                Period::Daily,
+                millis(2024, 9, 8) + 12 * hours,
+                millis(2024, 9, 8) + 13 * hours,
+                santiago
+            )
+            .unwrap(),
+            vec![(
+                millis(2024, 9, 8) + 4 * hours,
+                millis(2024, 9, 9) + 3 * hours
+            )]
+        );
+
+        assert!(periods(Period::Daily, end, start, utc).is_err());
+        assert!(periods(Period::Daily, 0, millis(2025, 1, 1), utc).is_err());
+    }
+
+    #[test]
+    fn rolled_up() {
+        let amounts = [
+            ("a:bank:eur", 1, 10),
+            ("a:bank:usd", 2, 5),
+            ("a:cash", 1, 3),
+            ("l:card", 1, 7),
+        ];
+        let rows = roll_up(amounts, None).unwrap();
+        let row = |name: &str, commodities_id: i32| {
+            rows.iter()
+                .find(|r| r.account_name == name && r.commodities_id == commodities_id)
+                .map(|r| (r.depth, r.amount))
+        };
+        assert_eq!(row("a", 1), Some((1, 13)));
+        assert_eq!(row("a", 2), Some((1, 5)));
+        assert_eq!(row("a:bank", 1), Some((2, 10)));
+        assert_eq!(row("a:bank:eur", 1), Some((3, 10)));
+        assert_eq!(row("l", 1), Some((1, 7)));
+        assert_eq!(rows.len(), 9);
+
+        let rows = roll_up(amounts, Some(2)).unwrap();
+        assert!(rows.iter().all(|r| r.depth <= 2));
+        assert_eq!(rows.len(), 7);
+
+        assert!(roll_up([("a:x", 1, i128::MAX), ("a:y", 1, 1)], None).is_err());
+    }
+}
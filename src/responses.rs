@@ -2,6 +2,7 @@ use chrono::{DateTime, Utc};
 use diesel::prelude::Insertable;
 use diesel::Selectable;
 use std::collections::HashMap;
+use std::fmt;
 use std::time::UNIX_EPOCH;
 use std::{ops::Neg, sync::LazyLock};
 use utoipa::ToSchema;
@@ -27,13 +28,37 @@ pub struct RequestMigrate {
 pub type RequestAdd = AddTransactions;
 pub type ResponseAdd = Vec<String>;
 
+pub type RequestExchange = AddExchange;
+
+pub type RequestRevalue = Revalue;
+pub type ResponseRevalue = Revaluation;
+
+pub type RequestAddPending = AddPendingTransactions;
+pub type RequestPostPending = PostPendingTransactions;
+pub type RequestVoidPending = VoidPendingTransactions;
+
+pub type RequestReverse = ReverseTransactions;
+
 pub type RequestAddPrepareGlob = AddFilterTransactions;
 pub type ResponseAddPrepare = RequestAdd;
 
+pub type ResponseImportCsv = ImportSummary;
+
 pub type ResponseAccountNames = Vec<String>;
-pub type ResponseCommodities = Vec<String>;
+pub type ResponseCommodities = Vec<Commodity>;
+
+pub type RequestCommodity = SaveCommodity;
+pub type ResponseCommodity = Commodity;
+
+pub type RequestPrices = SavePrices;
+pub type ResponsePrices = Vec<Price>;
 pub type ResponseTransactions = Vec<Transaction>;
 pub type ResponseBalances = Vec<Balance>;
+pub type ResponseBalanceSheet = BalanceSheet;
+pub type ResponseIncomeStatement = IncomeStatementReport;
+pub type ResponseCashFlow = CashFlow;
+pub type ResponseTrialBalance = TrialBalance;
+pub type ResponseRegister = Vec<RegisterPosting>;
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -45,10 +70,249 @@ pub struct ResponseIncomeStatements {
 // Types
 // ------------------------------------
 
+/// Amount in the smallest unit of its commodity, requests setting `amount_format=decimal` send
+/// and receive decimal strings scaled by the decimal place of the commodity instead.
+///
+/// Amounts are serialized as strings so that the full u128 range of tigerbeetle survives json,
+/// requests may still send integers as numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Amount {
+    Integer(i128),
+    Decimal(String),
+}
+
+impl Default for Amount {
+    fn default() -> Self {
+        Amount::Integer(0)
+    }
+}
+
+impl From<i128> for Amount {
+    fn from(value: i128) -> Self {
+        Amount::Integer(value)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AmountVisitor;
+
+        impl de::Visitor<'_> for AmountVisitor {
+            type Value = Amount;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an integer or a string containing an integer or decimal")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Amount, E> {
+                Ok(Amount::Integer(v as i128))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Amount, E> {
+                Ok(Amount::Integer(v as i128))
+            }
+
+            fn visit_i128<E: de::Error>(self, v: i128) -> Result<Amount, E> {
+                Ok(Amount::Integer(v))
+            }
+
+            fn visit_u128<E: de::Error>(self, v: u128) -> Result<Amount, E> {
+                i128::try_from(v)
+                    .map(Amount::Integer)
+                    .map_err(|_| E::custom(format!("amount {} is too large", v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Amount, E> {
+                let digits = v.strip_prefix('-').unwrap_or(v);
+                if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+                    v.parse::<i128>()
+                        .map(Amount::Integer)
+                        .map_err(|_| E::custom(format!("amount {} is too large", v)))
+                } else {
+                    Ok(Amount::Decimal(String::from(v)))
+                }
+            }
+        }
+
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+impl utoipa::PartialSchema for Amount {
+    fn schema() -> utoipa::openapi::RefOr<utoipa::openapi::schema::Schema> {
+        utoipa::openapi::ObjectBuilder::new()
+            .schema_type(utoipa::openapi::schema::Type::String)
+            .description(Some(
+                "Amount in the smallest unit of its commodity as a string, requests setting `amount_format=decimal` send\nand receive decimal strings scaled by the decimal place of the commodity instead",
+            ))
+            .examples(["1050"])
+            .into()
+    }
+}
+
+impl ToSchema for Amount {}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountFormat {
+    /// amounts are integers in the smallest unit of the commodity
+    #[default]
+    Integer,
+    /// amounts are decimal strings, e.g. "12.34"
+    Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Daily,
+    /// weeks start on monday
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Amount {
+    /// Builds the amount of `value` in the smallest unit of its commodity in the given format.
+    pub fn new(value: i128, decimal_place: i32, format: AmountFormat) -> Amount {
+        match format {
+            AmountFormat::Integer => Amount::Integer(value),
+            AmountFormat::Decimal => Amount::Decimal(format_decimal(value, decimal_place)),
+        }
+    }
+
+    /// Returns the amount in the smallest unit of its commodity, with `amount_format=decimal`
+    /// integers are whole units of the commodity.
+    pub fn to_integer(&self, decimal_place: i32, format: AmountFormat) -> Result<i128, String> {
+        match (self, format) {
+            (Amount::Integer(value), AmountFormat::Integer) => Ok(*value),
+            (Amount::Decimal(decimal), AmountFormat::Integer) => Err(format!(
+                "amount {} must be an integer, decimal strings require amount_format=decimal",
+                decimal
+            )),
+            (amount, AmountFormat::Decimal) => parse_decimal(&amount.to_string(), decimal_place),
+        }
+    }
+
+    /// Returns the amount like `to_integer`, rounding decimals past the decimal places of the
+    /// commodity up or down instead of rejecting them, as used for query bounds.
+    pub fn to_integer_rounded(
+        &self,
+        decimal_place: i32,
+        format: AmountFormat,
+        round_up: bool,
+    ) -> Result<i128, String> {
+        match format {
+            AmountFormat::Integer => self.to_integer(decimal_place, format),
+            AmountFormat::Decimal => {
+                parse_decimal_rounded(&self.to_string(), decimal_place, round_up)
+            }
+        }
+    }
+
+    /// Returns the amount in the smallest unit of its commodity after it was converted with
+    /// `to_integer`.
+    pub fn integer(&self) -> Result<i128, ValidationError> {
+        match self {
+            Amount::Integer(value) => Ok(*value),
+            Amount::Decimal(_) => Err(ValidationError::new("decimal amount not converted")),
+        }
+    }
+
+    /// Returns the converted amount as a tigerbeetle amount, which can not be negative.
+    pub fn to_u128(&self) -> Result<u128, ValidationError> {
+        u128::try_from(self.integer()?).map_err(|_| {
+            ValidationError::new("range").with_message("amount must be positive".into())
+        })
+    }
+
+    /// Converts an integer amount to the format of the request.
+    pub fn to_format(&self, decimal_place: i32, format: AmountFormat) -> Amount {
+        match self {
+            Amount::Integer(value) => Amount::new(*value, decimal_place, format),
+            Amount::Decimal(_) => self.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Amount::Integer(value) => write!(f, "{}", value),
+            Amount::Decimal(decimal) => write!(f, "{}", decimal),
+        }
+    }
+}
+
+/// Converts an unsigned tigerbeetle amount to a signed amount.
+pub fn to_i128(amount: u128) -> Result<i128, ValidationError> {
+    i128::try_from(amount).map_err(|_| {
+        ValidationError::new("overflow")
+            .with_message(format!("amount {} does not fit in i128", amount).into())
+    })
+}
+
+/// Subtracts the credits from the debits of an account without wrapping on large balances.
+pub fn debits_sub_credits(debits: u128, credits: u128) -> Result<i128, ValidationError> {
+    to_i128(debits)?
+        .checked_sub(to_i128(credits)?)
+        .ok_or_else(|| {
+            ValidationError::new("overflow")
+                .with_message(format!("balance {} - {} overflows", debits, credits).into())
+        })
+}
+
+fn validate_amount_positive(amount: &Amount) -> Result<(), ValidationError> {
+    let positive = match amount {
+        Amount::Integer(value) => *value >= 1,
+        Amount::Decimal(decimal) => {
+            !decimal.starts_with('-') && decimal.contains(|c: char| ('1'..='9').contains(&c))
+        }
+    };
+    if positive {
+        Ok(())
+    } else {
+        Err(ValidationError::new("range").with_message("amount must be positive".into()))
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    /// number of rows added to the ledger
+    pub imported: usize,
+    /// hex u128 transfer ids of the added rows
+    pub transfer_ids: Vec<String>,
+    /// rows not added because their transfer id already exists
+    pub skipped: Vec<SkippedRow>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedRow {
+    /// row of the csv file, the header is row 1
+    pub row: usize,
+    /// hex u128 transfer id
+    pub transfer_id: String,
+}
+
 pub static RE_ACCOUNTS_GLOB: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^[a-z0-9\*\.\|:]+$").expect("invalid regex"));
 pub static RE_ACCOUNT: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^(a|l|e|r|x):([a-z0-9]+:)*([a-z0-9]+)$").expect("invalid regex"));
+pub static RE_ACCOUNT_PREFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(a|l|e|r|x)(:[a-z0-9]+)*$").expect("invalid regex"));
+pub static RE_ISO_CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Z]{3}$").expect("invalid regex"));
+pub static RE_PRICE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\d+(\.\d+)?$").expect("invalid regex"));
 
 #[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -85,6 +349,77 @@ pub struct MigrateCommodity {
     pub decimal_place: i32,
 }
 
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveCommodity {
+    /// commodity unit used in transactions
+    #[validate(length(min = 1))]
+    pub unit: String,
+    /// location of decimal point, can not change once the commodity has transactions
+    #[validate(range(min = 0, max = 38))]
+    pub decimal_place: i32,
+    /// display symbol, e.g. €
+    pub symbol: Option<String>,
+    /// display name, e.g. Euro
+    pub name: Option<String>,
+    /// ISO 4217 currency code, e.g. EUR
+    #[validate(regex(path=*RE_ISO_CODE))]
+    pub iso_code: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Commodity {
+    /// tigerbeetle ledger id
+    pub id: i32,
+    /// commodity unit used in transactions
+    pub unit: String,
+    /// location of decimal point
+    pub decimal_place: i32,
+    /// display symbol, e.g. €
+    pub symbol: Option<String>,
+    /// display name, e.g. Euro
+    pub name: Option<String>,
+    /// ISO 4217 currency code, e.g. EUR
+    pub iso_code: Option<String>,
+}
+
+impl From<models::Commodities> for Commodity {
+    fn from(c: models::Commodities) -> Self {
+        Commodity {
+            id: c.id,
+            unit: c.unit,
+            decimal_place: c.decimal_place,
+            symbol: c.symbol,
+            name: c.name,
+            iso_code: c.iso_code,
+        }
+    }
+}
+
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SavePrices {
+    #[validate(length(min = 1), nested)]
+    pub prices: Vec<Price>,
+}
+
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Price {
+    /// commodity unit that is priced
+    #[validate(length(min = 1))]
+    pub commodity_unit: String,
+    /// commodity unit the price is in
+    #[validate(length(min = 1))]
+    pub quote_unit: String,
+    /// unix time milliseconds from which the price applies
+    pub date: i64,
+    /// decimal price of one commodity_unit in quote_unit, e.g. "1.0834"
+    #[validate(regex(path=*RE_PRICE))]
+    pub price: String,
+}
+
 #[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Transaction {
@@ -107,9 +442,60 @@ pub struct Transaction {
     /// account name
     pub credit_account: String,
     /// amount added to debit account
-    pub debit_amount: i64,
+    pub debit_amount: Amount,
     /// amount removed from credit account
-    pub credit_amount: i64,
+    pub credit_amount: Amount,
+    /// two-phase state of the transfer
+    pub status: TransferStatus,
+    /// hex u128 id of the pending transfer this transfer posts or voids, empty otherwise
+    pub pending_id: String,
+    /// hex u128 id of the transfer this transfer reverses, empty otherwise
+    pub reversal_of: String,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferStatus {
+    /// funds are reserved but not yet posted
+    Pending,
+    /// funds are moved, either directly or by posting a pending transfer
+    #[default]
+    Posted,
+    /// reserved funds of a pending transfer are released
+    Voided,
+}
+
+impl TransferStatus {
+    pub fn from_tb_flags(flags: tigerbeetle_unofficial::transfer::Flags) -> TransferStatus {
+        use tigerbeetle_unofficial::transfer::Flags;
+        if flags.contains(Flags::PENDING) {
+            TransferStatus::Pending
+        } else if flags.contains(Flags::VOID_PENDING_TRANSFER) {
+            TransferStatus::Voided
+        } else {
+            TransferStatus::Posted
+        }
+    }
+}
+
+impl fmt::Display for TransferStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferStatus::Pending => write!(f, "pending"),
+            TransferStatus::Posted => write!(f, "posted"),
+            TransferStatus::Voided => write!(f, "voided"),
+        }
+    }
+}
+
+/// Side of a transfer the queried accounts are on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferSide {
+    /// the accounts are debited
+    Debit,
+    /// the accounts are credited
+    Credit,
 }
 
 #[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
@@ -125,7 +511,10 @@ pub struct AddTransactions {
 #[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AddTransaction {
+    /// hex u128 transfer id, resubmitting an existing id returns it instead of adding it again
+    pub transfer_id: Option<String>,
     /// commodity used
+    #[validate(length(min = 1))]
     pub commodity_unit: String,
     /// transaction code
     pub code: i32,
@@ -138,58 +527,314 @@ pub struct AddTransaction {
     #[validate(regex(path=*RE_ACCOUNT))]
     pub credit_account: String,
     /// amount added to debit account
-    #[validate(range(min = 1))]
-    pub amount: i64,
+    #[validate(custom(function = "validate_amount_positive"))]
+    pub amount: Amount,
 }
 
-impl AddTransactions {
-    pub fn parse_from_csv_line(line: String) -> Result<AddTransactions, ValidationError> {
-        let mut full_date2: i64 = 0;
-        let mut transaction = AddTransaction {
-            commodity_unit: String::new(),
-            code: 0,
-            related_id: String::new(),
-            debit_account: String::new(),
-            credit_account: String::new(),
-            amount: 0,
+/// Returns the trading account of a commodity, e.g. `e:conversion:usd` for `USD`.
+pub fn conversion_account(commodity_unit: &str) -> Option<String> {
+    let name = commodity_unit
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if name.is_empty() {
+        None
+    } else {
+        Some(format!("{}:{}", models::CONVERSION_ACCOUNT, name))
+    }
+}
+
+fn validate_exchange(exchange: &AddExchange) -> Result<(), ValidationError> {
+    let credit = conversion_account(&exchange.credit_commodity_unit);
+    let debit = conversion_account(&exchange.debit_commodity_unit);
+    if credit.is_none() || debit.is_none() {
+        return Err(ValidationError::new("commodity_unit")
+            .with_message("commodity units of an exchange must contain a letter or digit".into()));
+    }
+    if exchange.credit_commodity_unit == exchange.debit_commodity_unit || credit == debit {
+        return Err(ValidationError::new("commodity_unit")
+            .with_message("an exchange must be between two different commodities".into()));
+    }
+    Ok(())
+}
+
+/// Exchange of one commodity for another, e.g. paying 100 USD for 92 EUR.
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_exchange"))]
+pub struct AddExchange {
+    /// unix time milliseconds
+    pub full_date2: i64,
+    /// transaction code
+    pub code: i32,
+    /// random hex u128 string
+    pub related_id: String,
+    /// account name receiving the bought commodity
+    #[validate(regex(path=*RE_ACCOUNT))]
+    pub debit_account: String,
+    /// commodity bought
+    #[validate(length(min = 1))]
+    pub debit_commodity_unit: String,
+    /// amount of the bought commodity added to the debit account
+    #[validate(custom(function = "validate_amount_positive"))]
+    pub debit_amount: Amount,
+    /// account name paying the sold commodity
+    #[validate(regex(path=*RE_ACCOUNT))]
+    pub credit_account: String,
+    /// commodity sold
+    #[validate(length(min = 1))]
+    pub credit_commodity_unit: String,
+    /// amount of the sold commodity taken from the credit account
+    #[validate(custom(function = "validate_amount_positive"))]
+    pub credit_amount: Amount,
+}
+
+impl AddExchange {
+    /// Splits the exchange into one transaction per commodity, each balanced by the trading
+    /// account of its commodity.
+    pub fn transactions(&self) -> Vec<AddTransaction> {
+        vec![
+            AddTransaction {
+                transfer_id: None,
+                commodity_unit: self.credit_commodity_unit.clone(),
+                code: self.code,
+                related_id: self.related_id.clone(),
+                debit_account: conversion_account(&self.credit_commodity_unit).unwrap_or_default(),
+                credit_account: self.credit_account.clone(),
+                amount: self.credit_amount.clone(),
+            },
+            AddTransaction {
+                transfer_id: None,
+                commodity_unit: self.debit_commodity_unit.clone(),
+                code: self.code,
+                related_id: self.related_id.clone(),
+                debit_account: self.debit_account.clone(),
+                credit_account: conversion_account(&self.debit_commodity_unit).unwrap_or_default(),
+                amount: self.debit_amount.clone(),
+            },
+        ]
+    }
+}
+
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AddPendingTransactions {
+    /// unix time milliseconds
+    pub full_date2: i64,
+    /// seconds until the pending transfers expire, zero never expires
+    pub timeout: u32,
+    /// list of transactions
+    #[validate(length(min = 1))]
+    pub transactions: Vec<AddTransaction>,
+}
+
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PostPendingTransactions {
+    /// list of pending transfers to post
+    #[validate(length(min = 1), nested)]
+    pub transactions: Vec<PostPendingTransaction>,
+}
+
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PostPendingTransaction {
+    /// hex u128 id of the pending transfer
+    pub pending_id: String,
+    /// amount to post, when empty the full pending amount is posted
+    #[validate(custom(function = "validate_amount_positive"))]
+    pub amount: Option<Amount>,
+}
+
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoidPendingTransactions {
+    /// list of hex u128 ids of the pending transfers
+    #[validate(length(min = 1))]
+    pub pending_ids: Vec<String>,
+}
+
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReverseTransactions {
+    /// unix time milliseconds
+    pub full_date2: i64,
+    /// list of hex u128 transfer ids to reverse
+    #[validate(length(min = 1))]
+    pub transfer_ids: Vec<String>,
+}
+
+/// Row of a csv file that can not be imported, rows are numbered like in a spreadsheet so the
+/// header is row 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRowError {
+    pub row: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CsvRowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row {}: {}", self.row, self.message)
+    }
+}
+
+/// Positions of the columns read on import, found by their header name
+struct CsvColumns {
+    transfer_id: Option<usize>,
+    commodity_unit: usize,
+    code: usize,
+    full_date2: usize,
+    related_id: usize,
+    debit_account: usize,
+    credit_account: usize,
+    amount: usize,
+    commodity_decimal: Option<usize>,
+    status: Option<usize>,
+}
+
+impl CsvColumns {
+    fn from_headers(headers: &csv::StringRecord) -> Result<CsvColumns, CsvRowError> {
+        let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+        let mut missing: Vec<&str> = Vec::new();
+        let mut required = |names: &[&'static str]| {
+            names
+                .iter()
+                .find_map(|name| column(name))
+                .unwrap_or_else(|| {
+                    missing.push(names[0]);
+                    0
+                })
         };
+        let columns = CsvColumns {
+            transfer_id: column("transfer_id"),
+            commodity_unit: required(&["commodity_unit"]),
+            code: required(&["code"]),
+            full_date2: required(&["full_date2"]),
+            related_id: required(&["related_id"]),
+            debit_account: required(&["debit_account"]),
+            credit_account: required(&["credit_account"]),
+            amount: required(&["amount", "debit_amount"]),
+            commodity_decimal: column("commodity_decimal"),
+            status: column("status"),
+        };
+        if missing.is_empty() {
+            Ok(columns)
+        } else {
+            Err(CsvRowError {
+                row: 1,
+                message: format!("missing columns {}", missing.join(", ")),
+            })
+        }
+    }
+}
 
-        for (i, v) in line.split(",").into_iter().enumerate() {
-            match i {
-                0 => {
-                    transaction.commodity_unit = String::from(v);
-                }
-                2 => {
-                    transaction.code = v
-                        .parse::<i32>()
-                        .map_err(|_| ValidationError::new("invalid full_date2"))?;
-                }
-                3 => {
-                    full_date2 = v
-                        .parse::<i64>()
-                        .map_err(|_| ValidationError::new("invalid code"))?;
-                }
-                5 => {
-                    transaction.related_id = String::from(v);
-                }
-                7 => {
-                    transaction.debit_account = String::from(v);
-                }
-                8 => {
-                    transaction.credit_account = String::from(v);
-                }
-                9 => {
-                    transaction.amount = v
-                        .parse::<i64>()
-                        .map_err(|_| ValidationError::new("invalid debit_amount"))?;
-                }
-                _ => {}
+impl AddTransactions {
+    /// Parses a csv file into one `AddTransactions` per row together with the line the row starts
+    /// on. Columns are found by the names in the header, `amount` may be named `debit_amount` so
+    /// exported files can be imported again. Rows of an export with the status `pending` or `voided`
+    /// moved no funds and are skipped, the rows posting pending transfers are imported.
+    ///
+    /// Amounts are integers in the smallest unit of their commodity, with `amount_format=decimal`
+    /// they are decimals scaled by the decimal place of their commodity. A row whose
+    /// `commodity_decimal` differs from the decimal place of its commodity is rejected.
+    pub fn parse_from_csv(
+        body: &str,
+        delimiter: u8,
+        decimal_places: &HashMap<String, i32>,
+        amount_format: AmountFormat,
+    ) -> Result<Vec<(usize, AddTransactions)>, Vec<CsvRowError>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .from_reader(body.as_bytes());
+        let headers = reader.headers().map_err(|err| {
+            vec![CsvRowError {
+                row: 1,
+                message: err.to_string(),
+            }]
+        })?;
+        let columns = CsvColumns::from_headers(headers).map_err(|err| vec![err])?;
+
+        let mut errors: Vec<CsvRowError> = Vec::new();
+        let mut add_transactions_arr: Vec<(usize, AddTransactions)> = Vec::new();
+        for (i, record) in reader.records().enumerate() {
+            // quoted fields may span lines, so rows are numbered by the line they start on
+            let position = match &record {
+                Ok(record) => record.position(),
+                Err(err) => err.position(),
             };
+            let row = position.map(|p| p.line() as usize).unwrap_or(i + 2);
+            match record.map_err(|err| err.to_string()).and_then(|record| {
+                AddTransactions::from_csv_record(&record, &columns, decimal_places, amount_format)
+            }) {
+                Ok(Some(v)) => add_transactions_arr.push((row, v)),
+                Ok(None) => {}
+                Err(message) => errors.push(CsvRowError { row, message }),
+            }
         }
-        Ok(AddTransactions {
-            full_date2,
+
+        if errors.is_empty() {
+            Ok(add_transactions_arr)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn from_csv_record(
+        record: &csv::StringRecord,
+        columns: &CsvColumns,
+        decimal_places: &HashMap<String, i32>,
+        amount_format: AmountFormat,
+    ) -> Result<Option<AddTransactions>, String> {
+        let field = |i: usize| record.get(i).unwrap_or_default().trim();
+        match columns.status.map(field) {
+            None | Some("") | Some("posted") => {}
+            Some("pending") | Some("voided") => return Ok(None),
+            Some(status) => return Err(format!("invalid status {}", status)),
+        }
+        let commodity_unit = field(columns.commodity_unit);
+        let decimal_place = decimal_places.get(commodity_unit).copied().unwrap_or(0);
+        if let Some(commodity_decimal) = columns.commodity_decimal.map(field) {
+            if !commodity_decimal.is_empty() && commodity_decimal.parse() != Ok(decimal_place) {
+                return Err(format!(
+                    "commodity_decimal {} differs from the {} decimal places of commodity {}",
+                    commodity_decimal, decimal_place, commodity_unit
+                ));
+            }
+        }
+        let amount = match amount_format {
+            AmountFormat::Integer => field(columns.amount).parse::<i128>().map_err(|_| {
+                format!(
+                    "amount {} must be an integer, decimal amounts require amount_format=decimal",
+                    field(columns.amount)
+                )
+            })?,
+            AmountFormat::Decimal => parse_decimal(field(columns.amount), decimal_place)?,
+        };
+
+        let transaction = AddTransaction {
+            transfer_id: columns
+                .transfer_id
+                .map(field)
+                .filter(|v| !v.is_empty())
+                .map(String::from),
+            commodity_unit: String::from(commodity_unit),
+            code: field(columns.code)
+                .parse::<i32>()
+                .map_err(|_| format!("invalid code {}", field(columns.code)))?,
+            related_id: String::from(field(columns.related_id)),
+            debit_account: String::from(field(columns.debit_account)),
+            credit_account: String::from(field(columns.credit_account)),
+            amount: amount.into(),
+        };
+        transaction.validate().map_err(|err| err.to_string())?;
+
+        Ok(Some(AddTransactions {
+            full_date2: field(columns.full_date2)
+                .parse::<i64>()
+                .map_err(|_| format!("invalid full_date2 {}", field(columns.full_date2)))?,
             transactions: vec![transaction],
-        })
+        }))
     }
 }
 
@@ -222,12 +867,12 @@ pub struct AddFilterTransaction {
     ))]
     pub credit_accounts_filter: Vec<String>,
     /// amount added to debit account
-    #[validate(range(min = 1))]
-    pub amount: i64,
+    #[validate(custom(function = "validate_amount_positive"))]
+    pub amount: Amount,
 }
 
 fn validate_add_filter_transaction_credit_accounts_filter(
-    credit_accounts_filter: &Vec<String>,
+    credit_accounts_filter: &[String],
 ) -> Result<(), ValidationError> {
     if credit_accounts_filter
         .iter()
@@ -251,7 +896,7 @@ impl Transaction {
             .as_millis() as i64;
         let date2 = transfer.user_data_64() as i64;
 
-        let debit_amount = transfer.amount() as i64;
+        let debit_amount = to_i128(transfer.amount())?;
 
         let debit_account = accounts.get(&transfer.debit_account_id());
         let credit_account = accounts.get(&transfer.credit_account_id());
@@ -271,59 +916,301 @@ impl Transaction {
             transfer_id: tb_utils::u128::to_hex_string(transfer.id()),
             debit_account,
             credit_account,
-            debit_amount,
-            credit_amount: debit_amount.neg(),
+            debit_amount: Amount::Integer(debit_amount),
+            credit_amount: Amount::Integer(debit_amount.neg()),
             commodity_unit: commodity.unit.clone(),
             commodity_decimal: commodity.decimal_place,
+            status: TransferStatus::from_tb_flags(transfer.flags()),
+            pending_id: match transfer.pending_id() {
+                0 => String::new(),
+                pending_id => tb_utils::u128::to_hex_string(pending_id),
+            },
+            reversal_of: match transfer.user_data_32() {
+                tb_utils::USER_DATA_32_REVERSAL => {
+                    tb_utils::u128::to_hex_string(tb_utils::u128::from_reversal_id(transfer.id()))
+                }
+                _ => String::new(),
+            },
         })
     }
 
-    pub fn csv_header() -> &'static str {
-        "commodity_unit,commodity_decimal,code,full_date,full_date2,related_id,transfer_id,debit_account,credit_account,debit_amount,credit_amount"
-    }
-    pub fn to_csv(&self) -> Result<String, ValidationError> {
-        Ok(format!(
-            "{},{},{},{},{},{},{},{},{},{},{}",
-            self.commodity_unit,    //  0
-            self.commodity_decimal, //  1
-            self.code,              //  2
-            self.full_date,         //  3
-            self.full_date2,        //  4
-            self.related_id,        //  5
-            self.transfer_id,       //  6
-            self.debit_account,     //  7
-            self.credit_account,    //  8
-            self.debit_amount,      //  9
-            self.credit_amount,     // 10
-        ))
+    pub fn with_format(mut self, format: AmountFormat) -> Transaction {
+        self.debit_amount = self.debit_amount.to_format(self.commodity_decimal, format);
+        self.credit_amount = self.credit_amount.to_format(self.commodity_decimal, format);
+        self
+    }
+
+    /// Amounts are written as decimals scaled by the decimal place of the commodity
+    fn decimal_amounts(&self) -> (Amount, Amount) {
+        (
+            self.debit_amount
+                .to_format(self.commodity_decimal, AmountFormat::Decimal),
+            self.credit_amount
+                .to_format(self.commodity_decimal, AmountFormat::Decimal),
+        )
+    }
+
+    pub fn csv_header() -> [&'static str; 14] {
+        [
+            "commodity_unit",
+            "commodity_decimal",
+            "code",
+            "full_date",
+            "full_date2",
+            "related_id",
+            "transfer_id",
+            "debit_account",
+            "credit_account",
+            "debit_amount",
+            "credit_amount",
+            "reversal_of",
+            "status",
+            "pending_id",
+        ]
+    }
+    pub fn to_csv_record(&self) -> [String; 14] {
+        let (debit_amount, credit_amount) = self.decimal_amounts();
+        [
+            self.commodity_unit.clone(),
+            self.commodity_decimal.to_string(),
+            self.code.to_string(),
+            self.full_date.to_string(),
+            self.full_date2.to_string(),
+            self.related_id.clone(),
+            self.transfer_id.clone(),
+            self.debit_account.clone(),
+            self.credit_account.clone(),
+            debit_amount.to_string(),
+            credit_amount.to_string(),
+            self.reversal_of.clone(),
+            self.status.to_string(),
+            self.pending_id.clone(),
+        ]
     }
     pub fn to_hledger_string(&self) -> Result<String, ValidationError> {
         let date = DateTime::<Utc>::from_timestamp_millis(self.full_date)
             .ok_or(ValidationError::new("invalid full_date"))?;
-        Ok(format!(
-            "{} * {} ; related id {}, code {}\n    {: >12} {: >10} {: <5}\n    {: >12} {: >10} {: <5}\n",
+        let reversal_of = match self.reversal_of.as_str() {
+            "" => String::new(),
+            reversal_of => format!(", reversal of {}", reversal_of),
+        };
+        let pending_id = match self.pending_id.as_str() {
+            "" => String::new(),
+            pending_id => format!(", pending id {}", pending_id),
+        };
+        let (debit_amount, credit_amount) = self.decimal_amounts();
+        Ok(self.comment_unposted(format!(
+            "{} {} {} ; transfer_id: {}, related id {}, code {}{}{}\n    {: >12} {: >10} {: <5}\n    {: >12} {: >10} {: <5}\n",
             //line 1
             date.format("%Y-%m-%d"),
+            self.status_flag(),
+            self.transfer_id,
             self.transfer_id,
             self.related_id,
             self.code,
+            reversal_of,
+            pending_id,
             //line 2
             self.debit_account,
-            self.debit_amount,
+            debit_amount.to_string(),
             self.commodity_unit,
             //line 3
             self.credit_account,
-            self.credit_amount,
+            credit_amount.to_string(),
             self.commodity_unit,
-        ))
+        )))
+    }
+    pub fn to_beancount_string(&self) -> Result<String, ValidationError> {
+        let date = DateTime::<Utc>::from_timestamp_millis(self.full_date)
+            .ok_or(ValidationError::new("invalid full_date"))?;
+        let reversal_of = match self.reversal_of.as_str() {
+            "" => String::new(),
+            reversal_of => format!("  reversal_of: \"{}\"\n", reversal_of),
+        };
+        let pending_id = match self.pending_id.as_str() {
+            "" => String::new(),
+            pending_id => format!("  pending_id: \"{}\"\n", pending_id),
+        };
+        let commodity = crate::beancount::commodity(&self.commodity_unit)?;
+        let (debit_amount, credit_amount) = self.decimal_amounts();
+        Ok(self.comment_unposted(format!(
+            "{} {} \"\"\n  transfer_id: \"{}\"\n  related_id: \"{}\"\n  code: {}\n{}{}  {}  {} {}\n  {}  {} {}\n",
+            date.format("%Y-%m-%d"),
+            self.status_flag(),
+            self.transfer_id,
+            self.related_id,
+            self.code,
+            reversal_of,
+            pending_id,
+            crate::beancount::account_name(&self.debit_account)?,
+            debit_amount,
+            commodity,
+            crate::beancount::account_name(&self.credit_account)?,
+            credit_amount,
+            commodity,
+        )))
+    }
+
+    /// Flag of the journal entry, `!` marks a pending transfer.
+    fn status_flag(&self) -> &'static str {
+        match self.status {
+            TransferStatus::Pending => "!",
+            TransferStatus::Posted | TransferStatus::Voided => "*",
+        }
+    }
+
+    /// Pending and voided transfers move no funds, their journal entries are written as comments
+    /// so that hledger and beancount only book the posted amounts.
+    fn comment_unposted(&self, entry: String) -> String {
+        match self.status {
+            TransferStatus::Posted => entry,
+            TransferStatus::Pending | TransferStatus::Voided => {
+                entry.lines().map(|line| format!("; {}\n", line)).collect()
+            }
+        }
+    }
+}
+
+/// Formats an amount in the smallest unit of a commodity as decimal, `1050` with 2 decimal
+/// places is `10.50`.
+pub fn format_decimal(amount: i128, decimal_place: i32) -> String {
+    let decimal_place = decimal_place.max(0) as usize;
+    let sign = if amount < 0 { "-" } else { "" };
+    let digits = format!(
+        "{:0>width$}",
+        amount.unsigned_abs(),
+        width = decimal_place + 1
+    );
+    let (int, frac) = digits.split_at(digits.len() - decimal_place);
+    match frac {
+        "" => format!("{}{}", sign, int),
+        frac => format!("{}{}.{}", sign, int, frac),
     }
 }
 
+/// Parses a decimal like `10.50` into the smallest unit of a commodity, amounts more precise
+/// than the decimal place of the commodity are rejected.
+pub fn parse_decimal(decimal: &str, decimal_place: i32) -> Result<i128, String> {
+    let invalid = || format!("invalid decimal amount {}", decimal);
+    let (negative, number) = match decimal.strip_prefix('-') {
+        Some(number) => (true, number),
+        None => (false, decimal),
+    };
+    let (int, frac) = match number.split_once('.') {
+        Some((_, "")) => return Err(invalid()),
+        Some((int, frac)) => (int, frac),
+        None => (number, ""),
+    };
+    if int.is_empty() || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let decimal_place = decimal_place.max(0) as usize;
+    if frac.len() > decimal_place {
+        return Err(format!(
+            "amount {} has more than {} decimal places",
+            decimal, decimal_place
+        ));
+    }
+    let value = format!("{}{:0<width$}", int, frac, width = decimal_place)
+        .parse::<i128>()
+        .map_err(|_| invalid())?;
+    Ok(if negative { -value } else { value })
+}
+
+/// Parses a decimal like `parse_decimal`, rounding to the nearest integer above (`round_up`) or
+/// below when it has more than `decimal_place` decimal places.
+pub fn parse_decimal_rounded(
+    decimal: &str,
+    decimal_place: i32,
+    round_up: bool,
+) -> Result<i128, String> {
+    let places = decimal_place.max(0) as usize;
+    let (kept, dropped) = match decimal.split_once('.') {
+        Some((int, frac)) if frac.len() > places => {
+            if !frac.chars().all(|c| c.is_ascii_digit()) {
+                return Err(format!("invalid decimal amount {}", decimal));
+            }
+            let (frac, dropped) = frac.split_at(places);
+            match frac {
+                "" => (String::from(int), dropped),
+                frac => (format!("{}.{}", int, frac), dropped),
+            }
+        }
+        _ => return parse_decimal(decimal, decimal_place),
+    };
+    let value = parse_decimal(&kept, decimal_place)?;
+    if dropped.chars().all(|c| c == '0') {
+        return Ok(value);
+    }
+    let negative = decimal.starts_with('-');
+    let value = match (round_up, negative) {
+        (true, false) => value.checked_add(1),
+        (false, true) => value.checked_sub(1),
+        _ => Some(value),
+    };
+    value.ok_or_else(|| format!("invalid decimal amount {}", decimal))
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferError {
+    /// index of the transaction in the request
+    pub index: u32,
+    /// hex u128 transfer id
+    pub transfer_id: String,
+    /// account name
+    pub debit_account: String,
+    /// account name
+    pub credit_account: String,
+    /// tigerbeetle result in snake case, e.g. exceeds_credits or linked_event_failed
+    pub kind: String,
+}
+
 #[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Balance {
     pub account_name: String,
-    pub amount: i64,
+    pub amount: Amount,
+    /// amount reserved on the debit side by pending transfers
+    pub debits_pending: Amount,
+    /// amount reserved on the credit side by pending transfers
+    pub credits_pending: Amount,
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+    /// amount valued in the commodity of `value_in`, only set when requested
+    pub value: Option<MarketValue>,
+}
+
+impl Balance {
+    pub fn with_format(mut self, format: AmountFormat) -> Balance {
+        for amount in [
+            &mut self.amount,
+            &mut self.debits_pending,
+            &mut self.credits_pending,
+        ] {
+            *amount = amount.to_format(self.commodity_decimal, format);
+        }
+        if let Some(value) = self.value.as_mut() {
+            value.amount = value.amount.to_format(value.commodity_decimal, format);
+        }
+        self
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MarketValue {
+    pub amount: Amount,
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MarketValues {
+    /// value of the amount of each date
+    pub amounts: Vec<Amount>,
     pub commodity_unit: String,
     pub commodity_decimal: i32,
 }
@@ -332,7 +1219,553 @@ pub struct Balance {
 #[serde(rename_all = "camelCase")]
 pub struct IncomeStatement {
     pub account_name: String,
-    pub amounts: Vec<i64>,
+    pub amounts: Vec<Amount>,
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+    /// amounts valued in the commodity of `value_in`, only set when requested
+    pub values: Option<MarketValues>,
+}
+
+impl IncomeStatement {
+    pub fn with_format(mut self, format: AmountFormat) -> IncomeStatement {
+        for amount in self.amounts.iter_mut() {
+            *amount = amount.to_format(self.commodity_decimal, format);
+        }
+        if let Some(values) = self.values.as_mut() {
+            for amount in values.amounts.iter_mut() {
+                *amount = amount.to_format(values.commodity_decimal, format);
+            }
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceSheet {
+    /// unix time milliseconds, empty for the current balances
+    pub as_of: Option<usize>,
+    /// assets, liabilities and equity in that order
+    pub sections: Vec<BalanceSheetSection>,
+    /// accounting equation of each commodity
+    pub checks: Vec<BalanceSheetCheck>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceSheetSection {
+    pub account_type: models::AccountType,
+    /// accounts sorted by name with the totals of their sub accounts, the depth 1 rows are the
+    /// section totals
+    pub rows: Vec<BalanceSheetRow>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceSheetRow {
+    pub account_name: String,
+    /// number of `:` separated parts of the account name
+    pub depth: usize,
+    /// balance of the account and its sub accounts, positive on the normal side of the account
+    /// type, debits for assets and credits for liabilities and equity
+    pub amount: Amount,
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceSheetCheck {
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+    pub assets: Amount,
+    pub liabilities: Amount,
+    pub equity: Amount,
+    /// revenues minus expenses not yet closed to equity
+    pub net_income: Amount,
+    /// true when assets equal liabilities plus equity plus net income
+    pub balanced: bool,
+}
+
+impl BalanceSheet {
+    pub fn with_format(mut self, format: AmountFormat) -> BalanceSheet {
+        for row in self.sections.iter_mut().flat_map(|s| s.rows.iter_mut()) {
+            row.amount = row.amount.to_format(row.commodity_decimal, format);
+        }
+        for check in self.checks.iter_mut() {
+            for amount in [
+                &mut check.assets,
+                &mut check.liabilities,
+                &mut check.equity,
+                &mut check.net_income,
+            ] {
+                *amount = amount.to_format(check.commodity_decimal, format);
+            }
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomeStatementReport {
+    pub period: Period,
+    /// IANA timezone the periods start at midnight in
+    pub timezone: String,
+    /// periods of the time transfers were added to the ledger, the same dates as the balance sheet
+    /// and cash flow use
+    pub periods: Vec<ReportPeriod>,
+    /// revenues and expenses in that order
+    pub sections: Vec<IncomeStatementSection>,
+    /// revenues minus expenses of each commodity
+    pub net_income: Vec<NetIncome>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportPeriod {
+    /// unix time milliseconds, inclusive
+    pub start: i64,
+    /// unix time milliseconds, exclusive
+    pub end: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomeStatementSection {
+    pub account_type: models::AccountType,
+    /// accounts sorted by name with the totals of their sub accounts, the depth 1 rows are the
+    /// section totals
+    pub rows: Vec<IncomeStatementRow>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomeStatementRow {
+    pub account_name: String,
+    /// number of `:` separated parts of the account name
+    pub depth: usize,
+    /// change of each period, positive for credited revenues and debited expenses
+    pub amounts: Vec<Amount>,
+    /// sum of all periods
+    pub total: Amount,
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NetIncome {
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+    /// net income of each period
+    pub amounts: Vec<Amount>,
+    /// sum of all periods
+    pub total: Amount,
+}
+
+impl IncomeStatementReport {
+    pub fn with_format(mut self, format: AmountFormat) -> IncomeStatementReport {
+        let rows = self
+            .sections
+            .iter_mut()
+            .flat_map(|s| s.rows.iter_mut())
+            .map(|r| (r.commodity_decimal, &mut r.amounts, &mut r.total));
+        let net_income = self
+            .net_income
+            .iter_mut()
+            .map(|n| (n.commodity_decimal, &mut n.amounts, &mut n.total));
+        for (commodity_decimal, amounts, total) in rows.chain(net_income) {
+            for amount in amounts.iter_mut().chain([total]) {
+                *amount = amount.to_format(commodity_decimal, format);
+            }
+        }
+        self
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CashFlowActivity {
+    Operating,
+    Investing,
+    Financing,
+}
+
+/// Classifies counterpart accounts named `prefix` or below it.
+#[derive(Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CashFlowRule {
+    /// account name or parent account name, e.g. `a:investments`
+    #[validate(regex(path=*RE_ACCOUNT_PREFIX))]
+    pub prefix: String,
+    pub activity: CashFlowActivity,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CashFlow {
+    /// operating, investing and financing in that order
+    pub sections: Vec<CashFlowSection>,
+    /// net change of the cash accounts of each commodity
+    pub totals: Vec<CashFlowAmounts>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CashFlowSection {
+    pub activity: CashFlowActivity,
+    pub amounts: Vec<CashFlowAmounts>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CashFlowAmounts {
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+    /// amount debited to the cash accounts
+    pub inflow: Amount,
+    /// amount credited from the cash accounts
+    pub outflow: Amount,
+    /// inflow minus outflow
+    pub net: Amount,
+}
+
+impl CashFlow {
+    pub fn with_format(mut self, format: AmountFormat) -> CashFlow {
+        let sections = self.sections.iter_mut().flat_map(|s| s.amounts.iter_mut());
+        for a in sections.chain(self.totals.iter_mut()) {
+            for amount in [&mut a.inflow, &mut a.outflow, &mut a.net] {
+                *amount = amount.to_format(a.commodity_decimal, format);
+            }
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalance {
+    /// every account of the accounts table sorted by name
+    pub accounts: Vec<TrialBalanceAccount>,
+    /// totals of every tigerbeetle account of each ledger
+    pub ledgers: Vec<TrialBalanceLedger>,
+    /// accounts missing in either the accounts table or tigerbeetle
+    pub orphans: Vec<OrphanAccount>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalanceAccount {
+    pub account_name: String,
+    /// tigerbeetle account id in hexadecimal
+    pub tb_id: String,
+    /// posted debits
+    pub debits: Amount,
+    /// posted credits
+    pub credits: Amount,
     pub commodity_unit: String,
     pub commodity_decimal: i32,
 }
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalanceLedger {
+    /// tigerbeetle ledger id
+    pub ledger: u32,
+    /// empty when the ledger has no commodity
+    pub commodity_unit: Option<String>,
+    pub commodity_decimal: i32,
+    pub debits: Amount,
+    pub credits: Amount,
+    /// true when the debits equal the credits
+    pub balanced: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanKind {
+    /// account row without a tigerbeetle account
+    MissingInLedger,
+    /// tigerbeetle account without an account row
+    MissingInStore,
+    /// account row of another commodity than the ledger of its tigerbeetle account
+    LedgerMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanAccount {
+    pub kind: OrphanKind,
+    /// tigerbeetle account id in hexadecimal
+    pub tb_id: String,
+    /// empty for tigerbeetle accounts without an account row
+    pub account_name: Option<String>,
+    /// tigerbeetle ledger id, empty for account rows without a tigerbeetle account
+    pub ledger: Option<u32>,
+}
+
+impl TrialBalance {
+    pub fn with_format(mut self, format: AmountFormat) -> TrialBalance {
+        for a in self.accounts.iter_mut() {
+            a.debits = a.debits.to_format(a.commodity_decimal, format);
+            a.credits = a.credits.to_format(a.commodity_decimal, format);
+        }
+        for l in self.ledgers.iter_mut() {
+            l.debits = l.debits.to_format(l.commodity_decimal, format);
+            l.credits = l.credits.to_format(l.commodity_decimal, format);
+        }
+        self
+    }
+}
+
+/// Posting of a transfer to one account with the balance of the account after it.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterPosting {
+    /// account name
+    pub account_name: String,
+    /// account name of the other side of the transfer
+    pub counterpart_account: String,
+    /// posted amount, debits are positive and credits negative, zero for pending and voided
+    /// transfers so that each balance is the previous balance plus the amount
+    pub amount: Amount,
+    /// posted debits minus credits of the account after this posting
+    pub balance: Amount,
+    /// unix time milliseconds
+    pub full_date: i64,
+    /// unix time milliseconds
+    pub full_date2: i64,
+    /// transaction code
+    pub code: i32,
+    /// random hex u128 string
+    pub related_id: String,
+    /// random hex u128 string
+    pub transfer_id: String,
+    /// two-phase state of the transfer, only posted transfers change the balance
+    pub status: TransferStatus,
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+}
+
+impl RegisterPosting {
+    pub fn with_format(mut self, format: AmountFormat) -> RegisterPosting {
+        self.amount = self.amount.to_format(self.commodity_decimal, format);
+        self.balance = self.balance.to_format(self.commodity_decimal, format);
+        self
+    }
+}
+
+pub const FX_GAIN_ACCOUNT: &str = "r:fx:unrealized";
+pub const FX_LOSS_ACCOUNT: &str = "x:fx:unrealized";
+
+#[derive(Default, Debug, Validate, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Revalue {
+    /// unix time milliseconds of the end of the period, transactions entered in the ledger up to
+    /// this date are revalued at their ledger timestamp like the balance sheet `as_of`, dates
+    /// before the last revalued period are rejected
+    pub date: i64,
+    /// commodity the asset and liability accounts are valued in
+    #[validate(length(min = 1))]
+    pub reporting_unit: String,
+    /// transaction code of the adjusting entries
+    pub code: i32,
+    /// prices used next to the stored prices, they take precedence on the same date
+    #[serde(default)]
+    #[validate(nested)]
+    pub prices: Vec<Price>,
+    /// account name credited with unrealized gains, defaults to `r:fx:unrealized`
+    #[validate(regex(path=*RE_ACCOUNT))]
+    pub gain_account: Option<String>,
+    /// account name debited with unrealized losses, defaults to `x:fx:unrealized`
+    #[validate(regex(path=*RE_ACCOUNT))]
+    pub loss_account: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Revaluation {
+    pub date: i64,
+    pub reporting_unit: String,
+    pub reporting_decimal: i32,
+    /// revaluation of each foreign commodity held by asset and liability accounts
+    pub commodities: Vec<RevaluedCommodity>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RevaluedCommodity {
+    pub commodity_unit: String,
+    pub commodity_decimal: i32,
+    /// sum of the asset and liability balances in the commodity
+    pub balance: Amount,
+    /// sum of each transaction valued at the price of its date, in the reporting commodity
+    pub historical_cost: Amount,
+    /// balance valued at the price of the revaluation date, in the reporting commodity
+    pub value: Amount,
+    /// value minus historical cost, in the reporting commodity
+    pub unrealized: Amount,
+    /// unrealized amount not booked by earlier revaluations, positive is a gain
+    pub adjustment: Amount,
+    /// hex u128 transfer id of the adjusting entry of this period, empty when nothing is booked
+    pub transfer_id: Option<String>,
+}
+
+impl Revaluation {
+    pub fn with_format(mut self, format: AmountFormat) -> Revaluation {
+        for c in self.commodities.iter_mut() {
+            c.balance = c.balance.to_format(c.commodity_decimal, format);
+            for amount in [
+                &mut c.historical_cost,
+                &mut c.value,
+                &mut c.unrealized,
+                &mut c.adjustment,
+            ] {
+                *amount = amount.to_format(self.reporting_decimal, format);
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_decimal, parse_decimal_rounded, AddTransactions, Amount, AmountFormat, CsvRowError,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn parse_from_csv() {
+        let body = "\
+debit_account;credit_account;amount;commodity_unit;code;full_date2;related_id;transfer_id
+a:bank;r:salary;1.00;\"EUR;cash\";1;1743633624679;1f;
+a:bank;r:salary;10;\"\"\"quoted\"\"\";2;1743633624679;1f;195fb004cbf19e9d857488e47bb0af1
+";
+        let decimal_places = HashMap::from([(String::from("EUR;cash"), 2)]);
+        let add_transactions_arr =
+            AddTransactions::parse_from_csv(body, b';', &decimal_places, AmountFormat::Decimal)
+                .unwrap();
+        assert_eq!(add_transactions_arr.len(), 2);
+        let t = &add_transactions_arr[0].1.transactions[0];
+        assert_eq!(t.commodity_unit, "EUR;cash");
+        assert_eq!(t.debit_account, "a:bank");
+        assert_eq!(t.amount, Amount::Integer(100));
+        assert_eq!(t.transfer_id, None);
+        let t = &add_transactions_arr[1].1.transactions[0];
+        assert_eq!(t.commodity_unit, "\"quoted\"");
+        assert_eq!(
+            t.transfer_id,
+            Some(String::from("195fb004cbf19e9d857488e47bb0af1"))
+        );
+        assert_eq!(add_transactions_arr[1].1.full_date2, 1743633624679);
+    }
+
+    #[test]
+    fn parse_from_csv_amount_format() {
+        let body = "\
+commodity_unit,commodity_decimal,code,full_date2,related_id,debit_account,credit_account,amount
+EUR,2,1,1743633624679,1f,a:bank,r:salary,100
+EUR,,1,1743633624679,1f,a:bank,r:salary,1.50
+EUR,0,1,1743633624679,1f,a:bank,r:salary,100
+";
+        let decimal_places = HashMap::from([(String::from("EUR"), 2)]);
+        let errors =
+            AddTransactions::parse_from_csv(body, b',', &decimal_places, AmountFormat::Integer)
+                .unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.row).collect::<Vec<usize>>(),
+            vec![3, 4]
+        );
+        assert_eq!(
+            errors[1].message,
+            "commodity_decimal 0 differs from the 2 decimal places of commodity EUR"
+        );
+
+        let rows = AddTransactions::parse_from_csv(
+            &body.replace("EUR,0,", "EUR,2,"),
+            b',',
+            &decimal_places,
+            AmountFormat::Decimal,
+        )
+        .unwrap();
+        assert_eq!(
+            rows.iter()
+                .map(|(_, t)| t.transactions[0].amount.clone())
+                .collect::<Vec<Amount>>(),
+            vec![
+                Amount::Integer(10000),
+                Amount::Integer(150),
+                Amount::Integer(10000)
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_decimals() {
+        assert_eq!(parse_decimal("12.34", 2), Ok(1234));
+        assert_eq!(parse_decimal("12.3", 2), Ok(1230));
+        assert_eq!(parse_decimal("-12", 2), Ok(-1200));
+        assert_eq!(parse_decimal("12", 0), Ok(12));
+        assert_eq!(
+            parse_decimal("1.234", 2),
+            Err(String::from("amount 1.234 has more than 2 decimal places"))
+        );
+        assert!(parse_decimal("1.", 2).is_err());
+        assert!(parse_decimal(".5", 2).is_err());
+        assert!(parse_decimal("1e3", 2).is_err());
+    }
+
+    #[test]
+    fn parse_decimals_rounded() {
+        assert_eq!(parse_decimal_rounded("1.5", 0, true), Ok(2));
+        assert_eq!(parse_decimal_rounded("1.5", 0, false), Ok(1));
+        assert_eq!(parse_decimal_rounded("1.234", 2, true), Ok(124));
+        assert_eq!(parse_decimal_rounded("1.230", 2, true), Ok(123));
+        assert_eq!(parse_decimal_rounded("-0.5", 0, false), Ok(-1));
+        assert_eq!(parse_decimal_rounded("-0.5", 0, true), Ok(0));
+        assert_eq!(parse_decimal_rounded("1.5", 2, true), Ok(150));
+        assert!(parse_decimal_rounded("1.5x", 0, true).is_err());
+    }
+
+    #[test]
+    fn parse_from_csv_errors() {
+        let body = "\
+commodity_unit,code,full_date2,related_id,debit_account,credit_account,debit_amount
+EUR,x,1743633624679,1f,a:bank,r:salary,100
+EUR,1,yesterday,1f,a:bank,r:salary,100
+EUR,1,1743633624679,\"1f
+2f\",a:bank,r:salary,100
+EUR,1,1743633624679,1f,bank,r:salary,100
+EUR,1,1743633624679,1f,a:bank,r:salary,100
+";
+        let errors =
+            AddTransactions::parse_from_csv(body, b',', &HashMap::new(), AmountFormat::Integer)
+                .unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.row).collect::<Vec<usize>>(),
+            vec![2, 3, 6]
+        );
+        assert_eq!(errors[0].message, "invalid code x");
+        assert_eq!(errors[1].message, "invalid full_date2 yesterday");
+
+        assert_eq!(
+            AddTransactions::parse_from_csv(
+                "commodity_unit,code\nEUR,1\n",
+                b',',
+                &HashMap::new(),
+                AmountFormat::Integer
+            )
+            .unwrap_err(),
+            vec![CsvRowError {
+                row: 1,
+                message: String::from(
+                    "missing columns full_date2, related_id, debit_account, credit_account, amount"
+                ),
+            }]
+        );
+    }
+}
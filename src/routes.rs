@@ -1,47 +1,46 @@
 use anyhow::anyhow;
 use axum::body::Body;
-use axum::http::StatusCode;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::Response;
-use axum::{extract::State, Json};
 use axum_macros::debug_handler;
 use itertools::Itertools as _;
 use serde::Deserialize;
-use std::collections::HashMap;
-use std::ops::Sub;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tigerbeetle_unofficial as tb;
-use utoipa::{OpenApi, ToSchema};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use validator::Validate;
 use validator::ValidationError;
 
-use crate::http_err::HttpResult;
+use crate::http_err::{HttpResult, Json};
 use crate::models::Account;
-use crate::models::{find_accounts_re, TB_MAX_BATCH_SIZE};
-use crate::models::{list_all_commodities, list_all_commodity_units};
+use crate::models::TB_MAX_BATCH_SIZE;
 use crate::responses::RE_ACCOUNTS_GLOB;
+use crate::store::Store;
 use crate::tb_utils::u128::{from_hex_string, to_hex_string};
-use crate::{http_err, models, responses, tb_utils, ApiDoc, AppState};
+use crate::{
+    beancount, hledger, http_err, models, prices, reports, responses, tb_utils, ApiDoc, AppState,
+};
 
 // #[debug_handler]
 #[utoipa::path(put, path = "/mutate/migrate", responses(
     (status = 200, description = "Returns status 200 when migration is complete"),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
 ))]
 pub async fn mutate_migrate(
     State(state): State<AppState>,
     Json(body): Json<responses::RequestMigrate>,
 ) -> http_err::HttpResult<Json<()>> {
     if !state.allow_add {
-        return Err(http_err::bad_error(std::io::Error::other(
-            "writing to ledger is disabled",
-        )));
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
     }
 
     body.validate().map_err(http_err::bad_error)?;
 
-    let conn = state.pool.get().await.map_err(http_err::internal_error)?;
-    let commodity_insert_res = models::insert_commodities(&conn, body.commodities).await?;
+    let commodity_insert_res = state.store.insert_commodities(body.commodities).await?;
 
     let new_accounts: http_err::HttpResult<Vec<Account>> = body
         .accounts
@@ -63,695 +62,4010 @@ pub async fn mutate_migrate(
         .collect();
     let new_accounts = new_accounts?;
 
-    models::insert_accounts(&conn, new_accounts).await?;
+    state.store.insert_accounts(new_accounts).await?;
 
     Ok(Json(()))
 }
 
 #[utoipa::path(post, path = "/query/account-names-all", responses(
     (status = 200, description = "Returns list of transaction ids", body = responses::Vec<String>),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
 ))]
 pub async fn query_account_names_all(
     State(state): State<AppState>,
 ) -> http_err::HttpResult<Json<responses::ResponseAccountNames>> {
-    let conn = state.pool.get().await.map_err(http_err::internal_error)?;
-
-    let accounts = models::list_all_accounts(&conn).await?;
+    let accounts = state.store.list_all_accounts().await?;
 
     Ok(Json(accounts))
 }
 
+/// Header containing a client generated key, hashed into the transfer ids of a request so that
+/// retrying the request does not add the transactions twice.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+#[derive(Default, Deserialize, IntoParams)]
+pub struct AmountParams {
+    /// `decimal` sends and returns amounts as decimal strings scaled by the decimal place of the
+    /// commodity, defaults to integers in the smallest unit of the commodity
+    #[serde(default)]
+    amount_format: responses::AmountFormat,
+}
+
+/// Converts the amounts of a request to integers in the smallest unit of their commodity.
+async fn amounts_to_integer<'a>(
+    state: &AppState,
+    format: responses::AmountFormat,
+    amounts: impl IntoIterator<Item = (&'a str, &'a mut responses::Amount)>,
+) -> http_err::HttpResult<()> {
+    let decimal_places = match format {
+        responses::AmountFormat::Decimal => commodity_decimal_places(state).await?,
+        responses::AmountFormat::Integer => HashMap::new(),
+    };
+    for (unit, amount) in amounts {
+        let decimal_place = decimal_places.get(unit).copied().unwrap_or(0);
+        *amount = amount
+            .to_integer(decimal_place, format)
+            .map_err(http_err::bad_error)?
+            .into();
+    }
+    Ok(())
+}
+
 // #[debug_handler]
-#[utoipa::path(put, path = "/mutate/add", responses(
+#[utoipa::path(put, path = "/mutate/add", params(
+    AmountParams,
+    ("Idempotency-Key" = Option<String>, Header, description = "Client generated key, resubmitting it returns the transaction ids of the first submission"),
+), responses(
     (status = 200, description = "Returns list of transaction ids", body = responses::ResponseAdd),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
 ))]
 pub async fn mutate_add(
     State(state): State<AppState>,
-    Json(body): Json<responses::RequestAdd>,
+    headers: HeaderMap,
+    Query(params): Query<AmountParams>,
+    Json(mut body): Json<responses::RequestAdd>,
 ) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
     if !state.allow_migrate {
-        return Err(http_err::bad_error(std::io::Error::other(
-            "migrating to ledger is disabled",
-        )));
+        return Err(http_err::disabled_error("migrating to ledger is disabled"));
     }
 
     body.validate().map_err(http_err::bad_error)?;
+    amounts_to_integer(
+        &state,
+        params.amount_format,
+        body.transactions
+            .iter_mut()
+            .map(|t| (t.commodity_unit.as_str(), &mut t.amount)),
+    )
+    .await?;
+
+    let idempotency_key = idempotency_key(&headers, "/mutate/add")?;
+
+    let tranfers = build_transfers(
+        &state,
+        body.full_date2,
+        &body.transactions,
+        idempotency_key,
+        tb::transfer::Flags::empty(),
+        0,
+    )
+    .await?;
+    let transfer_ids = tranfers.iter().map(|t| to_hex_string(t.id())).collect();
+
+    create_transfers(&state, tranfers).await?;
+
+    Ok(Json(transfer_ids))
+}
+
+/// Idempotency key of a request and the route it was sent to.
+#[derive(Clone, Copy)]
+struct IdempotencyKey<'a> {
+    route: &'static str,
+    key: &'a str,
+}
+
+impl IdempotencyKey<'_> {
+    /// Transfer id of the transaction at `index` of the request.
+    fn transfer_id(&self, index: usize) -> u128 {
+        tb_utils::u128::from_idempotency_key(self.route, self.key, index)
+    }
+}
+
+fn idempotency_key<'a>(
+    headers: &'a HeaderMap,
+    route: &'static str,
+) -> http_err::HttpResult<Option<IdempotencyKey<'a>>> {
+    headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|v| {
+            v.to_str()
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(|key| IdempotencyKey { route, key })
+                .ok_or(http_err::bad_error(anyhow!("invalid idempotency key")))
+        })
+        .transpose()
+}
+
+/// Creates the transfers, when every transfer already exists the transfers are left as is.
+async fn create_transfers(
+    state: &AppState,
+    tranfers: Vec<tb::Transfer>,
+) -> http_err::HttpResult<()> {
+    let err = match state.ledger.create_transfers(tranfers.clone()).await {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+    if let tb::error::CreateTransfersError::Api(api_err) = &err {
+        if tb_utils::is_create_transfers_exists(api_err) {
+            // linked transfers are rolled back together, make sure none of them are new
+            let transfer_ids = tranfers.iter().map(|t| t.id()).collect::<Vec<u128>>();
+            let found = state
+                .ledger
+                .lookup_transfers(transfer_ids.clone())
+                .await
+                .map_err(http_err::storage_error)?;
+            if found.len() == transfer_ids.len() {
+                return Ok(());
+            }
+        }
+    }
+    Err(create_transfers_error(state, &tranfers, &err).await)
+}
+
+/// Builds the error listing every rejected transfer with the names of its accounts.
+async fn create_transfers_error(
+    state: &AppState,
+    tranfers: &[tb::Transfer],
+    err: &tb::error::CreateTransfersError,
+) -> http_err::HttpErr {
+    let failed = match err {
+        tb::error::CreateTransfersError::Api(api_err) => api_err
+            .as_slice()
+            .iter()
+            .filter_map(|e| {
+                tranfers.get(e.index() as usize).map(|t| {
+                    (
+                        e.index(),
+                        t,
+                        tb_utils::create_transfer_error_kind_name(e.kind()),
+                    )
+                })
+            })
+            .collect::<Vec<_>>(),
+        _ => Vec::new(),
+    };
+
+    // account names only decorate the error, the ledger error is returned regardless
+    let tb_ids = failed
+        .iter()
+        .flat_map(|(_, t, _)| [t.debit_account_id(), t.credit_account_id()])
+        .unique()
+        .map(to_hex_string)
+        .collect::<Vec<String>>();
+    let accounts = state
+        .store
+        .find_accounts_by_tb_ids(tb_ids)
+        .await
+        .unwrap_or_default();
+    let account_name = |tb_id: u128| {
+        let tb_id = to_hex_string(tb_id);
+        accounts
+            .iter()
+            .find(|a| a.tb_id == tb_id)
+            .map(|a| a.name.clone())
+            .unwrap_or_default()
+    };
 
-    let conn = state.pool.get().await.map_err(http_err::internal_error)?;
+    let errors = failed
+        .iter()
+        .map(|(index, t, kind)| responses::TransferError {
+            index: *index,
+            transfer_id: to_hex_string(t.id()),
+            debit_account: account_name(t.debit_account_id()),
+            credit_account: account_name(t.credit_account_id()),
+            kind: kind.to_string(),
+        })
+        .collect();
+    http_err::transfers_error(err, errors)
+}
 
+/// Finds or creates the accounts of each transaction and builds linked transfers from them.
+async fn build_transfers(
+    state: &AppState,
+    full_date2: i64,
+    transactions: &[responses::AddTransaction],
+    idempotency_key: Option<IdempotencyKey<'_>>,
+    flags: tb::transfer::Flags,
+    timeout: u32,
+) -> http_err::HttpResult<Vec<tb::Transfer>> {
+    // ids and amounts are validated before any account is created
     let mut tranfers: Vec<tb::Transfer> = Vec::new();
-    let mut transfer_ids: Vec<String> = Vec::new();
-    for (index, t) in body.transactions.iter().enumerate() {
+    for (index, t) in transactions.iter().enumerate() {
+        let id = match (&t.transfer_id, idempotency_key) {
+            (Some(transfer_id), _) => tb_utils::u128::try_from_hex_string(transfer_id)
+                .map_err(|_| http_err::bad_error(anyhow!("invalid transfer id {}", transfer_id)))?,
+            (None, Some(key)) => key.transfer_id(index),
+            (None, None) => tb::id(),
+        };
+        let user_data_128 = tb_utils::u128::try_from_hex_string(&t.related_id)
+            .map_err(|_| http_err::bad_error(anyhow!("invalid related id {}", t.related_id)))?;
+        let user_data_64 = full_date2 as u64;
+        let user_data_32 = match flags.contains(tb::transfer::Flags::PENDING) {
+            true => tb_utils::USER_DATA_32_PENDING,
+            false => 0,
+        };
+
+        tranfers.push(
+            tb::Transfer::new(id)
+                .with_amount(t.amount.to_u128().map_err(http_err::bad_error)?)
+                .with_code(t.code as u16)
+                .with_user_data_128(user_data_128)
+                .with_user_data_64(user_data_64)
+                .with_user_data_32(user_data_32)
+                .with_flags(flags)
+                .with_timeout(timeout),
+        );
+    }
+
+    for (tranfer, t) in tranfers.iter_mut().zip(transactions) {
         let (account_debit, commodity) = models::find_or_create_account(
-            state.tb.clone(),
-            &conn,
+            state.ledger.as_ref(),
+            state.store.as_ref(),
             t.debit_account.clone(),
             t.commodity_unit.clone(),
         )
         .await?;
 
         let (account_credit, _) = models::find_or_create_account(
-            state.tb.clone(),
-            &conn,
+            state.ledger.as_ref(),
+            state.store.as_ref(),
             t.credit_account.clone(),
             t.commodity_unit.clone(),
         )
         .await?;
 
-        let user_data_128 = tb_utils::u128::from_hex_string(&t.related_id);
-        let user_data_64 = body.full_date2 as u64;
-
-        let id = tb::id();
-        transfer_ids.push(to_hex_string(id));
+        tranfer.set_debit_account_id(from_hex_string(account_debit.tb_id.as_str()));
+        tranfer.set_credit_account_id(from_hex_string(account_credit.tb_id.as_str()));
+        tranfer.set_ledger(commodity.id as u32);
+    }
+    link_transfers(&mut tranfers);
 
-        let mut tranfer = tb::Transfer::new(id)
-            .with_amount(t.amount as u128)
-            .with_code(t.code as u16)
-            .with_debit_account_id(from_hex_string(account_debit.tb_id.as_str()))
-            .with_credit_account_id(from_hex_string(account_credit.tb_id.as_str()))
-            .with_user_data_128(user_data_128)
-            .with_user_data_64(user_data_64)
-            .with_ledger(commodity.id as u32);
+    Ok(tranfers)
+}
 
-        // forces all transfers to be a linked
-        // see: https://docs.tigerbeetle.com/coding/linked-events/
-        if body.transactions.len() > 1 && index != body.transactions.len() - 1 {
-            tranfer = tranfer.with_flags(tb::transfer::Flags::LINKED)
+/// Forces all transfers to be linked so that they succeed or fail together.
+///
+/// see: https://docs.tigerbeetle.com/coding/linked-events/
+fn link_transfers(tranfers: &mut [tb::Transfer]) {
+    if let Some((_, chain)) = tranfers.split_last_mut() {
+        for tranfer in chain.iter_mut() {
+            tranfer.set_flags(tranfer.flags() | tb::transfer::Flags::LINKED);
         }
-        tranfers.push(tranfer);
     }
-
-    state.tb.create_transfers(tranfers).await.map_err(
-        |e: tb::core::error::CreateTransfersError| {
-            http_err::internal_error(format!(
-                "error on adding transfers to tigerbeetle: {}",
-                tb_utils::create_transfers_error_name(e)
-            ))
-        },
-    )?;
-
-    Ok(Json(transfer_ids))
 }
 
 // #[debug_handler]
-#[utoipa::path(post, path = "/query/export-hledger", responses(
-    (status = 200, description = "Returns hledger export", body=String),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+#[utoipa::path(put, path = "/mutate/exchange", params(
+    AmountParams,
+    ("Idempotency-Key" = Option<String>, Header, description = "Client generated key, resubmitting it returns the transaction ids of the first submission"),
+), responses(
+    (status = 200, description = "Returns the transaction ids of the sold and bought commodity, each balanced by the conversion account of its commodity", body = responses::ResponseAdd),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
 ))]
-pub async fn query_export_hledger(
-    state: State<AppState>,
-    json: Json<QueryTransactionsBody>,
-) -> Result<String, http_err::HttpErr> {
-    println!("testing");
-    let res_json = query_account_transactions(state, json).await?;
+pub async fn mutate_exchange(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<responses::RequestExchange>,
+) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
+    if !state.allow_add {
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
+    }
 
-    let res_hledger_arr = res_json
-        .iter()
-        .map(|item| item.to_hledger_string())
-        .collect::<Result<Vec<String>, ValidationError>>()
-        .map_err(http_err::internal_error)?;
-    let res_hledger = res_hledger_arr.iter().join("\n");
-    Ok(res_hledger)
+    body.validate().map_err(http_err::bad_error)?;
+    let mut transactions = body.transactions();
+    amounts_to_integer(
+        &state,
+        params.amount_format,
+        transactions
+            .iter_mut()
+            .map(|t| (t.commodity_unit.as_str(), &mut t.amount)),
+    )
+    .await?;
+
+    let idempotency_key = idempotency_key(&headers, "/mutate/exchange")?;
+
+    // both legs are linked so the exchange is added atomically
+    let tranfers = build_transfers(
+        &state,
+        body.full_date2,
+        &transactions,
+        idempotency_key,
+        tb::transfer::Flags::empty(),
+        0,
+    )
+    .await?;
+    let transfer_ids = tranfers.iter().map(|t| to_hex_string(t.id())).collect();
+
+    create_transfers(&state, tranfers).await?;
+
+    Ok(Json(transfer_ids))
 }
 
 // #[debug_handler]
-#[utoipa::path(post, path = "/query/export-csv", responses(
-    (status = 200, description = "Returns csv export", body=String),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+#[utoipa::path(put, path = "/mutate/pending", params(
+    AmountParams,
+    ("Idempotency-Key" = Option<String>, Header, description = "Client generated key, resubmitting it returns the transaction ids of the first submission"),
+), responses(
+    (status = 200, description = "Returns list of pending transaction ids", body = responses::ResponseAdd),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
 ))]
-pub async fn query_export_csv(
-    state: State<AppState>,
-    json: Json<QueryTransactionsBody>,
-) -> Result<String, http_err::HttpErr> {
-    println!("testing");
-    let res_json = query_account_transactions(state, json).await?;
+pub async fn mutate_add_pending(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<AmountParams>,
+    Json(mut body): Json<responses::RequestAddPending>,
+) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
+    if !state.allow_add {
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
+    }
 
-    let res_hledger_arr = res_json
-        .iter()
-        .map(|item| item.to_csv())
-        .collect::<Result<Vec<String>, ValidationError>>()
-        .map_err(http_err::internal_error)?;
-    let res_hledger = res_hledger_arr.iter().join("\n");
-    let mut res_header = String::from(responses::Transaction::csv_header());
-    res_header.push_str("\n");
-    res_header.push_str(res_hledger.as_str());
-    Ok(res_header)
+    body.validate().map_err(http_err::bad_error)?;
+    amounts_to_integer(
+        &state,
+        params.amount_format,
+        body.transactions
+            .iter_mut()
+            .map(|t| (t.commodity_unit.as_str(), &mut t.amount)),
+    )
+    .await?;
+
+    let idempotency_key = idempotency_key(&headers, "/mutate/pending")?;
+
+    let tranfers = build_transfers(
+        &state,
+        body.full_date2,
+        &body.transactions,
+        idempotency_key,
+        tb::transfer::Flags::PENDING,
+        body.timeout,
+    )
+    .await?;
+    let transfer_ids = tranfers.iter().map(|t| to_hex_string(t.id())).collect();
+
+    create_transfers(&state, tranfers).await?;
+
+    Ok(Json(transfer_ids))
 }
 
-#[debug_handler]
-#[utoipa::path(put, path = "/mutate/import-csv", responses(
-    (status = 200, description = "Returns status 200 when import is complete"),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+// #[debug_handler]
+#[utoipa::path(put, path = "/mutate/post", params(AmountParams), responses(
+    (status = 200, description = "Returns list of transaction ids posting the pending transactions, a retry returns the same ids", body = responses::ResponseAdd),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 404, description = "Pending transaction not found", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
 ))]
-pub async fn mutate_import_csv(
-    state: State<AppState>,
-    body: String,
-) -> Result<String, http_err::HttpErr> {
-    if !state.allow_migrate {
-        return Err(http_err::bad_error(std::io::Error::other(
-            "migrating to ledger is disabled",
-        )));
+pub async fn mutate_post_pending(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<responses::RequestPostPending>,
+) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
+    if !state.allow_add {
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
     }
-    let add_transactions_arr = body
-        .split("\n")
+
+    body.validate().map_err(http_err::bad_error)?;
+
+    let pending_transfers = lookup_pending_transfers(
+        &state,
+        body.transactions.iter().map(|t| t.pending_id.as_str()),
+    )
+    .await?;
+    // the ledger of a transfer is the id of its commodity
+    let decimal_places = state
+        .store
+        .list_all_commodities()
+        .await?
         .into_iter()
-        .skip(1)
-        .map(|line| responses::AddTransactions::parse_from_csv_line(String::from(line)))
-        .collect::<Result<Vec<responses::AddTransactions>, ValidationError>>()
-        .map_err(http_err::bad_error)?;
+        .map(|c| (c.id as u32, c.decimal_place))
+        .collect::<HashMap<u32, i32>>();
 
-    for add_transactions in add_transactions_arr.iter() {
-        let _ = mutate_add(state.clone(), Json(add_transactions.to_owned())).await?;
+    let mut tranfers = body
+        .transactions
+        .iter()
+        .zip(pending_transfers.iter())
+        .map(|(t, pending)| {
+            let decimal_place = decimal_places.get(&pending.ledger()).copied().unwrap_or(0);
+            let amount = match &t.amount {
+                Some(amount) => responses::Amount::Integer(
+                    amount
+                        .to_integer(decimal_place, params.amount_format)
+                        .map_err(http_err::bad_error)?,
+                )
+                .to_u128()
+                .map_err(http_err::bad_error)?,
+                None => pending.amount(),
+            };
+            if amount > pending.amount() {
+                return Err(http_err::bad_error(anyhow!(
+                    "amount exceeds pending transaction {}",
+                    t.pending_id
+                )));
+            }
+            Ok(resolve_pending_transfer(
+                pending,
+                amount,
+                tb::transfer::Flags::POST_PENDING_TRANSFER,
+            ))
+        })
+        .collect::<http_err::HttpResult<Vec<tb::Transfer>>>()?;
+
+    // the ledger accepts a post that already exists regardless of its amount
+    let posted = state
+        .ledger
+        .lookup_transfers(tranfers.iter().map(|t| t.id()).collect())
+        .await
+        .map_err(http_err::storage_error)?;
+    if let Some(t) = tranfers.iter().find(|t| {
+        posted
+            .iter()
+            .any(|p| p.id() == t.id() && p.amount() != t.amount())
+    }) {
+        return Err(http_err::conflict_error(anyhow!(
+            "pending transaction {} is already posted with another amount",
+            to_hex_string(t.pending_id())
+        )));
     }
 
-    Ok(String::new())
+    link_transfers(&mut tranfers);
+    let transfer_ids = tranfers.iter().map(|t| to_hex_string(t.id())).collect();
+
+    create_transfers(&state, tranfers).await?;
+
+    Ok(Json(transfer_ids))
 }
 
-#[utoipa::path(post, path = "/query/prepare-add", responses(
-    (status = 200, description = "Returns a prepared add payload to be run with the route PUT /app", body=responses::RequestAdd),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+// #[debug_handler]
+#[utoipa::path(put, path = "/mutate/void", responses(
+    (status = 200, description = "Returns list of transaction ids voiding the pending transactions, a retry returns the same ids", body = responses::ResponseAdd),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 404, description = "Pending transaction not found", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
 ))]
-pub async fn query_prepare_add_fcfs(
+pub async fn mutate_void_pending(
     State(state): State<AppState>,
-    Json(body): Json<responses::RequestAddPrepareGlob>,
-) -> http_err::HttpResult<Json<responses::ResponseAddPrepare>> {
+    Json(body): Json<responses::RequestVoidPending>,
+) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
     if !state.allow_add {
-        return Err(http_err::bad_error(std::io::Error::other(
-            "writing to ledger is disabled",
-        )));
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
     }
 
     body.validate().map_err(http_err::bad_error)?;
 
-    let conn = state.pool.get().await.map_err(http_err::internal_error)?;
+    let pending_transfers =
+        lookup_pending_transfers(&state, body.pending_ids.iter().map(String::as_str)).await?;
 
-    let mut add_transactions: Vec<responses::AddTransaction> = Vec::new();
-    // map of key: account_tb_id value: balance
-    let mut tb_account_balances: HashMap<String, i64> = HashMap::new();
-    for t in body.filter_transactions.iter() {
-        let mut remaining_amount = t.amount;
-        'loop_credit_accounts_filter_item: for credit_accounts_filter_item in
-            t.credit_accounts_filter.iter()
-        {
-            let credit_accounts = models::find_accounts_re_by_commodity(
-                &conn,
-                credit_accounts_filter_item.clone(),
-                t.commodity_unit.clone(),
+    let mut tranfers = pending_transfers
+        .iter()
+        .map(|pending| {
+            resolve_pending_transfer(
+                pending,
+                pending.amount(),
+                tb::transfer::Flags::VOID_PENDING_TRANSFER,
             )
-            .await?;
+        })
+        .collect::<Vec<_>>();
+    link_transfers(&mut tranfers);
+    let transfer_ids = tranfers.iter().map(|t| to_hex_string(t.id())).collect();
 
-            // println!("credit accounts {}", credit_accounts.len());
+    create_transfers(&state, tranfers).await?;
 
-            // get account balances where not already retrieved
-            let missing_tb_account_ids: Vec<u128> = credit_accounts
+    Ok(Json(transfer_ids))
+}
+
+// #[debug_handler]
+#[utoipa::path(put, path = "/mutate/reverse", responses(
+    (status = 200, description = "Returns list of transaction ids reversing the given transactions", body = responses::ResponseAdd),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 404, description = "Transaction not found", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
+))]
+pub async fn mutate_reverse(
+    State(state): State<AppState>,
+    Json(body): Json<responses::RequestReverse>,
+) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
+    if !state.allow_add {
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
+    }
+
+    body.validate().map_err(http_err::bad_error)?;
+
+    let transfer_ids = body
+        .transfer_ids
+        .iter()
+        .map(|id| {
+            tb_utils::u128::try_from_hex_string(id)
+                .map_err(|_| http_err::bad_error(anyhow!("invalid transfer id {}", id)))
+        })
+        .collect::<http_err::HttpResult<Vec<u128>>>()?;
+    if !transfer_ids.iter().all_unique() {
+        return Err(http_err::bad_error(anyhow!(
+            "a transaction can only be reversed once"
+        )));
+    }
+
+    // reversals already booked are found with the originals, the ledger rejects the ones booked
+    // concurrently as existing
+    let found = state
+        .ledger
+        .lookup_transfers(
+            transfer_ids
                 .iter()
-                .map(|a| &a.tb_id)
-                .filter(|a| !tb_account_balances.contains_key(*a))
-                .map(|s| tb_utils::u128::from_hex_string(s.as_str()))
-                .collect();
-            let tb_accounts: Vec<tb::core::account::Account> = state
-                .tb
-                .lookup_accounts(missing_tb_account_ids)
-                .await
-                .map_err(http_err::internal_error)?;
-            for a in tb_accounts.iter() {
-                tb_account_balances.insert(
-                    tb_utils::u128::to_hex_string(a.id()),
-                    a.debits_posted() as i64 - a.credits_posted() as i64,
-                );
-            }
+                .flat_map(|id| [*id, tb_utils::u128::to_reversal_id(*id)])
+                .collect(),
+        )
+        .await
+        .map_err(http_err::storage_error)?;
 
-            // remove from account balance and until remaining amount is zero & add a transaction
-            for account in credit_accounts.iter() {
-                if let Some(tb_account_balance) = tb_account_balances.get_mut(&account.tb_id) {
-                    if *tb_account_balance > 0 {
-                        let add_transaction_amount = if remaining_amount < *tb_account_balance {
-                            *tb_account_balance -= remaining_amount.clone();
-                            let old_remaining_amount = remaining_amount.clone();
-                            remaining_amount = 0;
-                            old_remaining_amount
-                        } else {
-                            let old_tb_account_balance = tb_account_balance.clone();
-                            remaining_amount -= old_tb_account_balance;
-                            *tb_account_balance = 0;
-                            old_tb_account_balance
-                        };
-                        add_transactions.push(responses::AddTransaction {
-                            commodity_unit: t.commodity_unit.clone(),
-                            code: t.code,
-                            related_id: t.related_id.clone(),
-                            debit_account: t.debit_account.clone(),
-                            credit_account: account.name.clone(),
-                            amount: add_transaction_amount,
-                        });
-                    }
-                }
-                if remaining_amount <= 0 {
-                    break 'loop_credit_accounts_filter_item;
-                }
-            }
-        }
-        if remaining_amount > 0 {
+    let mut tranfers: Vec<tb::Transfer> = Vec::new();
+    for transfer_id in transfer_ids.iter() {
+        let original =
+            found
+                .iter()
+                .find(|t| t.id() == *transfer_id)
+                .ok_or(http_err::not_found_error(anyhow!(
+                    "transaction {} not found",
+                    to_hex_string(*transfer_id)
+                )))?;
+        if original
+            .flags()
+            .intersects(tb::transfer::Flags::PENDING | tb::transfer::Flags::VOID_PENDING_TRANSFER)
+        {
             return Err(http_err::bad_error(anyhow!(
-                "not enough inside credit accounts to build a transaction"
+                "transaction {} is pending or voided, void the pending transaction instead",
+                to_hex_string(*transfer_id)
+            )));
+        }
+        let reversal_id = tb_utils::u128::to_reversal_id(original.id());
+        if found.iter().any(|t| t.id() == reversal_id) {
+            return Err(http_err::conflict_error(anyhow!(
+                "transaction {} is already reversed",
+                to_hex_string(*transfer_id)
             )));
         }
-    }
 
-    fn assert_total_value(
-        payload: Vec<responses::AddFilterTransaction>,
-        add_transactions: Vec<responses::AddTransaction>,
-    ) -> bool {
-        let find_sum_amount_by_unit = add_transactions
-            .iter()
-            .chunk_by(|t| t.commodity_unit.clone())
-            .into_iter()
-            .map(|g| {
-                (
-                    g.0,
-                    g.1.map(|t| t.amount).reduce(|acc, e| acc + e).unwrap_or(0),
-                )
-            })
-            .collect::<HashMap<String, i64>>();
-        for (unit, sum_amount) in find_sum_amount_by_unit.iter() {
-            let payload_by_unit_sum_amount = payload
-                .iter()
-                .filter(|v| v.commodity_unit == *unit)
-                .map(|v| v.amount)
-                .reduce(|acc, v| acc + v)
-                .unwrap_or(0);
+        // the related id is kept, the reversed transfer follows from the id of the reversal
+        tranfers.push(
+            tb::Transfer::new(reversal_id)
+                .with_amount(original.amount())
+                .with_code(original.code())
+                .with_debit_account_id(original.credit_account_id())
+                .with_credit_account_id(original.debit_account_id())
+                .with_user_data_128(original.user_data_128())
+                .with_user_data_64(body.full_date2 as u64)
+                .with_user_data_32(tb_utils::USER_DATA_32_REVERSAL)
+                .with_ledger(original.ledger()),
+        );
+    }
+    link_transfers(&mut tranfers);
+    let transfer_ids = tranfers.iter().map(|t| to_hex_string(t.id())).collect();
 
-            if *sum_amount != payload_by_unit_sum_amount {
-                println!(
-                    "for commodity {} payload sum amount {} is not the same as result amount {}",
-                    unit, payload_by_unit_sum_amount, sum_amount
-                );
-                return false;
-            }
-        }
-        true
+    // unlike adding, a reversal that already exists is a conflict
+    if let Err(err) = state.ledger.create_transfers(tranfers.clone()).await {
+        return Err(create_transfers_error(&state, &tranfers, &err).await);
     }
-    assert!(assert_total_value(
-        body.filter_transactions.clone(),
-        add_transactions.clone()
-    ));
 
-    Ok(Json(responses::AddTransactions {
-        full_date2: body.full_date2,
-        transactions: add_transactions,
-    }))
+    Ok(Json(transfer_ids))
 }
 
-#[derive(Deserialize, ToSchema, Validate)]
-pub struct QueryTransactionsBody {
-    date_newest: usize,
-    date_oldest: usize,
-    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
-    accounts_glob: String,
+/// Looks up pending transfers by hex id, returned in the same order as the ids.
+async fn lookup_pending_transfers<'a>(
+    state: &AppState,
+    pending_ids: impl Iterator<Item = &'a str>,
+) -> http_err::HttpResult<Vec<tb::Transfer>> {
+    let pending_ids = pending_ids
+        .map(|id| {
+            tb_utils::u128::try_from_hex_string(id)
+                .map_err(|_| http_err::bad_error(anyhow!("invalid pending id {}", id)))
+        })
+        .collect::<http_err::HttpResult<Vec<u128>>>()?;
+
+    let found = state
+        .ledger
+        .lookup_transfers(pending_ids.clone())
+        .await
+        .map_err(http_err::storage_error)?;
+
+    pending_ids
+        .iter()
+        .map(|pending_id| {
+            found
+                .iter()
+                .find(|t| t.id() == *pending_id)
+                .filter(|t| t.flags().contains(tb::transfer::Flags::PENDING))
+                .copied()
+                .ok_or(http_err::not_found_error(anyhow!(
+                    "pending transaction {} not found",
+                    to_hex_string(*pending_id)
+                )))
+        })
+        .collect()
+}
+
+/// Builds a transfer that posts or voids the pending transfer, keeping its accounts and user data.
+/// Its id follows from the pending id, so a retry returns the same id and a pending transfer is
+/// only resolved once.
+fn resolve_pending_transfer(
+    pending: &tb::Transfer,
+    amount: u128,
+    flags: tb::transfer::Flags,
+) -> tb::Transfer {
+    tb::Transfer::new(tb_utils::u128::to_resolution_id(pending.id()))
+        .with_pending_id(pending.id())
+        .with_amount(amount)
+        .with_code(pending.code())
+        .with_debit_account_id(pending.debit_account_id())
+        .with_credit_account_id(pending.credit_account_id())
+        .with_user_data_128(pending.user_data_128())
+        .with_user_data_64(pending.user_data_64())
+        .with_user_data_32(pending.user_data_32())
+        .with_ledger(pending.ledger())
+        .with_flags(flags)
 }
 
-#[utoipa::path(post, path = "/query/account-transactions", responses(
-    (status = 200, description = "Returns list of transactions by filter", body=Vec<responses::Transaction>),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/export-hledger", responses(
+    (status = 200, description = "Streams hledger export", body = String, content_type = "text/plain", headers(
+        ("content-disposition" = String, description = "Attachment filename"),
+    )),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
 ))]
-pub async fn query_account_transactions(
+pub async fn query_export_hledger(
     State(state): State<AppState>,
     Json(body): Json<QueryTransactionsBody>,
-) -> Result<Json<responses::ResponseTransactions>, http_err::HttpErr> {
-    body.validate().map_err(http_err::bad_error)?;
+) -> http_err::HttpResult<Response<Body>> {
+    export_transactions(state, body, ExportFormat::Hledger).await
+}
 
-    let conn = state.pool.get().await.map_err(http_err::internal_error)?;
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/export-ndjson", params(AmountParams), responses(
+    (status = 200, description = "Streams one json transaction per line", body = responses::Transaction, content_type = "application/x-ndjson", headers(
+        ("content-disposition" = String, description = "Attachment filename"),
+    )),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_export_ndjson(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryTransactionsBody>,
+) -> http_err::HttpResult<Response<Body>> {
+    export_transactions(
+        state,
+        body,
+        ExportFormat::Ndjson {
+            amount_format: params.amount_format,
+        },
+    )
+    .await
+}
 
-    let accounts: Vec<Account> = find_accounts_re(&conn, body.accounts_glob).await?;
-    // println!(
-    //     "accounts found: {}",
-    //     accounts.iter().map(|a| a.tb_id.clone()).join(", ")
-    // );
-    let commodities = list_all_commodities(&conn).await?;
-    let commodities = commodities
-        .iter()
-        .map(|c| (c.id as u32, c))
-        .collect::<HashMap<_, _>>();
+/// Format of a streamed export.
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Hledger,
+    Csv {
+        delimiter: u8,
+    },
+    Ndjson {
+        amount_format: responses::AmountFormat,
+    },
+}
 
-    // println!(
-    //     "commodities found: '{}'",
-    //     commodities.iter().map(|a| a.0).join(", ")
-    // );
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Hledger => "text/plain; charset=utf-8",
+            ExportFormat::Csv { .. } => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson { .. } => "application/x-ndjson",
+        }
+    }
 
-    // collect all transfers in a hashmap
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Hledger => "journal",
+            ExportFormat::Csv { .. } => "csv",
+            ExportFormat::Ndjson { .. } => "ndjson",
+        }
+    }
+
+    /// Writes one page of the export, the csv header is written before the first page.
+    fn write(
+        &self,
+        transactions: responses::ResponseTransactions,
+        first: bool,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut chunk: Vec<u8> = Vec::new();
+        match self {
+            ExportFormat::Hledger => {
+                for (i, transaction) in transactions.iter().enumerate() {
+                    if !first || i > 0 {
+                        chunk.push(b'\n');
+                    }
+                    chunk.extend(transaction.to_hledger_string()?.into_bytes());
+                }
+            }
+            ExportFormat::Csv { delimiter } => {
+                let mut writer = csv::WriterBuilder::new()
+                    .delimiter(*delimiter)
+                    .from_writer(chunk);
+                if first {
+                    writer.write_record(responses::Transaction::csv_header())?;
+                }
+                for transaction in transactions.iter() {
+                    writer.write_record(transaction.to_csv_record())?;
+                }
+                chunk = writer.into_inner()?;
+            }
+            ExportFormat::Ndjson { amount_format } => {
+                for transaction in transactions {
+                    serde_json::to_writer(&mut chunk, &transaction.with_format(*amount_format))?;
+                    chunk.push(b'\n');
+                }
+            }
+        }
+        Ok(chunk)
+    }
+}
+
+/// Streams the transactions of the accounts oldest first, reading tigerbeetle one batch at a time
+/// so that the export is never held in memory. Errors after the first page end the response early.
+async fn export_transactions(
+    state: AppState,
+    body: QueryTransactionsBody,
+    format: ExportFormat,
+) -> http_err::HttpResult<Response<Body>> {
+    body.validate().map_err(http_err::bad_error)?;
+    if body.limit.is_some() {
+        return Err(http_err::bad_error(anyhow!(
+            "limit is not supported by exports"
+        )));
+    }
+
+    let amount_format = match format {
+        ExportFormat::Ndjson { amount_format } => amount_format,
+        _ => responses::AmountFormat::default(),
+    };
+    let (newest_transfer_timestamp, oldest_transfer_timestamp) = body.timestamps()?;
+    let filter = TransferFilter::new(&state, &body, amount_format).await?;
+    let accounts: Vec<Account> = state.store.find_accounts_re(body.accounts_glob).await?;
+
+    // (cursor of the next page, is first page), none after the last page
+    let first_page: Option<(Option<TransfersCursor>, bool)> = Some((None, true));
+    let pages = futures_util::stream::unfold(
+        (state, accounts, filter, first_page),
+        move |(state, accounts, filter, page)| async move {
+            let (cursor, first) = page?;
+            let chunk = async {
+                let (transfers, next_cursor) = page_account_transfers(
+                    &state,
+                    &accounts,
+                    &filter,
+                    newest_transfer_timestamp,
+                    oldest_transfer_timestamp,
+                    cursor,
+                    MAX_TRANSACTIONS_PAGE as usize,
+                )
+                .await?;
+                let transactions =
+                    transfers_to_transactions(state.store.as_ref(), &accounts, transfers.iter())
+                        .await?;
+                let chunk = format
+                    .write(transactions, first)
+                    .map_err(http_err::internal_error)?;
+                Ok::<_, http_err::HttpErr>((chunk, next_cursor))
+            }
+            .await;
+            match chunk {
+                Ok((chunk, next_cursor)) => {
+                    let next_page = next_cursor.map(|cursor| (Some(cursor), false));
+                    Some((Ok(chunk), (state, accounts, filter, next_page)))
+                }
+                Err(err) => Some((Err(anyhow!(err.message)), (state, accounts, filter, None))),
+            }
+        },
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"transactions.{}\"",
+                format.extension()
+            ),
+        )
+        .body(Body::from_stream(pages))
+        .map_err(http_err::internal_error)
+}
+
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/export-beancount", responses(
+    (status = 200, description = "Returns beancount export", body=String),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_export_beancount(
+    state: State<AppState>,
+    json: Json<QueryTransactionsBody>,
+) -> Result<String, http_err::HttpErr> {
+    let (_, res_json) =
+        query_account_transactions(state, Query(AmountParams::default()), json).await?;
+
+    // account names and commodities that beancount does not allow are a bad request
+    let res_open = beancount::open_directives(&res_json).map_err(http_err::bad_error)?;
+    let res_beancount_arr = res_json
+        .iter()
+        .map(|item| item.to_beancount_string())
+        .collect::<Result<Vec<String>, ValidationError>>()
+        .map_err(http_err::bad_error)?;
+    let res_beancount = res_beancount_arr.iter().join("\n");
+    Ok(format!("{}\n{}", res_open, res_beancount))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CsvParams {
+    /// single character separating the columns, defaults to a comma
+    delimiter: Option<char>,
+}
+
+impl CsvParams {
+    fn delimiter(&self) -> http_err::HttpResult<u8> {
+        match self.delimiter {
+            None => Ok(b','),
+            Some(c) if c.is_ascii() && !matches!(c, '"' | '\r' | '\n') => Ok(c as u8),
+            Some(c) => Err(http_err::bad_error(anyhow!(
+                "invalid csv delimiter {:?}",
+                c
+            ))),
+        }
+    }
+}
+
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/export-csv", params(CsvParams), responses(
+    (status = 200, description = "Streams csv export", body = String, content_type = "text/csv", headers(
+        ("content-disposition" = String, description = "Attachment filename"),
+    )),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_export_csv(
+    State(state): State<AppState>,
+    Query(params): Query<CsvParams>,
+    Json(body): Json<QueryTransactionsBody>,
+) -> http_err::HttpResult<Response<Body>> {
+    let delimiter = params.delimiter()?;
+    export_transactions(state, body, ExportFormat::Csv { delimiter }).await
+}
+
+#[debug_handler]
+#[utoipa::path(put, path = "/mutate/import-csv", params(
+    CsvParams,
+    AmountParams,
+    ("Idempotency-Key" = Option<String>, Header, description = "Client generated key used for the transfer ids of rows without one, resubmitting it skips the rows already imported"),
+), responses(
+    (status = 200, description = "Returns the imported and skipped rows", body = responses::ResponseImportCsv),
+    (status = 400, description = "Rows that can not be imported", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
+))]
+pub async fn mutate_import_csv(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<CsvParams>,
+    Query(amount_params): Query<AmountParams>,
+    body: String,
+) -> http_err::HttpResult<Json<responses::ResponseImportCsv>> {
+    if !state.allow_migrate {
+        return Err(http_err::disabled_error("migrating to ledger is disabled"));
+    }
+    let idempotency_key = idempotency_key(&headers, "/mutate/import-csv")?;
+
+    // the whole file is validated before anything is added
+    let decimal_places = commodity_decimal_places(&state).await?;
+    let rows = responses::AddTransactions::parse_from_csv(
+        &body,
+        params.delimiter()?,
+        &decimal_places,
+        amount_params.amount_format,
+    )
+    .map_err(|errors| http_err::bad_error(errors.iter().join("\n")))?;
+    let groups = group_csv_rows(rows, idempotency_key)
+        .map_err(|errors| http_err::bad_error(errors.iter().join("\n")))?;
+
+    // rows with a transfer id that already exists are skipped, accounts are only created after
+    // the whole file is validated
+    let existing = existing_csv_transfers(&state, &groups).await?;
+
+    let mut summary = responses::ImportSummary::default();
+    let mut batches: Vec<Vec<tb::Transfer>> = vec![Vec::new()];
+    for (full_date2, rows) in groups {
+        let (skipped, rows): (Vec<_>, Vec<_>) = rows.into_iter().partition(|(_, t)| {
+            t.transfer_id
+                .as_deref()
+                .is_some_and(|id| existing.contains(&from_hex_string(id)))
+        });
+        summary
+            .skipped
+            .extend(skipped.into_iter().map(|(row, t)| responses::SkippedRow {
+                row,
+                transfer_id: t.transfer_id.unwrap_or_default(),
+            }));
+        if rows.is_empty() {
+            continue;
+        }
+
+        let transactions = rows.into_iter().map(|(_, t)| t).collect::<Vec<_>>();
+        let tranfers = build_transfers(
+            &state,
+            full_date2,
+            &transactions,
+            None,
+            tb::transfer::Flags::empty(),
+            0,
+        )
+        .await?;
+        // linked transfers of a group must be sent in the same batch
+        if let Some(batch) = batches
+            .last_mut()
+            .filter(|b| b.len() + tranfers.len() <= TB_MAX_BATCH_SIZE as usize)
+        {
+            batch.extend(tranfers);
+        } else {
+            batches.push(tranfers);
+        }
+    }
+
+    for batch in batches.into_iter().filter(|b| !b.is_empty()) {
+        let transfer_ids = batch.iter().map(|t| to_hex_string(t.id())).collect_vec();
+        create_transfers(&state, batch).await.map_err(|mut err| {
+            if summary.imported > 0 {
+                err.message = format!(
+                    "{} rows were imported before this error, resubmit the file with the same transfer ids or idempotency key to skip them: {}",
+                    summary.imported, err.message
+                );
+            }
+            err
+        })?;
+        summary.imported += transfer_ids.len();
+        summary.transfer_ids.extend(transfer_ids);
+    }
+
+    Ok(Json(summary))
+}
+
+/// Returns the ids of the rows that already exist. A row that differs from its existing transfer
+/// in amount, accounts or code is a conflict, accounts are looked up without creating them.
+async fn existing_csv_transfers(
+    state: &AppState,
+    groups: &[CsvGroup],
+) -> http_err::HttpResult<HashSet<u128>> {
+    let rows = groups
+        .iter()
+        .flat_map(|(_, rows)| rows.iter())
+        .filter_map(|(row, t)| {
+            t.transfer_id
+                .as_deref()
+                .map(|id| (*row, t, from_hex_string(id)))
+        })
+        .collect::<Vec<_>>();
+    let mut existing: HashMap<u128, tb::Transfer> = HashMap::new();
+    for chunk in rows.chunks(TB_MAX_BATCH_SIZE as usize) {
+        let found = state
+            .ledger
+            .lookup_transfers(chunk.iter().map(|(_, _, id)| *id).collect())
+            .await
+            .map_err(http_err::storage_error)?;
+        existing.extend(found.into_iter().map(|t| (t.id(), t)));
+    }
+
+    let mut account_ids: HashMap<(&str, &str), Option<u128>> = HashMap::new();
+    let mut conflicts: Vec<responses::CsvRowError> = Vec::new();
+    for (row, t, id) in rows {
+        let Some(transfer) = existing.get(&id) else {
+            continue;
+        };
+        let mut same =
+            t.amount.to_u128().ok() == Some(transfer.amount()) && t.code as u16 == transfer.code();
+        for (account, account_id) in [
+            (&t.debit_account, transfer.debit_account_id()),
+            (&t.credit_account, transfer.credit_account_id()),
+        ] {
+            let key = (account.as_str(), t.commodity_unit.as_str());
+            let found = match account_ids.get(&key) {
+                Some(found) => *found,
+                None => {
+                    let found = find_account_id(state, account, &t.commodity_unit).await?;
+                    account_ids.insert(key, found);
+                    found
+                }
+            };
+            same &= found == Some(account_id);
+        }
+        if !same {
+            conflicts.push(responses::CsvRowError {
+                row,
+                message: format!(
+                    "transfer id {} already exists with a different amount, accounts or code",
+                    to_hex_string(id)
+                ),
+            });
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(existing.into_keys().collect())
+    } else {
+        Err(http_err::conflict_error(conflicts.iter().join("\n")))
+    }
+}
+
+/// Returns the tigerbeetle id of an account without creating it.
+async fn find_account_id(
+    state: &AppState,
+    account: &str,
+    commodity_unit: &str,
+) -> http_err::HttpResult<Option<u128>> {
+    let Some(commodity) = state
+        .store
+        .find_commodity(String::from(commodity_unit))
+        .await?
+    else {
+        return Ok(None);
+    };
+    Ok(state
+        .store
+        .find_account(String::from(account), commodity.id)
+        .await?
+        .map(|a| from_hex_string(&a.tb_id)))
+}
+
+/// Rows of a csv file grouped by full_date2 and related id, each with its row number
+type CsvGroup = (i64, Vec<(usize, responses::AddTransaction)>);
+
+/// Groups the rows of a multi-leg transaction so that they are linked together. Rows without a
+/// transfer id get one derived from the idempotency key and their row number.
+fn group_csv_rows(
+    rows: Vec<(usize, responses::AddTransactions)>,
+    idempotency_key: Option<IdempotencyKey<'_>>,
+) -> Result<Vec<CsvGroup>, Vec<responses::CsvRowError>> {
+    let mut errors: Vec<responses::CsvRowError> = Vec::new();
+    let mut transfer_ids: HashSet<String> = HashSet::new();
+    let mut groups: Vec<CsvGroup> = Vec::new();
+    let mut group_index: HashMap<(i64, String), usize> = HashMap::new();
+    // every row is parsed into one AddTransactions
+    for (row, add_transactions) in rows {
+        for mut t in add_transactions.transactions {
+            if let (None, Some(key)) = (&t.transfer_id, idempotency_key) {
+                t.transfer_id = Some(to_hex_string(key.transfer_id(row)));
+            }
+            if tb_utils::u128::try_from_hex_string(&t.related_id).is_err() {
+                errors.push(responses::CsvRowError {
+                    row,
+                    message: format!("invalid related id {}", t.related_id),
+                });
+            }
+            if let Some(transfer_id) = &t.transfer_id {
+                if tb_utils::u128::try_from_hex_string(transfer_id).is_err() {
+                    errors.push(responses::CsvRowError {
+                        row,
+                        message: format!("invalid transfer id {}", transfer_id),
+                    });
+                } else if !transfer_ids.insert(transfer_id.clone()) {
+                    errors.push(responses::CsvRowError {
+                        row,
+                        message: format!("duplicate transfer id {}", transfer_id),
+                    });
+                }
+            }
+
+            let full_date2 = add_transactions.full_date2;
+            match group_index.get(&(full_date2, t.related_id.clone())) {
+                Some(i) => groups[*i].1.push((row, t)),
+                None => {
+                    group_index.insert((full_date2, t.related_id.clone()), groups.len());
+                    groups.push((full_date2, vec![(row, t)]));
+                }
+            }
+        }
+    }
+
+    for (_, rows) in groups.iter() {
+        if rows.len() > TB_MAX_BATCH_SIZE as usize {
+            errors.push(responses::CsvRowError {
+                row: rows[0].0,
+                message: format!(
+                    "related id {} has more than {} rows",
+                    rows[0].1.related_id, TB_MAX_BATCH_SIZE
+                ),
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(groups)
+    } else {
+        errors.sort_by_key(|e| e.row);
+        Err(errors)
+    }
+}
+
+// #[debug_handler]
+#[utoipa::path(put, path = "/mutate/import-hledger", request_body(content = String, description = "hledger journal, `P` directives are saved as prices", content_type = "text/plain"), params(
+    ("Idempotency-Key" = Option<String>, Header, description = "Client generated key used for the transfer ids of transactions without one, resubmitting it skips the transactions already imported. Without a key the ids follow from the content of each transaction"),
+), responses(
+    (status = 200, description = "Returns list of transaction ids", body = responses::ResponseAdd),
+    (status = 400, description = "Journal contains lines that can not be imported", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
+))]
+pub async fn mutate_import_hledger(
+    state: State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
+    if !state.allow_migrate {
+        return Err(http_err::disabled_error("migrating to ledger is disabled"));
+    }
+    let idempotency_key = idempotency_key(&headers, "/mutate/import-hledger")?;
+
+    let decimal_places = commodity_decimal_places(&state).await?;
+    // the whole journal is parsed before anything is added
+    let journal = hledger::parse_journal(&body, &decimal_places)
+        .map_err(|errors| http_err::bad_error(errors.iter().join("\n")))?;
+
+    let transfer_ids = add_all(
+        &state,
+        journal.transactions,
+        "/mutate/import-hledger",
+        idempotency_key,
+    )
+    .await?;
+    // prices are saved once the transactions are added so a rejected journal saves nothing
+    if !journal.prices.is_empty() {
+        save_prices(&state, journal.prices).await?;
+    }
+    Ok(transfer_ids)
+}
+
+// #[debug_handler]
+#[utoipa::path(put, path = "/mutate/import-beancount", request_body(content = String, description = "beancount file", content_type = "text/plain"), params(
+    ("Idempotency-Key" = Option<String>, Header, description = "Client generated key used for the transfer ids of transactions without one, resubmitting it skips the transactions already imported. Without a key the ids follow from the content of each transaction"),
+), responses(
+    (status = 200, description = "Returns list of transaction ids", body = responses::ResponseAdd),
+    (status = 400, description = "File contains lines that can not be imported", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
+))]
+pub async fn mutate_import_beancount(
+    state: State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
+    if !state.allow_migrate {
+        return Err(http_err::disabled_error("migrating to ledger is disabled"));
+    }
+    let idempotency_key = idempotency_key(&headers, "/mutate/import-beancount")?;
+
+    let decimal_places = commodity_decimal_places(&state).await?;
+    // the whole file is parsed before anything is added
+    let add_transactions_arr = beancount::parse_file(&body, &decimal_places)
+        .map_err(|errors| http_err::bad_error(errors.iter().join("\n")))?;
+
+    add_all(
+        &state,
+        add_transactions_arr,
+        "/mutate/import-beancount",
+        idempotency_key,
+    )
+    .await
+}
+
+async fn commodity_decimal_places(state: &AppState) -> http_err::HttpResult<HashMap<String, i32>> {
+    Ok(state
+        .store
+        .list_all_commodities()
+        .await?
+        .into_iter()
+        .map(|c| (c.unit, c.decimal_place))
+        .collect())
+}
+
+/// Adds the transactions of an imported file, returning the transfer ids of all transactions.
+///
+/// Transactions without a transfer id get one derived from the idempotency key and their index
+/// in the file. Without a key the ids are derived from the content of each transaction, so a
+/// resubmitted or corrected file skips the transactions already added. A transaction without a
+/// related id is related by the transfer id of its first leg.
+///
+/// Every transaction is validated before any account is created, the legs of each transaction
+/// are linked and sent in batches of up to `TB_MAX_BATCH_SIZE` transfers.
+async fn add_all(
+    state: &AppState,
+    mut add_transactions_arr: Vec<responses::AddTransactions>,
+    route: &'static str,
+    idempotency_key: Option<IdempotencyKey<'_>>,
+) -> http_err::HttpResult<Json<responses::ResponseAdd>> {
+    match idempotency_key {
+        Some(key) => {
+            for (index, t) in add_transactions_arr
+                .iter_mut()
+                .flat_map(|a| a.transactions.iter_mut())
+                .enumerate()
+            {
+                if t.transfer_id.is_none() {
+                    t.transfer_id = Some(to_hex_string(key.transfer_id(index)));
+                }
+            }
+        }
+        None => {
+            // identical transactions of a file are told apart by their occurrence
+            let mut occurrences: HashMap<String, usize> = HashMap::new();
+            for add_transactions in add_transactions_arr.iter_mut() {
+                let content = std::iter::once(add_transactions.full_date2.to_string())
+                    .chain(add_transactions.transactions.iter().map(|t| {
+                        format!(
+                            "{}|{}|{}|{}|{}|{}",
+                            t.commodity_unit,
+                            t.code,
+                            t.related_id,
+                            t.debit_account,
+                            t.credit_account,
+                            t.amount
+                        )
+                    }))
+                    .join(";");
+                let occurrence = occurrences.entry(content.clone()).or_default();
+                let key = IdempotencyKey {
+                    route,
+                    key: &format!("{}#{}", content, occurrence),
+                };
+                for (index, t) in add_transactions.transactions.iter_mut().enumerate() {
+                    if t.transfer_id.is_none() {
+                        t.transfer_id = Some(to_hex_string(key.transfer_id(index)));
+                    }
+                }
+                *occurrence += 1;
+            }
+        }
+    }
+    for add_transactions in add_transactions_arr.iter_mut() {
+        let related_id = add_transactions
+            .transactions
+            .first()
+            .and_then(|t| t.transfer_id.clone())
+            .unwrap_or_default();
+        for t in add_transactions.transactions.iter_mut() {
+            if t.related_id.is_empty() {
+                t.related_id = related_id.clone();
+            }
+        }
+    }
+
+    for add_transactions in add_transactions_arr.iter() {
+        add_transactions.validate().map_err(http_err::bad_error)?;
+        for t in add_transactions.transactions.iter() {
+            t.validate().map_err(http_err::bad_error)?;
+        }
+        if add_transactions.transactions.len() > TB_MAX_BATCH_SIZE as usize {
+            return Err(http_err::bad_error(anyhow!(
+                "a transaction has more than {} postings",
+                TB_MAX_BATCH_SIZE
+            )));
+        }
+    }
+
+    let mut batches: Vec<Vec<tb::Transfer>> = vec![Vec::new()];
+    for add_transactions in add_transactions_arr {
+        let tranfers = build_transfers(
+            state,
+            add_transactions.full_date2,
+            &add_transactions.transactions,
+            None,
+            tb::transfer::Flags::empty(),
+            0,
+        )
+        .await?;
+        // linked transfers of a transaction must be sent in the same batch
+        if let Some(batch) = batches
+            .last_mut()
+            .filter(|b| b.len() + tranfers.len() <= TB_MAX_BATCH_SIZE as usize)
+        {
+            batch.extend(tranfers);
+        } else {
+            batches.push(tranfers);
+        }
+    }
+
+    let mut transfer_ids: responses::ResponseAdd = Vec::new();
+    for batch in batches.into_iter().filter(|b| !b.is_empty()) {
+        let ids = batch.iter().map(|t| to_hex_string(t.id())).collect_vec();
+        create_transfers(state, batch).await.map_err(|mut err| {
+            if !transfer_ids.is_empty() {
+                err.message = format!(
+                    "{} transfers were added before this error, resubmit the file with the same idempotency key to skip them: {}",
+                    transfer_ids.len(),
+                    err.message
+                );
+            }
+            err
+        })?;
+        transfer_ids.extend(ids);
+    }
+
+    Ok(Json(transfer_ids))
+}
+
+#[utoipa::path(post, path = "/query/prepare-add", params(AmountParams), responses(
+    (status = 200, description = "Returns a prepared add payload to be run with the route PUT /app", body=responses::RequestAdd),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_prepare_add_fcfs(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(mut body): Json<responses::RequestAddPrepareGlob>,
+) -> http_err::HttpResult<Json<responses::ResponseAddPrepare>> {
+    if !state.allow_add {
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
+    }
+
+    body.validate().map_err(http_err::bad_error)?;
+    amounts_to_integer(
+        &state,
+        params.amount_format,
+        body.filter_transactions
+            .iter_mut()
+            .map(|t| (t.commodity_unit.as_str(), &mut t.amount)),
+    )
+    .await?;
+
+    // the payload is summed per commodity, reject totals that do not fit
+    let mut payload_totals: HashMap<&str, i128> = HashMap::new();
+    for t in body.filter_transactions.iter() {
+        let total = payload_totals.entry(t.commodity_unit.as_str()).or_insert(0);
+        *total = total
+            .checked_add(t.amount.integer().map_err(http_err::bad_error)?)
+            .ok_or(http_err::bad_error(anyhow!(
+                "sum of amounts of commodity {} overflows",
+                t.commodity_unit
+            )))?;
+    }
+
+    let mut add_transactions: Vec<responses::AddTransaction> = Vec::new();
+    // map of key: account_tb_id value: balance
+    let mut tb_account_balances: HashMap<String, i128> = HashMap::new();
+    for t in body.filter_transactions.iter() {
+        let mut remaining_amount = t.amount.integer().map_err(http_err::bad_error)?;
+        'loop_credit_accounts_filter_item: for credit_accounts_filter_item in
+            t.credit_accounts_filter.iter()
+        {
+            let credit_accounts = state
+                .store
+                .find_accounts_re_by_commodity(
+                    credit_accounts_filter_item.clone(),
+                    t.commodity_unit.clone(),
+                )
+                .await?;
+
+            // println!("credit accounts {}", credit_accounts.len());
+
+            // get account balances where not already retrieved
+            let missing_tb_account_ids: Vec<u128> = credit_accounts
+                .iter()
+                .map(|a| &a.tb_id)
+                .filter(|a| !tb_account_balances.contains_key(*a))
+                .map(|s| tb_utils::u128::from_hex_string(s.as_str()))
+                .collect();
+            let tb_accounts: Vec<tb::core::account::Account> = state
+                .ledger
+                .lookup_accounts(missing_tb_account_ids)
+                .await
+                .map_err(http_err::storage_error)?;
+            for a in tb_accounts.iter() {
+                tb_account_balances.insert(
+                    tb_utils::u128::to_hex_string(a.id()),
+                    responses::debits_sub_credits(a.debits_posted(), a.credits_posted())
+                        .map_err(http_err::internal_error)?,
+                );
+            }
+
+            // remove from account balance and until remaining amount is zero & add a transaction
+            for account in credit_accounts.iter() {
+                if let Some(tb_account_balance) = tb_account_balances.get_mut(&account.tb_id) {
+                    if *tb_account_balance > 0 {
+                        let add_transaction_amount = if remaining_amount < *tb_account_balance {
+                            *tb_account_balance -= remaining_amount;
+                            let old_remaining_amount = remaining_amount;
+                            remaining_amount = 0;
+                            old_remaining_amount
+                        } else {
+                            let old_tb_account_balance = *tb_account_balance;
+                            remaining_amount -= old_tb_account_balance;
+                            *tb_account_balance = 0;
+                            old_tb_account_balance
+                        };
+                        add_transactions.push(responses::AddTransaction {
+                            transfer_id: None,
+                            commodity_unit: t.commodity_unit.clone(),
+                            code: t.code,
+                            related_id: t.related_id.clone(),
+                            debit_account: t.debit_account.clone(),
+                            credit_account: account.name.clone(),
+                            amount: add_transaction_amount.into(),
+                        });
+                    }
+                }
+                if remaining_amount <= 0 {
+                    break 'loop_credit_accounts_filter_item;
+                }
+            }
+        }
+        if remaining_amount > 0 {
+            return Err(http_err::bad_error(anyhow!(
+                "not enough inside credit accounts to build a transaction"
+            )));
+        }
+    }
+
+    fn assert_total_value(
+        payload: &[responses::AddFilterTransaction],
+        add_transactions: &[responses::AddTransaction],
+    ) -> http_err::HttpResult<()> {
+        let find_sum_amount_by_unit = add_transactions
+            .iter()
+            .chunk_by(|t| t.commodity_unit.clone())
+            .into_iter()
+            .map(|g| {
+                (
+                    g.0,
+                    g.1.filter_map(|t| t.amount.integer().ok())
+                        .try_fold(0i128, i128::checked_add),
+                )
+            })
+            .collect::<HashMap<String, Option<i128>>>();
+        for (unit, sum_amount) in find_sum_amount_by_unit.iter() {
+            let payload_by_unit_sum_amount = payload
+                .iter()
+                .filter(|v| v.commodity_unit == *unit)
+                .filter_map(|v| v.amount.integer().ok())
+                .try_fold(0i128, i128::checked_add);
+
+            if sum_amount.is_none() || *sum_amount != payload_by_unit_sum_amount {
+                return Err(http_err::internal_error(format!(
+                    "for commodity {} payload sum amount {:?} is not the same as result amount {:?}",
+                    unit, payload_by_unit_sum_amount, sum_amount
+                )));
+            }
+        }
+        Ok(())
+    }
+    assert_total_value(&body.filter_transactions, &add_transactions)?;
+
+    let decimal_places = commodity_decimal_places(&state).await?;
+    for t in add_transactions.iter_mut() {
+        let decimal_place = decimal_places.get(&t.commodity_unit).copied().unwrap_or(0);
+        t.amount = t.amount.to_format(decimal_place, params.amount_format);
+    }
+
+    Ok(Json(responses::AddTransactions {
+        full_date2: body.full_date2,
+        transactions: add_transactions,
+    }))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[validate(schema(function = "validate_transactions_query"))]
+pub struct QueryTransactionsBody {
+    date_newest: usize,
+    date_oldest: usize,
+    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
+    accounts_glob: String,
+    /// Most transactions to return, the `next-cursor` response header is set when there are more
+    #[serde(default)]
+    #[validate(range(min = 1, max = MAX_TRANSACTIONS_PAGE))]
+    limit: Option<u32>,
+    /// Value of the `next-cursor` header of the previous page
+    #[serde(default)]
+    cursor: Option<String>,
+    /// Only transfers with one of these codes
+    #[serde(default)]
+    codes: Option<Vec<u16>>,
+    /// Only transfers with a code of at least this
+    #[serde(default)]
+    code_min: Option<u16>,
+    /// Only transfers with a code of at most this
+    #[serde(default)]
+    code_max: Option<u16>,
+    /// Only transfers with this hex related id
+    #[serde(default)]
+    related_id: Option<String>,
+    /// Only transfers of this commodity
+    #[serde(default)]
+    commodity_unit: Option<String>,
+    /// Only transfers of at least this amount, in the format of `amount_format`, decimals are
+    /// rounded up to the decimal places of each commodity
+    #[serde(default)]
+    amount_min: Option<responses::Amount>,
+    /// Only transfers of at most this amount, in the format of `amount_format`, decimals are
+    /// rounded down to the decimal places of each commodity
+    #[serde(default)]
+    amount_max: Option<responses::Amount>,
+    /// Only transfers debiting or only transfers crediting the accounts
+    #[serde(default)]
+    side: Option<responses::TransferSide>,
+    /// Only transfers with the other account of the transfer matching this glob
+    #[serde(default)]
+    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
+    counterparty_glob: Option<String>,
+}
+
+impl QueryTransactionsBody {
+    /// Returns the newest and oldest timestamp of the transfers.
+    fn timestamps(&self) -> http_err::HttpResult<(SystemTime, SystemTime)> {
+        let timestamp = |millis: usize| {
+            UNIX_EPOCH
+                .checked_add(Duration::from_millis(millis as u64))
+                .ok_or(http_err::internal_error(ValidationError::new(
+                    "i64 unix nano date max",
+                )))
+        };
+        Ok((timestamp(self.date_newest)?, timestamp(self.date_oldest)?))
+    }
+}
+
+fn validate_transactions_query(body: &QueryTransactionsBody) -> Result<(), ValidationError> {
+    if body.cursor.is_some() && body.limit.is_none() {
+        return Err(ValidationError::new("cursor requires limit"));
+    }
+    if let Some(cursor) = &body.cursor {
+        TransfersCursor::parse(cursor)?;
+    }
+    if let Some(related_id) = &body.related_id {
+        tb_utils::u128::try_from_hex_string(related_id)
+            .map_err(|_| ValidationError::new("invalid related_id"))?;
+    }
+    if body.code_min.unwrap_or(u16::MIN) > body.code_max.unwrap_or(u16::MAX) {
+        return Err(ValidationError::new("code_min must not exceed code_max"));
+    }
+    Ok(())
+}
+
+/// Filters of a transactions query resolved against the store. The side and commodity narrow the
+/// accounts read from tigerbeetle, a related id or a single code is read with `query_transfers`
+/// instead of reading every account, the other filters are checked on each transfer.
+struct TransferFilter {
+    codes: Option<HashSet<u16>>,
+    code_min: u16,
+    code_max: u16,
+    related_id: Option<u128>,
+    ledger: Option<u32>,
+    /// minimum and maximum amount per ledger
+    amounts: Option<HashMap<u32, (u128, u128)>>,
+    side: Option<responses::TransferSide>,
+    counterparties: Option<HashSet<u128>>,
+}
+
+impl TransferFilter {
+    async fn new(
+        state: &AppState,
+        body: &QueryTransactionsBody,
+        amount_format: responses::AmountFormat,
+    ) -> http_err::HttpResult<TransferFilter> {
+        let commodities = state.store.list_all_commodities().await?;
+        let ledger = match &body.commodity_unit {
+            Some(unit) => Some(
+                commodities
+                    .iter()
+                    .find(|c| &c.unit == unit)
+                    .ok_or(http_err::bad_error(anyhow!("unknown commodity {}", unit)))?
+                    .id as u32,
+            ),
+            None => None,
+        };
+        let amounts = match (&body.amount_min, &body.amount_max) {
+            (None, None) => None,
+            (amount_min, amount_max) => {
+                // decimal bounds round inwards to the decimal places of each commodity
+                let bound = |amount: &Option<responses::Amount>,
+                             decimal_place: i32,
+                             round_up: bool,
+                             default| {
+                    amount
+                        .as_ref()
+                        .map(|a| {
+                            a.to_integer_rounded(decimal_place, amount_format, round_up)
+                                .map(responses::Amount::Integer)
+                                .map_err(http_err::bad_error)?
+                                .to_u128()
+                                .map_err(http_err::bad_error)
+                        })
+                        .unwrap_or(Ok(default))
+                };
+                Some(
+                    commodities
+                        .iter()
+                        .filter(|c| ledger.is_none_or(|ledger| ledger == c.id as u32))
+                        .map(|c| {
+                            Ok((
+                                c.id as u32,
+                                (
+                                    bound(amount_min, c.decimal_place, true, u128::MIN)?,
+                                    bound(amount_max, c.decimal_place, false, u128::MAX)?,
+                                ),
+                            ))
+                        })
+                        .collect::<http_err::HttpResult<HashMap<u32, (u128, u128)>>>()?,
+                )
+            }
+        };
+        let counterparties = match &body.counterparty_glob {
+            Some(glob) => Some(
+                state
+                    .store
+                    .find_accounts_re(glob.clone())
+                    .await?
+                    .iter()
+                    .map(|a| from_hex_string(a.tb_id.as_str()))
+                    .collect(),
+            ),
+            None => None,
+        };
+        Ok(TransferFilter {
+            codes: body
+                .codes
+                .as_ref()
+                .map(|codes| codes.iter().copied().collect()),
+            code_min: body.code_min.unwrap_or(u16::MIN),
+            code_max: body.code_max.unwrap_or(u16::MAX),
+            related_id: body
+                .related_id
+                .as_deref()
+                .map(tb_utils::u128::try_from_hex_string)
+                .transpose()
+                .map_err(http_err::bad_error)?,
+            ledger,
+            amounts,
+            side: body.side,
+            counterparties,
+        })
+    }
+
+    /// Returns the code every matching transfer has, if there is only one.
+    fn code(&self) -> Option<u16> {
+        match &self.codes {
+            Some(codes) if codes.len() == 1 => codes.iter().next().copied(),
+            _ if self.code_min == self.code_max => Some(self.code_min),
+            _ => None,
+        }
+    }
+
+    /// Whether the transfers are read with `query_transfers` instead of per account.
+    fn is_query(&self) -> bool {
+        self.related_id.is_some() || self.code().is_some()
+    }
+
+    /// Whether every transfer read from tigerbeetle matches, so that reads can be sized to the
+    /// page.
+    fn is_narrowed(&self) -> bool {
+        !self.is_query()
+            && self.codes.is_none()
+            && self.code_min == u16::MIN
+            && self.code_max == u16::MAX
+            && self.amounts.is_none()
+            && self.counterparties.is_none()
+    }
+
+    fn account_flags(&self) -> tb::account::FilterFlags {
+        match self.side {
+            Some(responses::TransferSide::Debit) => tb::account::FilterFlags::DEBITS,
+            Some(responses::TransferSide::Credit) => tb::account::FilterFlags::CREDITS,
+            None => tb::account::FilterFlags::DEBITS | tb::account::FilterFlags::CREDITS,
+        }
+    }
+
+    fn query_filter(&self, limit: u32) -> tb::QueryFilter {
+        let mut filter = tb::QueryFilter::new(limit);
+        if let Some(related_id) = self.related_id {
+            filter = filter.with_user_data_128(related_id);
+        }
+        if let Some(ledger) = self.ledger {
+            filter = filter.with_ledger(ledger);
+        }
+        if let Some(code) = self.code() {
+            filter = filter.with_code(code);
+        }
+        filter
+    }
+
+    /// Whether the transfer is to or from one of the accounts and passes every filter.
+    fn matches(&self, transfer: &tb::Transfer, accounts: &HashSet<u128>) -> bool {
+        let is_counterparty = |account_id: u128| {
+            self.counterparties
+                .as_ref()
+                .is_none_or(|counterparties| counterparties.contains(&account_id))
+        };
+        let debited = accounts.contains(&transfer.debit_account_id())
+            && is_counterparty(transfer.credit_account_id());
+        let credited = accounts.contains(&transfer.credit_account_id())
+            && is_counterparty(transfer.debit_account_id());
+        let side = match self.side {
+            Some(responses::TransferSide::Debit) => debited,
+            Some(responses::TransferSide::Credit) => credited,
+            None => debited || credited,
+        };
+        let code = transfer.code();
+        side && self
+            .codes
+            .as_ref()
+            .is_none_or(|codes| codes.contains(&code))
+            && (self.code_min..=self.code_max).contains(&code)
+            && self
+                .related_id
+                .is_none_or(|id| id == transfer.user_data_128())
+            && self.ledger.is_none_or(|ledger| ledger == transfer.ledger())
+            && self.amounts.as_ref().is_none_or(|amounts| {
+                amounts
+                    .get(&transfer.ledger())
+                    .is_some_and(|(min, max)| (min..=max).contains(&&transfer.amount()))
+            })
+    }
+}
+
+/// Header containing the cursor of the next page of transactions, absent on the last page.
+pub const NEXT_CURSOR_HEADER: &str = "next-cursor";
+
+/// Most transactions in one page, leaves room in a batch for the transfer at the cursor.
+const MAX_TRANSACTIONS_PAGE: u32 = TB_MAX_BATCH_SIZE - 2;
+
+/// Position of the last transfer of a page, encoded as the hex timestamp followed by the hex id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct TransfersCursor {
+    timestamp: u64,
+    id: u128,
+}
+
+impl TransfersCursor {
+    fn of(transfer: &tb::Transfer) -> TransfersCursor {
+        TransfersCursor {
+            timestamp: transfer.as_raw().timestamp,
+            id: transfer.id(),
+        }
+    }
+
+    fn parse(cursor: &str) -> Result<TransfersCursor, ValidationError> {
+        let invalid = || ValidationError::new("invalid cursor");
+        if cursor.len() != 48 || !cursor.is_ascii() {
+            return Err(invalid());
+        }
+        let (timestamp, id) = cursor.split_at(16);
+        Ok(TransfersCursor {
+            timestamp: u64::from_str_radix(timestamp, 16).map_err(|_| invalid())?,
+            id: u128::from_str_radix(id, 16).map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl std::fmt::Display for TransfersCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}{:032x}", self.timestamp, self.id)
+    }
+}
+
+/// Reads the page of transfers after the cursor when `limit` is set, with the `next-cursor`
+/// header when there are more, or else all transfers page by page.
+async fn read_account_transfers(
+    state: &AppState,
+    accounts: &[Account],
+    filter: &TransferFilter,
+    body: &QueryTransactionsBody,
+    newest_transfer_timestamp: SystemTime,
+    oldest_transfer_timestamp: SystemTime,
+) -> http_err::HttpResult<(Vec<tb::Transfer>, HeaderMap)> {
+    let mut headers = HeaderMap::new();
+    let transfers = match body.limit {
+        Some(limit) => {
+            let cursor = body
+                .cursor
+                .as_deref()
+                .map(TransfersCursor::parse)
+                .transpose()
+                .map_err(http_err::bad_error)?;
+            let (transfers, next_cursor) = page_account_transfers(
+                state,
+                accounts,
+                filter,
+                newest_transfer_timestamp,
+                oldest_transfer_timestamp,
+                cursor,
+                limit as usize,
+            )
+            .await?;
+            if let Some(next_cursor) = next_cursor {
+                headers.insert(
+                    NEXT_CURSOR_HEADER,
+                    next_cursor
+                        .to_string()
+                        .parse()
+                        .map_err(http_err::internal_error)?,
+                );
+            }
+            transfers
+        }
+        None => {
+            let mut transfers: Vec<tb::Transfer> = Vec::new();
+            let mut cursor = None;
+            loop {
+                let (page, next_cursor) = page_account_transfers(
+                    state,
+                    accounts,
+                    filter,
+                    newest_transfer_timestamp,
+                    oldest_transfer_timestamp,
+                    cursor,
+                    MAX_TRANSACTIONS_PAGE as usize,
+                )
+                .await?;
+                transfers.extend(page);
+                cursor = next_cursor;
+                if cursor.is_none() {
+                    break;
+                }
+            }
+            transfers
+        }
+    };
+    Ok((transfers, headers))
+}
+
+#[utoipa::path(post, path = "/query/account-transactions", params(AmountParams), responses(
+    (status = 200, description = "Returns list of transactions by filter", body=Vec<responses::Transaction>, headers(
+        ("next-cursor" = String, description = "Cursor of the next page when `limit` is set and there are more transactions"),
+    )),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_account_transactions(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryTransactionsBody>,
+) -> Result<(HeaderMap, Json<responses::ResponseTransactions>), http_err::HttpErr> {
+    body.validate().map_err(http_err::bad_error)?;
+
+    let (newest_transfer_timestamp, oldest_transfer_timestamp) = body.timestamps()?;
+    let filter = TransferFilter::new(&state, &body, params.amount_format).await?;
+    let accounts: Vec<Account> = state
+        .store
+        .find_accounts_re(body.accounts_glob.clone())
+        .await?;
+    // println!(
+    //     "accounts found: {}",
+    //     accounts.iter().map(|a| a.tb_id.clone()).join(", ")
+    // );
+
+    let (transfers, headers) = read_account_transfers(
+        &state,
+        &accounts,
+        &filter,
+        &body,
+        newest_transfer_timestamp,
+        oldest_transfer_timestamp,
+    )
+    .await?;
+
+    let transactions =
+        transfers_to_transactions(state.store.as_ref(), &accounts, transfers.iter()).await?;
+
+    // println!("transactions len {}", transactions.len());
+
+    Ok((
+        headers,
+        Json(
+            transactions
+                .into_iter()
+                .map(|t| t.with_format(params.amount_format))
+                .collect(),
+        ),
+    ))
+}
+
+#[utoipa::path(post, path = "/query/account-register", params(AmountParams), responses(
+    (status = 200, description = "Returns each posting to the accounts with the balance of its account after it, sorted by timestamp", body = responses::ResponseRegister, headers(
+        ("next-cursor" = String, description = "Cursor of the next page when `limit` is set and there are more transactions"),
+    )),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_account_register(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryTransactionsBody>,
+) -> Result<(HeaderMap, Json<responses::ResponseRegister>), http_err::HttpErr> {
+    body.validate().map_err(http_err::bad_error)?;
+
+    let (newest_transfer_timestamp, oldest_transfer_timestamp) = body.timestamps()?;
+    let filter = TransferFilter::new(&state, &body, params.amount_format).await?;
+    let accounts: Vec<Account> = state
+        .store
+        .find_accounts_re(body.accounts_glob.clone())
+        .await?;
+    let commodities = state
+        .store
+        .list_all_commodities()
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect::<HashMap<i32, models::Commodities>>();
+
+    let (transfers, headers) = read_account_transfers(
+        &state,
+        &accounts,
+        &filter,
+        &body,
+        newest_transfer_timestamp,
+        oldest_transfer_timestamp,
+    )
+    .await?;
+    let accounts_by_id = accounts
+        .iter()
+        .map(|a| (from_hex_string(a.tb_id.as_str()), a))
+        .collect::<HashMap<u128, &Account>>();
+
+    // the ledger keeps the balance after every transfer, so only the balances between the first
+    // and last transfer of the page are read
+    let mut balances: HashMap<(u128, SystemTime), i128> = HashMap::new();
+    if let (Some(first), Some(last)) = (transfers.first(), transfers.last()) {
+        let tb_ids = transfers
+            .iter()
+            .flat_map(|t| [t.debit_account_id(), t.credit_account_id()])
+            .filter(|id| accounts_by_id.contains_key(id))
+            .unique()
+            .collect::<Vec<u128>>();
+        for tb_id in tb_ids {
+            for balance in
+                collect_account_balances(&state, tb_id, last.timestamp(), first.timestamp()).await?
+            {
+                let amount = responses::debits_sub_credits(
+                    balance.debits_posted(),
+                    balance.credits_posted(),
+                )
+                .map_err(http_err::internal_error)?;
+                balances.insert((tb_id, balance.timestamp()), amount);
+            }
+        }
+    }
+
+    // (account, transfer, balance after the transfer)
+    let mut postings: Vec<(&Account, tb::Transfer, i128)> = Vec::new();
+    for transfer in transfers {
+        for tb_id in [transfer.debit_account_id(), transfer.credit_account_id()] {
+            let Some(account) = accounts_by_id.get(&tb_id) else {
+                continue;
+            };
+            if !filter.matches(&transfer, &HashSet::from([tb_id])) {
+                continue;
+            }
+            let balance =
+                balances
+                    .get(&(tb_id, transfer.timestamp()))
+                    .ok_or(http_err::internal_error(anyhow!(
+                        "logical error no balance of {} after transfer {}",
+                        account.name,
+                        to_hex_string(transfer.id())
+                    )))?;
+            postings.push((account, transfer, *balance));
+        }
+    }
+    postings.sort_by(|a, b| {
+        Ord::cmp(&a.1.timestamp(), &b.1.timestamp()).then_with(|| a.0.name.cmp(&b.0.name))
+    });
+
+    let counterparts = state
+        .store
+        .find_accounts_by_tb_ids(
+            postings
+                .iter()
+                .flat_map(|(_, t, _)| [t.debit_account_id(), t.credit_account_id()])
+                .unique()
+                .map(to_hex_string)
+                .collect(),
+        )
+        .await?
+        .into_iter()
+        .map(|a| (from_hex_string(a.tb_id.as_str()), a.name))
+        .collect::<HashMap<u128, String>>();
+
+    let register = postings
+        .into_iter()
+        .map(|(account, transfer, balance)| {
+            let commodity =
+                commodities
+                    .get(&account.commodities_id)
+                    .ok_or(http_err::internal_error(anyhow!(
+                        "logical error unable to find commodity {}",
+                        account.commodities_id
+                    )))?;
+            let is_debit = transfer.debit_account_id() == from_hex_string(account.tb_id.as_str());
+            // pending and voided transfers leave the posted balance as is
+            let posted = match transfer.flags().intersects(
+                tb::transfer::Flags::PENDING | tb::transfer::Flags::VOID_PENDING_TRANSFER,
+            ) {
+                true => 0,
+                false => responses::to_i128(transfer.amount()).map_err(http_err::internal_error)?,
+            };
+            let (amount, counterpart_id) = if is_debit {
+                (posted, transfer.credit_account_id())
+            } else {
+                (-posted, transfer.debit_account_id())
+            };
+            Ok(responses::RegisterPosting {
+                account_name: account.name.clone(),
+                counterpart_account: counterparts
+                    .get(&counterpart_id)
+                    .cloned()
+                    .unwrap_or_default(),
+                amount: amount.into(),
+                balance: balance.into(),
+                full_date: transfer
+                    .timestamp()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(http_err::internal_error)?
+                    .as_millis() as i64,
+                full_date2: transfer.user_data_64() as i64,
+                code: transfer.code() as i32,
+                related_id: to_hex_string(transfer.user_data_128()),
+                transfer_id: to_hex_string(transfer.id()),
+                status: responses::TransferStatus::from_tb_flags(transfer.flags()),
+                commodity_unit: commodity.unit.clone(),
+                commodity_decimal: commodity.decimal_place,
+            }
+            .with_format(params.amount_format))
+        })
+        .collect::<http_err::HttpResult<Vec<_>>>()?;
+
+    Ok((headers, Json(register)))
+}
+
+/// Collects all transfers of the accounts between both timestamps, a zero timestamp is unbounded.
+async fn collect_account_transfers(
+    state: &AppState,
+    accounts: &[Account],
+    newest_transfer_timestamp: SystemTime,
+    oldest_transfer_timestamp: SystemTime,
+) -> http_err::HttpResult<HashMap<u128, tb::Transfer>> {
+    // collect all transfers in a hashmap
     let mut transfers: HashMap<u128, tb::Transfer> = HashMap::new();
     for account in accounts.iter() {
         // get transfers per account
         let account_tb_id = from_hex_string(account.tb_id.as_str());
 
-        let flags = tb::core::account::FilterFlags::DEBITS
-            | tb::core::account::FilterFlags::CREDITS
-            | tb::core::account::FilterFlags::REVERSED;
-        // println!("getting account {account_tb_id} transfers");
+        let flags = tb::core::account::FilterFlags::DEBITS
+            | tb::core::account::FilterFlags::CREDITS
+            | tb::core::account::FilterFlags::REVERSED;
+        // println!("getting account {account_tb_id} transfers");
+
+        // loops around and collects more than the TB_MAX_BATCH_SIZE if possible
+        let mut is_response_larger_than_tb_max_batch_size = true;
+        let mut previous_transfer_timestamp = newest_transfer_timestamp;
+        while is_response_larger_than_tb_max_batch_size {
+            let filter = tb::core::account::Filter::new(account_tb_id, TB_MAX_BATCH_SIZE)
+                .with_flags(flags)
+                .with_timestamp_max(previous_transfer_timestamp)
+                .with_timestamp_min(oldest_transfer_timestamp);
+            let transfers_data: Vec<tb::core::Transfer> = state
+                .ledger
+                .get_account_transfers(filter)
+                .await
+                .map_err(http_err::storage_error)?;
+            // println!("found transfer data len {}", transfers_data.len());
+
+            is_response_larger_than_tb_max_batch_size =
+                transfers_data.len() > (TB_MAX_BATCH_SIZE as usize) - 1;
+            let transfers_data_last_index = match transfers_data.len() {
+                i if i > 0 => i - 1,
+                _ => 0,
+            };
+            for (i, transfer_data) in transfers_data.iter().enumerate() {
+                let id = transfer_data.id();
+                transfers.entry(id).or_insert(*transfer_data);
+                if transfers_data_last_index == i {
+                    previous_transfer_timestamp = transfer_data
+                        .timestamp()
+                        .checked_sub(Duration::from_nanos(1))
+                        .ok_or(http_err::internal_error(ValidationError::new("time")))?;
+                }
+            }
+        }
+    }
+    Ok(transfers)
+}
+
+/// Returns the oldest `limit` transfers of the accounts matching the filter after the cursor, and
+/// the cursor of the next page if there are more. Each account is read from the cursor on until
+/// `limit + 1` transfers match, no more are needed from each account to find the first `limit + 1`
+/// of all accounts.
+async fn page_account_transfers(
+    state: &AppState,
+    accounts: &[Account],
+    filter: &TransferFilter,
+    newest_transfer_timestamp: SystemTime,
+    oldest_transfer_timestamp: SystemTime,
+    cursor: Option<TransfersCursor>,
+    limit: usize,
+) -> http_err::HttpResult<(Vec<tb::Transfer>, Option<TransfersCursor>)> {
+    let account_ids = accounts
+        .iter()
+        .filter(|a| {
+            filter
+                .ledger
+                .is_none_or(|ledger| ledger == a.commodities_id as u32)
+        })
+        .map(|a| from_hex_string(a.tb_id.as_str()))
+        .collect::<HashSet<u128>>();
+    // reads of a single account, or none for one read of all transfers
+    let reads: Vec<Option<u128>> = match filter.is_query() {
+        true if account_ids.is_empty() => Vec::new(),
+        true => vec![None],
+        false => account_ids.iter().copied().map(Some).sorted().collect(),
+    };
+    // one more than the limit and the transfer at the cursor itself, when every transfer matches
+    let batch_size = match filter.is_narrowed() {
+        true => (limit as u32 + 2).min(TB_MAX_BATCH_SIZE),
+        false => TB_MAX_BATCH_SIZE,
+    };
+
+    let mut transfers: BTreeMap<TransfersCursor, tb::Transfer> = BTreeMap::new();
+    for read in reads {
+        let mut timestamp_min = match cursor {
+            Some(cursor) => {
+                oldest_transfer_timestamp.max(UNIX_EPOCH + Duration::from_nanos(cursor.timestamp))
+            }
+            None => oldest_transfer_timestamp,
+        };
+        loop {
+            let batch = match read {
+                Some(account_id) => {
+                    let account_filter = tb::account::Filter::new(account_id, batch_size)
+                        .with_flags(filter.account_flags())
+                        .with_timestamp_max(newest_transfer_timestamp)
+                        .with_timestamp_min(timestamp_min);
+                    state.ledger.get_account_transfers(account_filter).await
+                }
+                None => {
+                    let query_filter = filter
+                        .query_filter(batch_size)
+                        .with_timestamp_max(newest_transfer_timestamp)
+                        .with_timestamp_min(timestamp_min);
+                    state.ledger.query_transfers(query_filter).await
+                }
+            }
+            .map_err(http_err::storage_error)?;
+            let Some(last) = batch.last().map(TransfersCursor::of) else {
+                break;
+            };
+            let is_last_batch = batch.len() < batch_size as usize;
+            transfers.extend(
+                batch
+                    .into_iter()
+                    .map(|t| (TransfersCursor::of(&t), t))
+                    .filter(|(position, t)| {
+                        cursor.is_none_or(|cursor| *position > cursor)
+                            && filter.matches(t, &account_ids)
+                    }),
+            );
+            // transfers past the first limit + 1 of all accounts can not be in this page
+            while transfers.len() > limit + 1 {
+                transfers.pop_last();
+            }
+            let is_page_full =
+                transfers.len() > limit && transfers.keys().next_back().is_some_and(|p| *p <= last);
+            if is_last_batch || is_page_full {
+                break;
+            }
+            timestamp_min = UNIX_EPOCH + Duration::from_nanos(last.timestamp + 1);
+        }
+    }
+
+    let next_cursor = match transfers.len() > limit {
+        true => {
+            transfers.pop_last();
+            transfers.keys().next_back().copied()
+        }
+        false => None,
+    };
+    Ok((transfers.into_values().collect(), next_cursor))
+}
+
+/// Collects the balance of the account after each of its transfers between both timestamps, a zero
+/// timestamp is unbounded.
+async fn collect_account_balances(
+    state: &AppState,
+    account_tb_id: u128,
+    newest_transfer_timestamp: SystemTime,
+    oldest_transfer_timestamp: SystemTime,
+) -> http_err::HttpResult<Vec<tb::account::Balance>> {
+    let mut balances: Vec<tb::account::Balance> = Vec::new();
+    let mut timestamp_min = oldest_transfer_timestamp;
+    loop {
+        let filter = tb::account::Filter::new(account_tb_id, TB_MAX_BATCH_SIZE)
+            .with_flags(tb::account::FilterFlags::DEBITS | tb::account::FilterFlags::CREDITS)
+            .with_timestamp_max(newest_transfer_timestamp)
+            .with_timestamp_min(timestamp_min);
+        let page = state
+            .ledger
+            .get_account_balances(filter)
+            .await
+            .map_err(http_err::storage_error)?;
+        let is_last_page = page.len() < TB_MAX_BATCH_SIZE as usize;
+        if let Some(last) = page.last() {
+            timestamp_min = last
+                .timestamp()
+                .checked_add(Duration::from_nanos(1))
+                .ok_or(http_err::internal_error(ValidationError::new("time")))?;
+        }
+        balances.extend(page);
+        if is_last_page {
+            return Ok(balances);
+        }
+    }
+}
+
+/// Converts transfers to transactions sorted by timestamp, looking up any counter accounts not listed.
+async fn transfers_to_transactions<'a>(
+    store: &dyn Store,
+    accounts: &[Account],
+    transfers: impl Iterator<Item = &'a tb::Transfer>,
+) -> http_err::HttpResult<responses::ResponseTransactions> {
+    let commodities = store.list_all_commodities().await?;
+    let commodities = commodities
+        .iter()
+        .map(|c| (c.id as u32, c))
+        .collect::<HashMap<_, _>>();
+
+    // println!(
+    //     "commodities found: '{}'",
+    //     commodities.iter().map(|a| a.0).join(", ")
+    // );
+
+    let transfers = transfers
+        .sorted_by(|a, b| Ord::cmp(&a.timestamp(), &b.timestamp()))
+        .collect::<Vec<_>>();
+
+    // collect all accounts
+    let mut accounts = accounts
+        .iter()
+        .map(|a| (from_hex_string(a.tb_id.as_str()), a))
+        .collect::<HashMap<u128, &Account>>();
+    let mut missing_account_tb_ids: Vec<String> = Vec::new();
+    for transfer in transfers.iter() {
+        for account_tb_id in [transfer.credit_account_id(), transfer.debit_account_id()].iter() {
+            if !accounts.contains_key(account_tb_id) {
+                missing_account_tb_ids.push(to_hex_string(*account_tb_id));
+            }
+        }
+    }
+
+    let more_accounts = store
+        .find_accounts_by_tb_ids(missing_account_tb_ids)
+        .await?;
+    more_accounts.iter().for_each(|a| {
+        accounts.insert(from_hex_string(a.tb_id.as_str()), a);
+    });
+
+    transfers
+        .iter()
+        .map(|transfer| {
+            let commodity = commodities
+                .get(&(transfer.ledger()))
+                .expect("logical error unable to find commodity from transfer");
+            responses::Transaction::from_tb(**transfer, accounts.clone(), commodity)
+                .map_err(|_| http_err::internal_error(ValidationError::new("err")))
+        })
+        .collect::<HttpResult<responses::ResponseTransactions>>()
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct QueryPendingTransactionsBody {
+    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
+    accounts_glob: String,
+}
+
+#[utoipa::path(post, path = "/query/pending-transactions", params(AmountParams), responses(
+    (status = 200, description = "Returns list of pending transactions not yet posted, voided or expired by filter", body=Vec<responses::Transaction>),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_pending_transactions(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryPendingTransactionsBody>,
+) -> Result<Json<responses::ResponseTransactions>, http_err::HttpErr> {
+    body.validate().map_err(http_err::bad_error)?;
+
+    let accounts: Vec<Account> = state.store.find_accounts_re(body.accounts_glob).await?;
+    let account_tb_ids = accounts
+        .iter()
+        .map(|a| from_hex_string(a.tb_id.as_str()))
+        .collect::<HashSet<u128>>();
+
+    // only pending transfers and the transfers resolving them are read, not the account history
+    let mut transfers: Vec<tb::Transfer> = Vec::new();
+    let mut timestamp_min = UNIX_EPOCH;
+    loop {
+        let filter = tb::QueryFilter::new(TB_MAX_BATCH_SIZE)
+            .with_user_data_32(tb_utils::USER_DATA_32_PENDING)
+            .with_timestamp_min(timestamp_min);
+        let page = state
+            .ledger
+            .query_transfers(filter)
+            .await
+            .map_err(http_err::storage_error)?;
+        let is_last_page = page.len() < TB_MAX_BATCH_SIZE as usize;
+        if let Some(last) = page.last() {
+            timestamp_min = last
+                .timestamp()
+                .checked_add(Duration::from_nanos(1))
+                .ok_or(http_err::internal_error(ValidationError::new("time")))?;
+        }
+        transfers.extend(page.into_iter().filter(|t| {
+            account_tb_ids.contains(&t.debit_account_id())
+                || account_tb_ids.contains(&t.credit_account_id())
+        }));
+        if is_last_page {
+            break;
+        }
+    }
+
+    let resolved_pending_ids = transfers
+        .iter()
+        .filter(|t| {
+            t.flags().intersects(
+                tb::transfer::Flags::POST_PENDING_TRANSFER
+                    | tb::transfer::Flags::VOID_PENDING_TRANSFER,
+            )
+        })
+        .map(|t| t.pending_id())
+        .collect::<HashSet<u128>>();
+    let now = SystemTime::now();
+    let pending_transfers = transfers.iter().filter(|t| {
+        t.flags().contains(tb::transfer::Flags::PENDING)
+            && !resolved_pending_ids.contains(&t.id())
+            && (t.timeout() == 0 || t.timestamp() + Duration::from_secs(t.timeout() as u64) > now)
+    });
+
+    let transactions =
+        transfers_to_transactions(state.store.as_ref(), &accounts, pending_transfers).await?;
+
+    Ok(Json(
+        transactions
+            .into_iter()
+            .map(|t| t.with_format(params.amount_format))
+            .collect(),
+    ))
+}
+
+#[utoipa::path(post, path = "/query/commodities-all", responses(
+    (status = 200, description = "Returns list of commodities sorted by unit", body=responses::ResponseCommodities),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_commodities_all(
+    State(state): State<AppState>,
+) -> Result<Json<responses::ResponseCommodities>, http_err::HttpErr> {
+    let res = state
+        .store
+        .list_all_commodities()
+        .await?
+        .into_iter()
+        .map(responses::Commodity::from)
+        .collect();
+
+    Ok(Json(res))
+}
+
+// #[debug_handler]
+#[utoipa::path(put, path = "/mutate/commodity", responses(
+    (status = 200, description = "Returns the created or updated commodity", body = responses::ResponseCommodity),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 409, description = "Decimal place can not change once the commodity has transactions", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
+))]
+pub async fn mutate_commodity(
+    State(state): State<AppState>,
+    Json(body): Json<responses::RequestCommodity>,
+) -> http_err::HttpResult<Json<responses::ResponseCommodity>> {
+    if !state.allow_add {
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
+    }
+
+    body.validate().map_err(http_err::bad_error)?;
+
+    let existing = state.store.find_commodity(body.unit.clone()).await?;
+    if let Some(existing) = existing.filter(|c| c.decimal_place != body.decimal_place) {
+        // amounts are stored in the smallest unit, rescaling would change every transaction
+        let filter = tb::QueryFilter::new(1).with_ledger(existing.id as u32);
+        let transfers = state
+            .ledger
+            .query_transfers(filter)
+            .await
+            .map_err(http_err::storage_error)?;
+        if !transfers.is_empty() {
+            return Err(http_err::conflict_error(anyhow!(
+                "commodity {} has transactions, its decimal place can not change",
+                existing.unit
+            )));
+        }
+    }
+
+    let commodity = state
+        .store
+        .save_commodity(models::CommodityChangeset {
+            unit: body.unit,
+            decimal_place: body.decimal_place,
+            symbol: body.symbol,
+            name: body.name,
+            iso_code: body.iso_code,
+        })
+        .await?;
+
+    Ok(Json(commodity.into()))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct QueryAccountBalancesBody {
+    date: Option<usize>,
+    /// commodity unit to value each balance in at the balance date
+    value_in: Option<String>,
+    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
+    accounts_glob: String,
+}
+
+#[utoipa::path(post, path = "/query/account-balances", params(AmountParams), responses(
+    (status = 200, description = "Returns list of account balances by filter", body=Vec<responses::Balance>),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_account_balances(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryAccountBalancesBody>,
+) -> Result<Json<responses::ResponseBalances>, http_err::HttpErr> {
+    if !RE_ACCOUNTS_GLOB.is_match(&body.accounts_glob) {
+        return Err(http_err::bad_error(ValidationError::new(
+            "invalid accounts search",
+        )));
+    }
+
+    let accounts: Vec<Account> = state.store.find_accounts_re(body.accounts_glob).await?;
+    // println!(
+    //     "accounts found: {}",
+    //     accounts.iter().map(|a| a.id).join(", ")
+    // );
+    let all_commodities = state.store.list_all_commodities().await?;
+    let commodities = all_commodities
+        .iter()
+        .map(|c| (c.id as u32, c))
+        .collect::<HashMap<_, _>>();
+
+    let balance_date = match body.date {
+        Some(date) => date as i64,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(http_err::internal_error)?
+            .as_millis() as i64,
+    };
+    let valuation = match &body.value_in {
+        Some(value_in) => {
+            Some(Valuation::load(&state, &all_commodities, value_in, balance_date).await?)
+        }
+        None => None,
+    };
+
+    let mut balances: Vec<responses::Balance> = Vec::new();
+
+    let ids = accounts
+        .iter()
+        .map(|a| from_hex_string(a.tb_id.as_str()))
+        .collect::<Vec<_>>();
+
+    if let Some(date) = body.date {
+        // show balance by date
+        let timestamp_max = SystemTime::UNIX_EPOCH
+            .checked_add(Duration::from_millis(date as u64))
+            .expect("invalid time")
+            // fills nano seconds to max
+            .checked_add(Duration::from_nanos(999_999))
+            .expect("invalid time");
+        for account in accounts.iter() {
+            let filter = tb::account::Filter::new(
+                tb_utils::u128::from_hex_string(account.tb_id.as_str()),
+                1,
+            )
+            .with_flags(
+                tb::account::FilterFlags::CREDITS
+                    | tb::account::FilterFlags::DEBITS
+                    | tb::account::FilterFlags::REVERSED,
+            )
+            .with_timestamp_max(timestamp_max);
+            let tb_account_balance: Vec<tb::account::Balance> = state
+                .ledger
+                .get_account_balances(filter)
+                .await
+                .map_err(http_err::storage_error)?;
+
+            let commodity = commodities
+                .iter()
+                .find(|c| *(c.0) == (account.commodities_id as u32))
+                .expect("logical error unable to find commodity");
+            let commodity_unit = commodity.1.unit.clone();
+            let commodity_decimal = commodity.1.decimal_place;
+
+            let (amount, debits_pending, credits_pending) = match tb_account_balance.first() {
+                Some(tb_account_balance_first) => (
+                    responses::debits_sub_credits(
+                        tb_account_balance_first.debits_posted(),
+                        tb_account_balance_first.credits_posted(),
+                    )
+                    .map_err(http_err::internal_error)?,
+                    responses::to_i128(tb_account_balance_first.debits_pending())
+                        .map_err(http_err::internal_error)?,
+                    responses::to_i128(tb_account_balance_first.credits_pending())
+                        .map_err(http_err::internal_error)?,
+                ),
+                None => (0, 0, 0),
+            };
+
+            balances.push(responses::Balance {
+                account_name: account.name.clone(),
+                amount: amount.into(),
+                debits_pending: debits_pending.into(),
+                credits_pending: credits_pending.into(),
+                value: valuation
+                    .as_ref()
+                    .map(|v| v.value(account, commodity.1, amount, balance_date))
+                    .transpose()?,
+                commodity_unit,
+                commodity_decimal,
+            });
+        }
+    } else {
+        //show balance total
+        let tb_accounts: Vec<tb::core::account::Account> = state
+            .ledger
+            .lookup_accounts(ids)
+            .await
+            .map_err(http_err::storage_error)?;
+
+        for tb_account in tb_accounts.iter() {
+            let amount = responses::debits_sub_credits(
+                tb_account.debits_posted(),
+                tb_account.credits_posted(),
+            )
+            .map_err(http_err::internal_error)?;
+            let tb_account_id = to_hex_string(tb_account.id());
+            let account = accounts
+                .iter()
+                .find(|a| a.tb_id == tb_account_id)
+                .expect("logical error unable to find account");
+
+            let commodity = commodities
+                .iter()
+                .find(|c| *(c.0) == (account.commodities_id as u32))
+                .expect("logical error unable to find commodity");
+            let commodity_unit = commodity.1.unit.clone();
+            let commodity_decimal = commodity.1.decimal_place;
+
+            balances.push(responses::Balance {
+                account_name: account.name.clone(),
+                amount: amount.into(),
+                debits_pending: responses::to_i128(tb_account.debits_pending())
+                    .map_err(http_err::internal_error)?
+                    .into(),
+                credits_pending: responses::to_i128(tb_account.credits_pending())
+                    .map_err(http_err::internal_error)?
+                    .into(),
+                value: valuation
+                    .as_ref()
+                    .map(|v| v.value(account, commodity.1, amount, balance_date))
+                    .transpose()?,
+                commodity_unit,
+                commodity_decimal,
+            });
+        }
+    }
+
+    Ok(Json(
+        balances
+            .into_iter()
+            .map(|b| b.with_format(params.amount_format))
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct QueryAccountIncomeStatementBody {
+    #[validate(length(min = 1))]
+    dates: Vec<usize>,
+    /// commodity unit to value the amounts in at each date
+    value_in: Option<String>,
+    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
+    accounts_glob: String,
+}
+
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/account-income-statements", params(AmountParams), responses(
+    (status = 200, description = "Returns list of balances by filter by date", body=responses::ResponseIncomeStatements),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_account_income_statement(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryAccountIncomeStatementBody>,
+) -> http_err::HttpResult<Json<responses::ResponseIncomeStatements>> {
+    body.validate().map_err(http_err::bad_error)?;
+
+    if !RE_ACCOUNTS_GLOB.is_match(&body.accounts_glob) {
+        return Err(http_err::bad_error(ValidationError::new(
+            "invalid accounts search",
+        )));
+    }
 
-        // loops around and collects more than the TB_MAX_BATCH_SIZE if possible
-        let mut is_response_larger_than_tb_max_batch_size = true;
-        let mut previous_transfer_timestamp = UNIX_EPOCH
-            .checked_add(Duration::from_millis(body.date_newest as u64))
-            .ok_or(http_err::internal_error(ValidationError::new(
-                "i64 unix nano date max",
-            )))?;
-        let oldest_transfer_timestamp = UNIX_EPOCH
-            .checked_add(Duration::from_millis(body.date_oldest as u64))
-            .ok_or(http_err::internal_error(ValidationError::new(
-                "i64 unix nano date max",
-            )))?;
-        while is_response_larger_than_tb_max_batch_size {
-            let filter = tb::core::account::Filter::new(account_tb_id, TB_MAX_BATCH_SIZE)
-                .with_flags(flags)
-                .with_timestamp_max(previous_transfer_timestamp)
-                .with_timestamp_min(oldest_transfer_timestamp);
-            let transfers_data: Vec<tb::core::Transfer> = state
-                .tb
-                .get_account_transfers(Box::new(filter))
+    let accounts: Vec<Account> = state.store.find_accounts_re(body.accounts_glob).await?;
+    // println!(
+    //     "accounts found: {}",
+    //     accounts.iter().map(|a| a.id).join(", ")
+    // );
+    let all_commodities = state.store.list_all_commodities().await?;
+    let commodities = all_commodities
+        .iter()
+        .map(|c| (c.id as u32, c))
+        .collect::<HashMap<_, _>>();
+    let valuation = match &body.value_in {
+        Some(value_in) => {
+            let date_newest = body.dates.iter().max().copied().unwrap_or_default() as i64;
+            Some(Valuation::load(&state, &all_commodities, value_in, date_newest).await?)
+        }
+        None => None,
+    };
+
+    let dates: Vec<SystemTime> = body
+        .dates
+        .iter()
+        .map(|date| {
+            SystemTime::UNIX_EPOCH
+                .checked_add(Duration::from_millis(*date as u64))
+                .expect("invalid time")
+                // fills nano seconds to max
+                .checked_add(Duration::from_nanos(999_999))
+                .expect("invalid time")
+        })
+        .collect();
+
+    let mut income_statements: Vec<responses::IncomeStatement> = Vec::new();
+    // show balance by date
+    let dates_len = dates.len();
+    for account in accounts.iter() {
+        let mut amounts = Vec::with_capacity(dates_len);
+        let commodity = commodities
+            .iter()
+            .find(|c| *(c.0) == (account.commodities_id as u32))
+            .expect("logical error unable to find commodity");
+        let commodity_unit = commodity.1.unit.clone();
+        let commodity_decimal = commodity.1.decimal_place;
+        for timestamp_max in dates.iter() {
+            let filter = tb::account::Filter::new(
+                tb_utils::u128::from_hex_string(account.tb_id.as_str()),
+                1,
+            )
+            .with_flags(
+                tb::account::FilterFlags::CREDITS
+                    | tb::account::FilterFlags::DEBITS
+                    | tb::account::FilterFlags::REVERSED,
+            )
+            .with_timestamp_max(*timestamp_max);
+            let tb_account_balance: Vec<tb::account::Balance> = state
+                .ledger
+                .get_account_balances(filter)
                 .await
-                .map_err(http_err::internal_error)?;
-            // println!("found transfer data len {}", transfers_data.len());
+                .map_err(http_err::storage_error)?;
 
-            is_response_larger_than_tb_max_batch_size =
-                transfers_data.len() > (TB_MAX_BATCH_SIZE as usize) - 1;
-            let transfers_data_last_index = match transfers_data.len() {
-                i if i > 0 => i - 1,
-                _ => 0,
+            let amount = match tb_account_balance.first() {
+                Some(tb_account_balance_first) => responses::debits_sub_credits(
+                    tb_account_balance_first.debits_posted(),
+                    tb_account_balance_first.credits_posted(),
+                )
+                .map_err(http_err::internal_error)?,
+                None => 0,
             };
-            for (i, transfer_data) in transfers_data.iter().enumerate() {
-                let id = transfer_data.id();
-                transfers.entry(id).or_insert(*transfer_data);
-                if transfers_data_last_index == i {
-                    previous_transfer_timestamp = transfer_data
-                        .timestamp()
-                        .checked_sub(Duration::from_nanos(1))
-                        .ok_or(http_err::internal_error(ValidationError::new("time")))?;
+            amounts.push(amount);
+        }
+        let values = match &valuation {
+            Some(v) => Some(v.values(account, commodity.1, &amounts, &body.dates)?),
+            None => None,
+        };
+        income_statements.push(
+            responses::IncomeStatement {
+                account_name: account.name.clone(),
+                amounts: amounts.into_iter().map(responses::Amount::from).collect(),
+                commodity_unit,
+                commodity_decimal,
+                values,
+            }
+            .with_format(params.amount_format),
+        );
+    }
+    Ok(Json(responses::ResponseIncomeStatements {
+        dates: body.dates,
+        income_statements,
+    }))
+}
+
+/// Returns the posted debits and credits of each account by tigerbeetle id, `as_of` returns them
+/// at the end of that unix time millisecond.
+async fn posted_balances(
+    state: &AppState,
+    accounts: &[Account],
+    as_of: Option<usize>,
+) -> http_err::HttpResult<HashMap<u128, (u128, u128)>> {
+    let ids = accounts
+        .iter()
+        .map(|a| from_hex_string(a.tb_id.as_str()))
+        .collect::<Vec<u128>>();
+    let mut balances: HashMap<u128, (u128, u128)> = HashMap::with_capacity(ids.len());
+    match as_of {
+        Some(date) => {
+            let timestamp_max = SystemTime::UNIX_EPOCH
+                .checked_add(Duration::from_millis(date as u64))
+                .and_then(|t| t.checked_add(Duration::from_nanos(999_999)))
+                .ok_or(http_err::bad_error(anyhow!("invalid date {}", date)))?;
+            for id in ids {
+                let filter = tb::account::Filter::new(id, 1)
+                    .with_flags(
+                        tb::account::FilterFlags::CREDITS
+                            | tb::account::FilterFlags::DEBITS
+                            | tb::account::FilterFlags::REVERSED,
+                    )
+                    .with_timestamp_max(timestamp_max);
+                let balance = state
+                    .ledger
+                    .get_account_balances(filter)
+                    .await
+                    .map_err(http_err::storage_error)?
+                    .first()
+                    .map(|b| (b.debits_posted(), b.credits_posted()))
+                    .unwrap_or((0, 0));
+                balances.insert(id, balance);
+            }
+        }
+        None => {
+            for chunk in ids.chunks(TB_MAX_BATCH_SIZE as usize) {
+                let tb_accounts = state
+                    .ledger
+                    .lookup_accounts(chunk.to_vec())
+                    .await
+                    .map_err(http_err::storage_error)?;
+                for a in tb_accounts {
+                    balances.insert(a.id(), (a.debits_posted(), a.credits_posted()));
                 }
             }
         }
     }
+    Ok(balances)
+}
 
-    // collect all accounts
-    let mut accounts = accounts
-        .iter()
-        .map(|a| (from_hex_string(a.tb_id.as_str()), a))
-        .collect::<HashMap<u128, &Account>>();
-    let mut missing_account_tb_ids: Vec<String> = Vec::new();
-    for transfer in transfers
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct QueryBalanceSheetBody {
+    /// unix time milliseconds, defaults to the current balances
+    as_of: Option<usize>,
+    /// deepest account level listed, 1 only lists the totals of each section
+    #[validate(range(min = 1))]
+    depth: Option<usize>,
+}
+
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/balance-sheet", params(AmountParams), responses(
+    (status = 200, description = "Returns assets, liabilities and equity rolled up by account hierarchy", body = responses::ResponseBalanceSheet),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_balance_sheet(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryBalanceSheetBody>,
+) -> http_err::HttpResult<Json<responses::ResponseBalanceSheet>> {
+    use models::AccountType;
+
+    body.validate().map_err(http_err::bad_error)?;
+
+    let accounts = state.store.find_accounts_re(String::from("**")).await?;
+    let balances = posted_balances(&state, &accounts, body.as_of).await?;
+    let commodities = state
+        .store
+        .list_all_commodities()
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect::<HashMap<i32, models::Commodities>>();
+
+    // amounts are positive on the normal side of their account type
+    let mut amounts: HashMap<AccountType, Vec<(&str, i32, i128)>> = HashMap::new();
+    for account in accounts.iter() {
+        let account_type =
+            AccountType::read(account.name.as_str()).map_err(http_err::internal_error)?;
+        let (debits, credits) = balances
+            .get(&from_hex_string(account.tb_id.as_str()))
+            .copied()
+            .unwrap_or((0, 0));
+        let amount = responses::debits_sub_credits(debits, credits)
+            .map_err(http_err::internal_error)?
+            .checked_mul(account_type.sign())
+            .ok_or(http_err::internal_error(anyhow!(
+                "balance of {} overflows",
+                account.name
+            )))?;
+        amounts.entry(account_type).or_default().push((
+            account.name.as_str(),
+            account.commodities_id,
+            amount,
+        ));
+    }
+    let rolled_up = |account_type: AccountType, depth: Option<usize>| {
+        reports::roll_up(
+            amounts.get(&account_type).cloned().unwrap_or_default(),
+            depth,
+        )
+        .map_err(http_err::internal_error)
+    };
+    let commodity = |commodities_id: i32| {
+        commodities
+            .get(&commodities_id)
+            .ok_or(http_err::internal_error(anyhow!(
+                "logical error unable to find commodity {}",
+                commodities_id
+            )))
+    };
+
+    let mut sections: Vec<responses::BalanceSheetSection> = Vec::new();
+    for account_type in [
+        AccountType::Assets,
+        AccountType::Liabilities,
+        AccountType::Equity,
+    ] {
+        let rows = rolled_up(account_type, body.depth)?
+            .into_iter()
+            .map(|r| {
+                let c = commodity(r.commodities_id)?;
+                Ok(responses::BalanceSheetRow {
+                    account_name: r.account_name,
+                    depth: r.depth,
+                    amount: r.amount.into(),
+                    commodity_unit: c.unit.clone(),
+                    commodity_decimal: c.decimal_place,
+                })
+            })
+            .collect::<http_err::HttpResult<Vec<_>>>()?;
+        sections.push(responses::BalanceSheetSection { account_type, rows });
+    }
+
+    // totals of each account type by commodity
+    let mut totals: HashMap<(AccountType, i32), i128> = HashMap::new();
+    for account_type in [
+        AccountType::Assets,
+        AccountType::Liabilities,
+        AccountType::Equity,
+        AccountType::Revenues,
+        AccountType::Expenses,
+    ] {
+        for r in rolled_up(account_type, Some(1))? {
+            totals.insert((account_type, r.commodities_id), r.amount);
+        }
+    }
+    let total = |account_type: AccountType, commodities_id: i32| {
+        totals
+            .get(&(account_type, commodities_id))
+            .copied()
+            .unwrap_or(0)
+    };
+    let overflow = || http_err::internal_error(anyhow!("balance sheet total overflows"));
+    let mut checks: Vec<responses::BalanceSheetCheck> = Vec::new();
+    for commodities_id in accounts.iter().map(|a| a.commodities_id).unique().sorted() {
+        let c = commodity(commodities_id)?;
+        let assets = total(AccountType::Assets, commodities_id);
+        let liabilities = total(AccountType::Liabilities, commodities_id);
+        let equity = total(AccountType::Equity, commodities_id);
+        let net_income = total(AccountType::Revenues, commodities_id)
+            .checked_sub(total(AccountType::Expenses, commodities_id))
+            .ok_or_else(overflow)?;
+        let claims = liabilities
+            .checked_add(equity)
+            .and_then(|v| v.checked_add(net_income))
+            .ok_or_else(overflow)?;
+        checks.push(responses::BalanceSheetCheck {
+            commodity_unit: c.unit.clone(),
+            commodity_decimal: c.decimal_place,
+            assets: assets.into(),
+            liabilities: liabilities.into(),
+            equity: equity.into(),
+            net_income: net_income.into(),
+            balanced: assets == claims,
+        });
+    }
+
+    Ok(Json(
+        responses::BalanceSheet {
+            as_of: body.as_of,
+            sections,
+            checks,
+        }
+        .with_format(params.amount_format),
+    ))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct QueryIncomeStatementBody {
+    period: responses::Period,
+    /// unix time milliseconds, the first period is the one containing this date
+    start: i64,
+    /// unix time milliseconds, exclusive
+    end: i64,
+    /// IANA timezone the periods start at midnight in, e.g. "Europe/Amsterdam", defaults to UTC
+    timezone: Option<String>,
+    /// deepest account level listed, 1 only lists the totals of each section
+    #[validate(range(min = 1))]
+    depth: Option<usize>,
+}
+
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/income-statement", params(AmountParams), responses(
+    (status = 200, description = "Returns the revenues, expenses and net income of each period by the time the transfers were added to the ledger", body = responses::ResponseIncomeStatement),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_income_statement(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryIncomeStatementBody>,
+) -> http_err::HttpResult<Json<responses::ResponseIncomeStatement>> {
+    use models::AccountType;
+
+    body.validate().map_err(http_err::bad_error)?;
+
+    let timezone = body.timezone.as_deref().unwrap_or("UTC");
+    let timezone = timezone
+        .parse::<chrono_tz::Tz>()
+        .map_err(|_| http_err::bad_error(anyhow!("invalid timezone {}", timezone)))?;
+    let periods = reports::periods(body.period, body.start, body.end, &timezone)
+        .map_err(http_err::bad_error)?;
+    // periods are of tigerbeetle timestamps like the balance sheet and cash flow, so only the
+    // transfers of the periods are read
+    let timestamp = |date: i64| {
+        u64::try_from(date)
+            .ok()
+            .and_then(|date| UNIX_EPOCH.checked_add(Duration::from_millis(date)))
+            .ok_or(http_err::bad_error(anyhow!("invalid date {}", date)))
+    };
+    let oldest_transfer_timestamp = timestamp(periods[0].0)?;
+    let newest_transfer_timestamp = timestamp(periods[periods.len() - 1].1)?
+        .checked_sub(Duration::from_nanos(1))
+        .ok_or(http_err::bad_error(anyhow!("invalid end {}", body.end)))?;
+
+    let accounts = state
+        .store
+        .find_accounts_re(String::from("r:**|x:**"))
+        .await?;
+    let commodities = state
+        .store
+        .list_all_commodities()
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect::<HashMap<i32, models::Commodities>>();
+    let overflow = || http_err::internal_error(anyhow!("income statement total overflows"));
+
+    // change of each account in each period, positive on the normal side of its account type
+    let mut changes: Vec<(AccountType, &Account, Vec<i128>)> = Vec::with_capacity(accounts.len());
+    for account in accounts.iter() {
+        let account_type =
+            AccountType::read(account.name.as_str()).map_err(http_err::internal_error)?;
+        let tb_id = from_hex_string(account.tb_id.as_str());
+        let mut amounts = vec![0i128; periods.len()];
+        let transfers = collect_account_transfers(
+            &state,
+            std::slice::from_ref(account),
+            newest_transfer_timestamp,
+            oldest_transfer_timestamp,
+        )
+        .await?;
+        for t in transfers.values() {
+            let flags = t.flags();
+            if flags.contains(tb::transfer::Flags::PENDING)
+                || flags.contains(tb::transfer::Flags::VOID_PENDING_TRANSFER)
+            {
+                continue;
+            }
+            let date = t
+                .timestamp()
+                .duration_since(UNIX_EPOCH)
+                .map_err(http_err::internal_error)?
+                .as_millis() as i64;
+            let index = periods.partition_point(|(start, _)| *start <= date);
+            if index == 0 || date >= periods[index - 1].1 {
+                continue;
+            }
+            let amount = responses::to_i128(t.amount()).map_err(http_err::internal_error)?;
+            let amount = if t.debit_account_id() == tb_id {
+                amount
+            } else {
+                -amount
+            };
+            amounts[index - 1] = amount
+                .checked_mul(account_type.sign())
+                .and_then(|a| amounts[index - 1].checked_add(a))
+                .ok_or_else(overflow)?;
+        }
+        changes.push((account_type, account, amounts));
+    }
+
+    let mut sections: Vec<responses::IncomeStatementSection> = Vec::new();
+    // totals of each section by commodity
+    let mut totals: HashMap<(AccountType, i32), Vec<i128>> = HashMap::new();
+    for account_type in [AccountType::Revenues, AccountType::Expenses] {
+        let mut rows: BTreeMap<(String, i32), (usize, Vec<i128>)> = BTreeMap::new();
+        for i in 0..periods.len() {
+            let rolled_up = reports::roll_up(
+                changes
+                    .iter()
+                    .filter(|(t, _, _)| *t == account_type)
+                    .map(|(_, a, amounts)| (a.name.as_str(), a.commodities_id, amounts[i])),
+                body.depth,
+            )
+            .map_err(http_err::internal_error)?;
+            for r in rolled_up {
+                rows.entry((r.account_name, r.commodities_id))
+                    .or_insert((r.depth, vec![0; periods.len()]))
+                    .1[i] = r.amount;
+            }
+        }
+        let rows = rows
+            .into_iter()
+            .map(|((account_name, commodities_id), (depth, amounts))| {
+                let c = commodities
+                    .get(&commodities_id)
+                    .ok_or(http_err::internal_error(anyhow!(
+                        "logical error unable to find commodity {}",
+                        commodities_id
+                    )))?;
+                if depth == 1 {
+                    totals.insert((account_type, commodities_id), amounts.clone());
+                }
+                Ok(responses::IncomeStatementRow {
+                    account_name,
+                    depth,
+                    total: checked_sum(&amounts).ok_or_else(overflow)?.into(),
+                    amounts: amounts.into_iter().map(responses::Amount::from).collect(),
+                    commodity_unit: c.unit.clone(),
+                    commodity_decimal: c.decimal_place,
+                })
+            })
+            .collect::<http_err::HttpResult<Vec<_>>>()?;
+        sections.push(responses::IncomeStatementSection { account_type, rows });
+    }
+
+    let mut net_income: Vec<responses::NetIncome> = Vec::new();
+    for c in commodities
         .values()
-        .sorted_by(|a, b| Ord::cmp(&a.timestamp(), &b.timestamp()))
+        .filter(|c| accounts.iter().any(|a| a.commodities_id == c.id))
+        .sorted_by(|a, b| a.unit.cmp(&b.unit))
     {
-        for account_tb_id in [transfer.credit_account_id(), transfer.debit_account_id()].iter() {
-            if !accounts.contains_key(account_tb_id) {
-                missing_account_tb_ids.push(to_hex_string(*account_tb_id));
+        let zeros = vec![0; periods.len()];
+        let revenues = totals.get(&(AccountType::Revenues, c.id)).unwrap_or(&zeros);
+        let expenses = totals.get(&(AccountType::Expenses, c.id)).unwrap_or(&zeros);
+        let amounts = revenues
+            .iter()
+            .zip(expenses.iter())
+            .map(|(r, x)| r.checked_sub(*x).ok_or_else(overflow))
+            .collect::<http_err::HttpResult<Vec<i128>>>()?;
+        net_income.push(responses::NetIncome {
+            commodity_unit: c.unit.clone(),
+            commodity_decimal: c.decimal_place,
+            total: checked_sum(&amounts).ok_or_else(overflow)?.into(),
+            amounts: amounts.into_iter().map(responses::Amount::from).collect(),
+        });
+    }
+
+    Ok(Json(
+        responses::IncomeStatementReport {
+            period: body.period,
+            timezone: timezone.name().to_string(),
+            periods: periods
+                .into_iter()
+                .map(|(start, end)| responses::ReportPeriod { start, end })
+                .collect(),
+            sections,
+            net_income,
+        }
+        .with_format(params.amount_format),
+    ))
+}
+
+fn checked_sum(amounts: &[i128]) -> Option<i128> {
+    amounts.iter().try_fold(0i128, |sum, a| sum.checked_add(*a))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct QueryCashFlowBody {
+    date_newest: usize,
+    date_oldest: usize,
+    /// accounts holding cash, e.g. `a:bank:**`
+    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
+    cash_accounts_glob: String,
+    /// rules classifying the counterpart accounts, checked before the default rules where
+    /// revenues and expenses are operating, assets investing and liabilities and equity financing
+    #[serde(default)]
+    #[validate(nested)]
+    rules: Vec<responses::CashFlowRule>,
+}
+
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/cash-flow", params(AmountParams), responses(
+    (status = 200, description = "Returns the inflow and outflow of the cash accounts by activity of the counterpart account", body = responses::ResponseCashFlow),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_cash_flow(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+    Json(body): Json<QueryCashFlowBody>,
+) -> http_err::HttpResult<Json<responses::ResponseCashFlow>> {
+    use responses::CashFlowActivity;
+
+    body.validate().map_err(http_err::bad_error)?;
+
+    let accounts: Vec<Account> = state
+        .store
+        .find_accounts_re(body.cash_accounts_glob)
+        .await?;
+    let newest_transfer_timestamp = UNIX_EPOCH
+        .checked_add(Duration::from_millis(body.date_newest as u64))
+        .ok_or(http_err::internal_error(ValidationError::new(
+            "i64 unix nano date max",
+        )))?;
+    let oldest_transfer_timestamp = UNIX_EPOCH
+        .checked_add(Duration::from_millis(body.date_oldest as u64))
+        .ok_or(http_err::internal_error(ValidationError::new(
+            "i64 unix nano date max",
+        )))?;
+    let transfers = collect_account_transfers(
+        &state,
+        &accounts,
+        newest_transfer_timestamp,
+        oldest_transfer_timestamp,
+    )
+    .await?;
+
+    // transfers between two cash accounts do not change the cash
+    let cash_ids = accounts
+        .iter()
+        .map(|a| from_hex_string(a.tb_id.as_str()))
+        .collect::<HashSet<u128>>();
+    let mut legs: Vec<(u128, u32, i128)> = Vec::new();
+    for t in transfers.values() {
+        let flags = t.flags();
+        if flags.contains(tb::transfer::Flags::PENDING)
+            || flags.contains(tb::transfer::Flags::VOID_PENDING_TRANSFER)
+        {
+            continue;
+        }
+        let amount = responses::to_i128(t.amount()).map_err(http_err::internal_error)?;
+        match (
+            cash_ids.contains(&t.debit_account_id()),
+            cash_ids.contains(&t.credit_account_id()),
+        ) {
+            (true, false) => legs.push((t.credit_account_id(), t.ledger(), amount)),
+            (false, true) => legs.push((t.debit_account_id(), t.ledger(), -amount)),
+            _ => {}
+        }
+    }
+
+    let counterparts = state
+        .store
+        .find_accounts_by_tb_ids(
+            legs.iter()
+                .map(|(id, _, _)| *id)
+                .unique()
+                .map(to_hex_string)
+                .collect(),
+        )
+        .await?
+        .into_iter()
+        .map(|a| (from_hex_string(a.tb_id.as_str()), a.name))
+        .collect::<HashMap<u128, String>>();
+    let commodities = state
+        .store
+        .list_all_commodities()
+        .await?
+        .into_iter()
+        .map(|c| (c.id as u32, c))
+        .collect::<HashMap<u32, models::Commodities>>();
+
+    let mut rules = body.rules;
+    rules.extend(reports::default_cash_flow_rules());
+    let overflow = || http_err::internal_error(anyhow!("cash flow total overflows"));
+    // inflow and outflow by activity, None are the totals
+    let mut flows: BTreeMap<(Option<CashFlowActivity>, String), (i32, i128, i128)> =
+        BTreeMap::new();
+    for (id, ledger, amount) in legs {
+        let account_name = counterparts
+            .get(&id)
+            .ok_or(http_err::internal_error(anyhow!(
+                "logical error unable to find account {}",
+                to_hex_string(id)
+            )))?;
+        let activity = reports::classify(account_name, &rules).ok_or(http_err::internal_error(
+            anyhow!("logical error unable to classify {}", account_name),
+        ))?;
+        let commodity = commodities
+            .get(&ledger)
+            .ok_or(http_err::internal_error(anyhow!(
+                "logical error unable to find commodity {}",
+                ledger
+            )))?;
+        for key in [Some(activity), None] {
+            let flow = flows.entry((key, commodity.unit.clone())).or_insert((
+                commodity.decimal_place,
+                0,
+                0,
+            ));
+            if amount > 0 {
+                flow.1 = flow.1.checked_add(amount).ok_or_else(overflow)?;
+            } else {
+                flow.2 = flow.2.checked_sub(amount).ok_or_else(overflow)?;
+            }
+        }
+    }
+    let amounts = |activity: Option<CashFlowActivity>| {
+        flows
+            .iter()
+            .filter(|((a, _), _)| *a == activity)
+            .map(|((_, unit), (decimal, inflow, outflow))| {
+                Ok(responses::CashFlowAmounts {
+                    commodity_unit: unit.clone(),
+                    commodity_decimal: *decimal,
+                    inflow: (*inflow).into(),
+                    outflow: (*outflow).into(),
+                    net: inflow.checked_sub(*outflow).ok_or_else(overflow)?.into(),
+                })
+            })
+            .collect::<http_err::HttpResult<Vec<_>>>()
+    };
+
+    let mut sections: Vec<responses::CashFlowSection> = Vec::new();
+    for activity in [
+        CashFlowActivity::Operating,
+        CashFlowActivity::Investing,
+        CashFlowActivity::Financing,
+    ] {
+        sections.push(responses::CashFlowSection {
+            activity,
+            amounts: amounts(Some(activity))?,
+        });
+    }
+
+    Ok(Json(
+        responses::CashFlow {
+            sections,
+            totals: amounts(None)?,
+        }
+        .with_format(params.amount_format),
+    ))
+}
+
+/// Lists every tigerbeetle account by paging through them in timestamp order.
+async fn query_all_tb_accounts(state: &AppState) -> http_err::HttpResult<Vec<tb::Account>> {
+    let mut tb_accounts: Vec<tb::Account> = Vec::new();
+    let mut timestamp_min = UNIX_EPOCH;
+    loop {
+        let filter = tb::QueryFilter::new(TB_MAX_BATCH_SIZE).with_timestamp_min(timestamp_min);
+        let page = state
+            .ledger
+            .query_accounts(filter)
+            .await
+            .map_err(http_err::storage_error)?;
+        let is_last_page = page.len() < TB_MAX_BATCH_SIZE as usize;
+        if let Some(last) = page.last() {
+            timestamp_min = last
+                .timestamp()
+                .checked_add(Duration::from_nanos(1))
+                .ok_or(http_err::internal_error(ValidationError::new("time")))?;
+        }
+        tb_accounts.extend(page);
+        if is_last_page {
+            return Ok(tb_accounts);
+        }
+    }
+}
+
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/trial-balance", params(AmountParams), responses(
+    (status = 200, description = "Returns the posted debits and credits of every account, the totals of each ledger and the accounts missing in the accounts table or tigerbeetle", body = responses::ResponseTrialBalance),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+))]
+pub async fn query_trial_balance(
+    State(state): State<AppState>,
+    Query(params): Query<AmountParams>,
+) -> http_err::HttpResult<Json<responses::ResponseTrialBalance>> {
+    let accounts = state.store.find_accounts_re(String::from("**")).await?;
+    let commodities = state
+        .store
+        .list_all_commodities()
+        .await?
+        .into_iter()
+        .map(|c| (c.id as u32, c))
+        .collect::<HashMap<u32, models::Commodities>>();
+    let tb_accounts = query_all_tb_accounts(&state)
+        .await?
+        .into_iter()
+        .map(|a| (a.id(), a))
+        .collect::<HashMap<u128, tb::Account>>();
+    let overflow = || http_err::internal_error(anyhow!("trial balance total overflows"));
+
+    let mut orphans: Vec<responses::OrphanAccount> = Vec::new();
+    let mut trial_accounts: Vec<responses::TrialBalanceAccount> = Vec::new();
+    let mut tb_ids: HashSet<u128> = HashSet::with_capacity(accounts.len());
+    for account in accounts.iter() {
+        let tb_id = from_hex_string(account.tb_id.as_str());
+        tb_ids.insert(tb_id);
+        let commodity =
+            commodities
+                .get(&(account.commodities_id as u32))
+                .ok_or(http_err::internal_error(anyhow!(
+                    "logical error unable to find commodity {}",
+                    account.commodities_id
+                )))?;
+        let (debits, credits) = match tb_accounts.get(&tb_id) {
+            Some(tb_account) => {
+                if tb_account.ledger() != account.commodities_id as u32 {
+                    orphans.push(responses::OrphanAccount {
+                        kind: responses::OrphanKind::LedgerMismatch,
+                        tb_id: account.tb_id.clone(),
+                        account_name: Some(account.name.clone()),
+                        ledger: Some(tb_account.ledger()),
+                    });
+                }
+                (tb_account.debits_posted(), tb_account.credits_posted())
             }
+            None => {
+                orphans.push(responses::OrphanAccount {
+                    kind: responses::OrphanKind::MissingInLedger,
+                    tb_id: account.tb_id.clone(),
+                    account_name: Some(account.name.clone()),
+                    ledger: None,
+                });
+                (0, 0)
+            }
+        };
+        trial_accounts.push(responses::TrialBalanceAccount {
+            account_name: account.name.clone(),
+            tb_id: account.tb_id.clone(),
+            debits: responses::to_i128(debits)
+                .map_err(http_err::internal_error)?
+                .into(),
+            credits: responses::to_i128(credits)
+                .map_err(http_err::internal_error)?
+                .into(),
+            commodity_unit: commodity.unit.clone(),
+            commodity_decimal: commodity.decimal_place,
+        });
+    }
+
+    let mut totals: BTreeMap<u32, (u128, u128)> = BTreeMap::new();
+    for tb_account in tb_accounts.values().sorted_by_key(|a| a.timestamp()) {
+        if !tb_ids.contains(&tb_account.id()) {
+            orphans.push(responses::OrphanAccount {
+                kind: responses::OrphanKind::MissingInStore,
+                tb_id: to_hex_string(tb_account.id()),
+                account_name: None,
+                ledger: Some(tb_account.ledger()),
+            });
+        }
+        let total = totals.entry(tb_account.ledger()).or_insert((0, 0));
+        total.0 = total
+            .0
+            .checked_add(tb_account.debits_posted())
+            .ok_or_else(overflow)?;
+        total.1 = total
+            .1
+            .checked_add(tb_account.credits_posted())
+            .ok_or_else(overflow)?;
+    }
+    let ledgers = totals
+        .into_iter()
+        .map(|(ledger, (debits, credits))| {
+            let commodity = commodities.get(&ledger);
+            Ok(responses::TrialBalanceLedger {
+                ledger,
+                commodity_unit: commodity.map(|c| c.unit.clone()),
+                commodity_decimal: commodity.map(|c| c.decimal_place).unwrap_or(0),
+                debits: responses::to_i128(debits)
+                    .map_err(http_err::internal_error)?
+                    .into(),
+                credits: responses::to_i128(credits)
+                    .map_err(http_err::internal_error)?
+                    .into(),
+                balanced: debits == credits,
+            })
+        })
+        .collect::<http_err::HttpResult<Vec<_>>>()?;
+
+    Ok(Json(
+        responses::TrialBalance {
+            accounts: trial_accounts,
+            ledgers,
+            orphans,
         }
+        .with_format(params.amount_format),
+    ))
+}
+
+/// Values amounts in the commodity requested by `value_in`.
+struct Valuation<'a> {
+    book: prices::PriceBook,
+    target: &'a models::Commodities,
+}
+
+impl<'a> Valuation<'a> {
+    /// Loads all prices to or from `value_in` up to `date_newest`.
+    async fn load(
+        state: &AppState,
+        commodities: &'a [models::Commodities],
+        value_in: &str,
+        date_newest: i64,
+    ) -> http_err::HttpResult<Valuation<'a>> {
+        let target = commodities
+            .iter()
+            .find(|c| c.unit == value_in)
+            .ok_or(http_err::bad_error(anyhow!(
+                "unknown commodity {}",
+                value_in
+            )))?;
+        let found = state
+            .store
+            .find_prices(Some(vec![target.id]), i64::MIN, date_newest)
+            .await?;
+        Ok(Valuation {
+            book: prices::PriceBook::new(found, commodities),
+            target,
+        })
     }
 
-    let more_accounts = models::find_accounts_by_tb_ids(&conn, missing_account_tb_ids).await?;
-    more_accounts.iter().for_each(|a| {
-        accounts.insert(from_hex_string(a.tb_id.as_str()), a);
-    });
+    fn amount(
+        &self,
+        account: &Account,
+        commodity: &models::Commodities,
+        amount: i128,
+        date: i64,
+    ) -> http_err::HttpResult<responses::Amount> {
+        self.book
+            .value(amount, commodity.id, self.target.id, date)
+            .map(responses::Amount::from)
+            .map_err(|err| {
+                http_err::bad_error(anyhow!(
+                    "unable to value {} {} of {} in {} at {}, {}",
+                    amount,
+                    commodity.unit,
+                    account.name,
+                    self.target.unit,
+                    date,
+                    err
+                ))
+            })
+    }
 
-    let transactions = transfers
-        .iter()
-        .sorted_by(|(_, a), (_, b)| Ord::cmp(&a.timestamp(), &b.timestamp()))
-        .map(|(_, transfer)| {
-            let commodity = commodities
-                .get(&(transfer.ledger()))
-                .expect("logical error unable to find commodity from transfer");
-            responses::Transaction::from_tb(*transfer, accounts.clone(), commodity)
-                .map_err(|_| http_err::internal_error(ValidationError::new("err")))
+    fn value(
+        &self,
+        account: &Account,
+        commodity: &models::Commodities,
+        amount: i128,
+        date: i64,
+    ) -> http_err::HttpResult<responses::MarketValue> {
+        Ok(responses::MarketValue {
+            amount: self.amount(account, commodity, amount, date)?,
+            commodity_unit: self.target.unit.clone(),
+            commodity_decimal: self.target.decimal_place,
         })
-        .collect::<HttpResult<responses::ResponseTransactions>>()?;
+    }
 
-    // println!("transactions len {}", transactions.len());
+    fn values(
+        &self,
+        account: &Account,
+        commodity: &models::Commodities,
+        amounts: &[i128],
+        dates: &[usize],
+    ) -> http_err::HttpResult<responses::MarketValues> {
+        Ok(responses::MarketValues {
+            amounts: amounts
+                .iter()
+                .zip(dates.iter())
+                .map(|(amount, date)| self.amount(account, commodity, *amount, *date as i64))
+                .collect::<http_err::HttpResult<Vec<responses::Amount>>>()?,
+            commodity_unit: self.target.unit.clone(),
+            commodity_decimal: self.target.decimal_place,
+        })
+    }
+}
 
-    Ok(Json(transactions))
+/// Saves prices, creating the commodities that do not exist yet.
+async fn save_prices(
+    state: &AppState,
+    new_prices: Vec<responses::Price>,
+) -> http_err::HttpResult<()> {
+    let mut rows: Vec<models::Price> = Vec::with_capacity(new_prices.len());
+    for p in new_prices {
+        if p.commodity_unit == p.quote_unit {
+            return Err(http_err::bad_error(anyhow!(
+                "price of {} can not be in itself",
+                p.commodity_unit
+            )));
+        }
+        let commodity = state
+            .store
+            .find_or_create_commodity(p.commodity_unit)
+            .await?;
+        let quote = state.store.find_or_create_commodity(p.quote_unit).await?;
+        rows.push(models::Price {
+            commodities_id: commodity.id,
+            quote_commodities_id: quote.id,
+            date: p.date,
+            price: p.price,
+        });
+    }
+    state.store.save_prices(rows).await
 }
 
-#[utoipa::path(post, path = "/query/commodities-all", responses(
-    (status = 200, description = "Returns list of commodities", body=Vec<String>),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+// #[debug_handler]
+#[utoipa::path(put, path = "/mutate/prices", responses(
+    (status = 200, description = "Returns status 200 when the prices are saved, prices of the same commodities and date are replaced"),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
 ))]
-pub async fn query_commodities_all(
+pub async fn mutate_prices(
     State(state): State<AppState>,
-) -> Result<Json<responses::ResponseCommodities>, http_err::HttpErr> {
-    let conn = state.pool.get().await.map_err(http_err::internal_error)?;
-    let res = list_all_commodity_units(&conn).await?;
+    Json(body): Json<responses::RequestPrices>,
+) -> http_err::HttpResult<Json<()>> {
+    if !state.allow_add {
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
+    }
 
-    Ok(Json(res))
+    body.validate().map_err(http_err::bad_error)?;
+
+    save_prices(&state, body.prices).await?;
+
+    Ok(Json(()))
 }
 
 #[derive(Deserialize, ToSchema, Validate)]
-pub struct QueryAccountBalancesBody {
-    date: Option<usize>,
-    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
-    accounts_glob: String,
+pub struct QueryPricesBody {
+    /// only prices of or in this commodity unit
+    commodity_unit: Option<String>,
+    /// unix time milliseconds
+    date_oldest: Option<i64>,
+    /// unix time milliseconds
+    date_newest: Option<i64>,
 }
 
-#[utoipa::path(post, path = "/query/account-balances", responses(
-    (status = 200, description = "Returns list of account balances by filter", body=Vec<responses::Balance>),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+// #[debug_handler]
+#[utoipa::path(post, path = "/query/prices", responses(
+    (status = 200, description = "Returns list of prices sorted by date", body = Vec<responses::Price>),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
 ))]
-pub async fn query_account_balances(
+pub async fn query_prices(
     State(state): State<AppState>,
-    Json(body): Json<QueryAccountBalancesBody>,
-) -> Result<Json<responses::ResponseBalances>, http_err::HttpErr> {
-    let conn = state.pool.get().await.map_err(http_err::internal_error)?;
-
-    if !RE_ACCOUNTS_GLOB.is_match(&body.accounts_glob) {
-        return Err(http_err::bad_error(ValidationError::new(
-            "invalid accounts search",
-        )));
-    }
-
-    let accounts: Vec<Account> = find_accounts_re(&conn, body.accounts_glob).await?;
-    // println!(
-    //     "accounts found: {}",
-    //     accounts.iter().map(|a| a.id).join(", ")
-    // );
-    let commodities = list_all_commodities(&conn).await?;
-    let commodities = commodities
-        .iter()
-        .map(|c| (c.id as u32, c))
-        .collect::<HashMap<_, _>>();
-
-    let mut balances: Vec<responses::Balance> = Vec::new();
-
-    let ids = accounts
+    Json(body): Json<QueryPricesBody>,
+) -> http_err::HttpResult<Json<responses::ResponsePrices>> {
+    let commodities = state.store.list_all_commodities().await?;
+    let commodities_ids = body.commodity_unit.as_ref().map(|unit| {
+        commodities
+            .iter()
+            .filter(|c| c.unit == *unit)
+            .map(|c| c.id)
+            .collect()
+    });
+    let units = commodities
         .iter()
-        .map(|a| from_hex_string(a.tb_id.as_str()))
-        .collect::<Vec<_>>();
-
-    if let Some(date) = body.date {
-        // show balance by date
-        let timestamp_max = SystemTime::UNIX_EPOCH
-            .checked_add(Duration::from_millis(date as u64))
-            .expect("invalid time")
-            // fills nano seconds to max
-            .checked_add(Duration::from_nanos(999_999))
-            .expect("invalid time");
-        for account in accounts.iter() {
-            let filter = tb::account::Filter::new(
-                tb_utils::u128::from_hex_string(account.tb_id.as_str()),
-                1,
-            )
-            .with_flags(
-                tb::account::FilterFlags::CREDITS
-                    | tb::account::FilterFlags::DEBITS
-                    | tb::account::FilterFlags::REVERSED,
-            )
-            .with_timestamp_max(timestamp_max);
-            let tb_account_balance: Vec<tb::account::Balance> = state
-                .tb
-                .get_account_balances(Box::new(filter))
-                .await
-                .map_err(http_err::internal_error)?;
-
-            let commodity = commodities
-                .iter()
-                .find(|c| *(c.0) == (account.commodities_id as u32))
-                .expect("logical error unable to find commodity");
-            let commodity_unit = commodity.1.unit.clone();
-            let commodity_decimal = commodity.1.decimal_place;
-
-            let amount = match tb_account_balance.first() {
-                Some(tb_account_balance_first) => (tb_account_balance_first.debits_posted() as i64)
-                    .sub(tb_account_balance_first.credits_posted() as i64),
-                None => 0,
-            };
-
-            balances.push(responses::Balance {
-                account_name: account.name.clone(),
-                amount,
-                commodity_unit,
-                commodity_decimal,
-            });
-        }
-    } else {
-        //show balance total
-        let tb_accounts: Vec<tb::core::account::Account> = state
-            .tb
-            .lookup_accounts(ids)
-            .await
-            .map_err(http_err::internal_error)?;
-
-        for tb_account in tb_accounts.iter() {
-            let amount =
-                (tb_account.debits_posted() as i64).sub(tb_account.credits_posted() as i64);
-            let tb_account_id = to_hex_string(tb_account.id());
-            let account = accounts
-                .iter()
-                .find(|a| a.tb_id == tb_account_id)
-                .expect("logical error unable to find account");
-
-            let commodity = commodities
-                .iter()
-                .find(|c| *(c.0) == (account.commodities_id as u32))
-                .expect("logical error unable to find commodity");
-            let commodity_unit = commodity.1.unit.clone();
-            let commodity_decimal = commodity.1.decimal_place;
-
-            balances.push(responses::Balance {
-                account_name: account.name.clone(),
-                amount,
-                commodity_unit,
-                commodity_decimal,
-            });
-        }
-    }
-
-    Ok(Json(balances))
-}
+        .map(|c| (c.id, c.unit.as_str()))
+        .collect::<HashMap<i32, &str>>();
+
+    let found = state
+        .store
+        .find_prices(
+            commodities_ids,
+            body.date_oldest.unwrap_or(i64::MIN),
+            body.date_newest.unwrap_or(i64::MAX),
+        )
+        .await?;
 
-#[derive(Deserialize, Validate, ToSchema)]
-pub struct QueryAccountIncomeStatementBody {
-    #[validate(length(min = 1))]
-    dates: Vec<usize>,
-    #[validate(regex(path=*RE_ACCOUNTS_GLOB))]
-    accounts_glob: String,
+    let unit = |id: &i32| {
+        units
+            .get(id)
+            .map(|unit| String::from(*unit))
+            .ok_or(http_err::internal_error(format!(
+                "commodity {} of a price not found",
+                id
+            )))
+    };
+    Ok(Json(
+        found
+            .into_iter()
+            .map(|p| {
+                Ok(responses::Price {
+                    commodity_unit: unit(&p.commodities_id)?,
+                    quote_unit: unit(&p.quote_commodities_id)?,
+                    date: p.date,
+                    price: p.price,
+                })
+            })
+            .collect::<http_err::HttpResult<_>>()?,
+    ))
 }
 
 // #[debug_handler]
-#[utoipa::path(post, path = "/query/account-income-statements", responses(
-    (status = 200, description = "Returns list of balances by filter by date", body=responses::ResponseIncomeStatements),
-    (status = 400, description = "Bad request error occurred", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+#[utoipa::path(put, path = "/mutate/revalue", params(AmountParams), responses(
+    (status = 200, description = "Returns the unrealized gain or loss of each foreign commodity and the adjusting entries booked, transactions are dated by their ledger timestamp like the balance sheet, revaluing the same date again books nothing", body = responses::ResponseRevalue),
+    (status = 400, description = "Bad request error occurred", body = http_err::HttpErr),
+    (status = 403, description = "Disabled by server configuration", body = http_err::HttpErr),
+    (status = 409, description = "Transactions conflict with existing transactions", body = http_err::HttpErr),
+    (status = 422, description = "Transactions rejected by the ledger", body = http_err::HttpErr),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
+    (status = 503, description = "Ledger unavailable", body = http_err::HttpErr),
 ))]
-pub async fn query_account_income_statement(
+pub async fn mutate_revalue(
     State(state): State<AppState>,
-    Json(body): Json<QueryAccountIncomeStatementBody>,
-) -> http_err::HttpResult<Json<responses::ResponseIncomeStatements>> {
-    body.validate().map_err(http_err::bad_error)?;
+    Query(params): Query<AmountParams>,
+    Json(body): Json<responses::RequestRevalue>,
+) -> http_err::HttpResult<Json<responses::ResponseRevalue>> {
+    if !state.allow_add {
+        return Err(http_err::disabled_error("writing to ledger is disabled"));
+    }
 
-    let conn = state.pool.get().await.map_err(http_err::internal_error)?;
+    body.validate().map_err(http_err::bad_error)?;
 
-    if !RE_ACCOUNTS_GLOB.is_match(&body.accounts_glob) {
-        return Err(http_err::bad_error(ValidationError::new(
-            "invalid accounts search",
-        )));
+    let all_commodities = state.store.list_all_commodities().await?;
+    let find_commodity = |unit: &str| {
+        all_commodities
+            .iter()
+            .find(|c| c.unit == unit)
+            .ok_or(http_err::bad_error(anyhow!("unknown commodity {}", unit)))
+    };
+    let reporting = find_commodity(&body.reporting_unit)?;
+
+    let mut book_prices = state
+        .store
+        .find_prices(Some(vec![reporting.id]), i64::MIN, body.date)
+        .await?;
+    for p in body.prices.iter() {
+        book_prices.push(models::Price {
+            commodities_id: find_commodity(&p.commodity_unit)?.id,
+            quote_commodities_id: find_commodity(&p.quote_unit)?.id,
+            date: p.date,
+            price: p.price.clone(),
+        });
     }
+    let book = prices::PriceBook::new(book_prices, &all_commodities);
+    let value = |amount: i128, commodity: &models::Commodities, date: i64| {
+        book.value(amount, commodity.id, reporting.id, date)
+            .map_err(|err| {
+                http_err::bad_error(anyhow!(
+                    "unable to value {} {} in {} at {}, {}",
+                    amount,
+                    commodity.unit,
+                    reporting.unit,
+                    date,
+                    err
+                ))
+            })
+    };
 
-    let accounts: Vec<Account> = find_accounts_re(&conn, body.accounts_glob).await?;
-    // println!(
-    //     "accounts found: {}",
-    //     accounts.iter().map(|a| a.id).join(", ")
-    // );
-    let commodities = list_all_commodities(&conn).await?;
-    let commodities = commodities
-        .iter()
-        .map(|c| (c.id as u32, c))
-        .collect::<HashMap<_, _>>();
+    let accounts = state
+        .store
+        .find_accounts_re(String::from("a:**|l:**"))
+        .await?;
+    let gain_account = body
+        .gain_account
+        .clone()
+        .unwrap_or(String::from(responses::FX_GAIN_ACCOUNT));
+    let loss_account = body
+        .loss_account
+        .clone()
+        .unwrap_or(String::from(responses::FX_LOSS_ACCOUNT));
+    let period_key = format!("fx-revaluation:{}:{}", reporting.unit, body.date);
+
+    // revaluations of commodities sharing a conversion account would be booked together
+    let mut conversion_units: HashMap<String, &str> = HashMap::new();
+    for commodity in all_commodities.iter().filter(|c| c.id != reporting.id) {
+        let Some(conversion_account) = responses::conversion_account(&commodity.unit) else {
+            continue;
+        };
+        if let Some(other) = conversion_units.insert(conversion_account.clone(), &commodity.unit) {
+            return Err(http_err::bad_error(anyhow!(
+                "commodities {} and {} share the conversion account {}",
+                other,
+                commodity.unit,
+                conversion_account
+            )));
+        }
+    }
 
-    let dates: Vec<SystemTime> = body
-        .dates
-        .iter()
-        .map(|date| {
-            SystemTime::UNIX_EPOCH
-                .checked_add(Duration::from_millis(*date as u64))
-                .expect("invalid time")
-                // fills nano seconds to max
-                .checked_add(Duration::from_nanos(999_999))
-                .expect("invalid time")
-        })
-        .collect();
+    let timestamp_max = SystemTime::UNIX_EPOCH
+        .checked_add(Duration::from_millis(body.date as u64))
+        .and_then(|t| t.checked_add(Duration::from_nanos(999_999)))
+        .ok_or(http_err::bad_error(anyhow!("invalid date {}", body.date)))?;
 
-    let mut income_statements: Vec<responses::IncomeStatement> = Vec::new();
-    // show balance by date
-    let dates_len = dates.len();
-    for account in accounts.iter() {
-        let mut amounts = Vec::with_capacity(dates_len);
-        let commodity = commodities
+    let mut commodities: Vec<responses::RevaluedCommodity> = Vec::new();
+    let mut tranfers: Vec<tb::Transfer> = Vec::new();
+    for commodity in all_commodities.iter().filter(|c| c.id != reporting.id) {
+        let commodity_accounts = accounts
             .iter()
-            .find(|c| *(c.0) == (account.commodities_id as u32))
-            .expect("logical error unable to find commodity");
-        let commodity_unit = commodity.1.unit.clone();
-        let commodity_decimal = commodity.1.decimal_place;
-        for timestamp_max in dates.iter() {
-            let filter = tb::account::Filter::new(
-                tb_utils::u128::from_hex_string(account.tb_id.as_str()),
-                1,
-            )
-            .with_flags(
-                tb::account::FilterFlags::CREDITS
-                    | tb::account::FilterFlags::DEBITS
-                    | tb::account::FilterFlags::REVERSED,
-            )
-            .with_timestamp_max(*timestamp_max);
-            let tb_account_balance: Vec<tb::account::Balance> = state
-                .tb
-                .get_account_balances(Box::new(filter))
-                .await
-                .map_err(http_err::internal_error)?;
+            .filter(|a| a.commodities_id == commodity.id)
+            .cloned()
+            .collect::<Vec<Account>>();
+        if commodity_accounts.is_empty() {
+            continue;
+        }
+        let tb_ids = commodity_accounts
+            .iter()
+            .map(|a| from_hex_string(a.tb_id.as_str()))
+            .collect::<HashSet<u128>>();
+
+        // transfers between two revalued accounts cancel out, like the balance sheet the
+        // transfers are dated by their ledger timestamp
+        let transfers =
+            collect_account_transfers(&state, &commodity_accounts, timestamp_max, UNIX_EPOCH)
+                .await?;
+        let mut balance: i128 = 0;
+        let mut historical_cost: i128 = 0;
+        for t in transfers.values() {
+            let flags = t.flags();
+            let date = t
+                .timestamp()
+                .duration_since(UNIX_EPOCH)
+                .map_err(http_err::internal_error)?
+                .as_millis() as i64;
+            if flags.contains(tb::transfer::Flags::PENDING)
+                || flags.contains(tb::transfer::Flags::VOID_PENDING_TRANSFER)
+            {
+                continue;
+            }
+            let amount = responses::to_i128(t.amount()).map_err(http_err::internal_error)?;
+            let amount = match (
+                tb_ids.contains(&t.debit_account_id()),
+                tb_ids.contains(&t.credit_account_id()),
+            ) {
+                (true, false) => amount,
+                (false, true) => -amount,
+                _ => continue,
+            };
+            balance = balance
+                .checked_add(amount)
+                .ok_or(http_err::internal_error(anyhow!("balance overflows")))?;
+            historical_cost = historical_cost
+                .checked_add(value(amount, commodity, date)?)
+                .ok_or(http_err::internal_error(anyhow!(
+                    "historical cost overflows"
+                )))?;
+        }
+        let current_value = value(balance, commodity, body.date)?;
+        let unrealized =
+            current_value
+                .checked_sub(historical_cost)
+                .ok_or(http_err::internal_error(anyhow!(
+                    "unrealized amount overflows"
+                )))?;
 
-            let amount = match tb_account_balance.first() {
-                Some(tb_account_balance_first) => (tb_account_balance_first.debits_posted() as i64)
-                    .sub(tb_account_balance_first.credits_posted() as i64),
-                None => 0,
+        // earlier revaluations are booked against the conversion account of the commodity
+        // held in the reporting commodity
+        let conversion_account =
+            responses::conversion_account(&commodity.unit).ok_or(http_err::bad_error(anyhow!(
+                "commodity {} has no conversion account",
+                commodity.unit
+            )))?;
+        let transfer_id = tb_utils::u128::from_idempotency_key(
+            "/mutate/revalue",
+            &period_key,
+            commodity.id as usize,
+        );
+        let booked_in_period = !state
+            .ledger
+            .lookup_transfers(vec![transfer_id])
+            .await
+            .map_err(http_err::storage_error)?
+            .is_empty();
+        // the conversion account only holds revaluations, which are booked in date order so
+        // that its balance is the amount booked and its newest transfer is the last revaluation
+        let mut booked: i128 = 0;
+        let mut last_booked: Option<i64> = None;
+        if let Some(account) = state
+            .store
+            .find_account(conversion_account.clone(), reporting.id)
+            .await?
+        {
+            let account_tb_id = from_hex_string(account.tb_id.as_str());
+            if let Some(a) = state
+                .ledger
+                .lookup_accounts(vec![account_tb_id])
+                .await
+                .map_err(http_err::storage_error)?
+                .first()
+            {
+                let debits =
+                    responses::to_i128(a.debits_posted()).map_err(http_err::internal_error)?;
+                let credits =
+                    responses::to_i128(a.credits_posted()).map_err(http_err::internal_error)?;
+                booked = debits
+                    .checked_sub(credits)
+                    .ok_or(http_err::internal_error(anyhow!("booked amount overflows")))?;
+            }
+            let filter = tb::core::account::Filter::new(account_tb_id, 1).with_flags(
+                tb::core::account::FilterFlags::DEBITS
+                    | tb::core::account::FilterFlags::CREDITS
+                    | tb::core::account::FilterFlags::REVERSED,
+            );
+            last_booked = state
+                .ledger
+                .get_account_transfers(filter)
+                .await
+                .map_err(http_err::storage_error)?
+                .first()
+                .map(|t| t.user_data_64() as i64);
+        }
+        if let Some(last_booked) = last_booked.filter(|d| !booked_in_period && *d > body.date) {
+            return Err(http_err::bad_error(anyhow!(
+                "commodity {} is already revalued at {}, dates before the last revaluation can not be revalued",
+                commodity.unit,
+                last_booked
+            )));
+        }
+        let adjustment = if booked_in_period {
+            0
+        } else {
+            unrealized
+                .checked_sub(booked)
+                .ok_or(http_err::internal_error(anyhow!("adjustment overflows")))?
+        };
+
+        if adjustment != 0 {
+            let (debit_account, credit_account) = if adjustment > 0 {
+                (conversion_account, gain_account.clone())
+            } else {
+                (loss_account.clone(), conversion_account)
             };
-            amounts.push(amount);
+            let (debit_account, _) = models::find_or_create_account(
+                state.ledger.as_ref(),
+                state.store.as_ref(),
+                debit_account,
+                reporting.unit.clone(),
+            )
+            .await?;
+            let (credit_account, _) = models::find_or_create_account(
+                state.ledger.as_ref(),
+                state.store.as_ref(),
+                credit_account,
+                reporting.unit.clone(),
+            )
+            .await?;
+            tranfers.push(
+                tb::Transfer::new(transfer_id)
+                    .with_amount(adjustment.unsigned_abs())
+                    .with_code(body.code as u16)
+                    .with_debit_account_id(from_hex_string(debit_account.tb_id.as_str()))
+                    .with_credit_account_id(from_hex_string(credit_account.tb_id.as_str()))
+                    .with_user_data_128(transfer_id)
+                    .with_user_data_64(body.date as u64)
+                    .with_ledger(reporting.id as u32),
+            );
         }
-        income_statements.push(responses::IncomeStatement {
-            account_name: account.name.clone(),
-            amounts,
-            commodity_unit,
-            commodity_decimal,
+
+        commodities.push(responses::RevaluedCommodity {
+            commodity_unit: commodity.unit.clone(),
+            commodity_decimal: commodity.decimal_place,
+            balance: balance.into(),
+            historical_cost: historical_cost.into(),
+            value: current_value.into(),
+            unrealized: unrealized.into(),
+            adjustment: adjustment.into(),
+            transfer_id: (booked_in_period || adjustment != 0).then(|| to_hex_string(transfer_id)),
         });
     }
-    Ok(Json(responses::ResponseIncomeStatements {
-        dates: body.dates,
-        income_statements,
-    }))
+
+    if !tranfers.is_empty() {
+        // all commodities of the period are booked together
+        link_transfers(&mut tranfers);
+        create_transfers(&state, tranfers).await?;
+    }
+
+    Ok(Json(
+        responses::Revaluation {
+            date: body.date,
+            reporting_unit: reporting.unit.clone(),
+            reporting_decimal: reporting.decimal_place,
+            commodities,
+        }
+        .with_format(params.amount_format),
+    ))
 }
 
 // #[debug_handler]
 #[utoipa::path(get, path = "/openapi", responses(
     (status = 200, description = "Returns openapi v3.1 as json", body = String),
-    (status = 500, description = "Internal server error occurred", body = String),
+    (status = 500, description = "Internal server error occurred", body = http_err::HttpErr),
 ))]
 pub async fn get_openapi() -> http_err::HttpResult<Response<Body>> {
     let openapi_json = ApiDoc::openapi()
         .to_pretty_json()
-        .map_err(|e| http_err::bad_error(e))?;
+        .map_err(http_err::bad_error)?;
 
     let res = Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
         .body(Body::from(openapi_json))
-        .unwrap();
+        .map_err(http_err::internal_error)?;
     Ok(res)
 }
 
@@ -15,12 +15,22 @@ diesel::table! {
         id -> Int4,
         unit -> Text,
         decimal_place -> Int4,
+        symbol -> Nullable<Text>,
+        name -> Nullable<Text>,
+        iso_code -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    prices (id) {
+        id -> Int4,
+        commodities_id -> Int4,
+        quote_commodities_id -> Int4,
+        date -> Int8,
+        price -> Text,
     }
 }
 
 diesel::joinable!(accounts -> commodities (commodities_id));
 
-diesel::allow_tables_to_appear_in_same_query!(
-    accounts,
-    commodities,
-);
+diesel::allow_tables_to_appear_in_same_query!(accounts, commodities, prices,);
@@ -0,0 +1,503 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use diesel::{prelude::*, result::Error::NotFound};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use validator::ValidationError;
+
+use crate::{
+    http_err,
+    models::{
+        Account, Commodities, CommodityChangeset, CommodityInsertResponse, CommodityQueryIdUnit,
+        Newcommodity, Price,
+    },
+    responses,
+};
+
+// this embeds the migrations into the application binary
+// the migration paths are relative to the `CARGO_MANIFEST_DIR`
+pub const POSTGRES_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/");
+pub const SQLITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations_sqlite/");
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = http_err::HttpResult<T>> + Send + 'a>>;
+
+/// Account and commodity metadata, implemented for postgres and sqlite.
+///
+/// Balances and transfers are never stored here, only the names linking to the ledger.
+pub trait Store: Send + Sync {
+    fn insert_commodities(
+        &self,
+        new_commodities: Vec<responses::MigrateCommodity>,
+    ) -> StoreFuture<'_, Vec<CommodityInsertResponse>>;
+
+    fn insert_accounts(&self, new_accounts: Vec<Account>) -> StoreFuture<'_, ()>;
+
+    fn list_all_accounts(&self) -> StoreFuture<'_, Vec<String>>;
+
+    fn find_account(&self, name: String, commodities_id: i32) -> StoreFuture<'_, Option<Account>>;
+
+    fn create_account(&self, account: Account) -> StoreFuture<'_, Account>;
+
+    fn find_or_create_commodity(&self, unit: String) -> StoreFuture<'_, Commodities>;
+
+    fn find_commodity(&self, unit: String) -> StoreFuture<'_, Option<Commodities>>;
+
+    /// Inserts the commodity or updates the commodity with the same unit.
+    fn save_commodity(&self, commodity: CommodityChangeset) -> StoreFuture<'_, Commodities>;
+
+    fn find_accounts_re(&self, filter: String) -> StoreFuture<'_, Vec<Account>>;
+
+    fn find_accounts_re_by_commodity(
+        &self,
+        filter: String,
+        commodity_unit: String,
+    ) -> StoreFuture<'_, Vec<Account>>;
+
+    fn find_accounts_by_tb_ids(&self, tb_ids: Vec<String>) -> StoreFuture<'_, Vec<Account>>;
+
+    fn list_all_commodities(&self) -> StoreFuture<'_, Vec<Commodities>>;
+
+    /// Inserts the prices, replacing prices of the same commodities and date.
+    fn save_prices(&self, new_prices: Vec<Price>) -> StoreFuture<'_, ()>;
+
+    /// Lists prices between both dates sorted by date, when `commodities_ids` is set only prices
+    /// with one of the commodities on either side are listed.
+    fn find_prices(
+        &self,
+        commodities_ids: Option<Vec<i32>>,
+        date_oldest: i64,
+        date_newest: i64,
+    ) -> StoreFuture<'_, Vec<Price>>;
+}
+
+/// Connects to `database_url` and runs the pending migrations of its backend.
+///
+/// `postgres://` and `postgresql://` connect to postgres,
+/// `sqlite://<path>` opens a sqlite file and `sqlite://:memory:` a database that lives as long as the process.
+pub async fn connect(database_url: &str) -> Arc<dyn Store> {
+    if let Some(path) = database_url.strip_prefix("sqlite://") {
+        let manager = deadpool_diesel::sqlite::Manager::new(path, deadpool_diesel::Runtime::Tokio1);
+        // sqlite allows a single writer, a single connection also keeps `:memory:` databases shared
+        let pool = deadpool_diesel::sqlite::Pool::builder(manager)
+            .max_size(1)
+            .build()
+            .expect("unable to open sqlite");
+        let conn = pool.get().await.expect("unable to open sqlite pool");
+        conn.interact(|conn| conn.run_pending_migrations(SQLITE_MIGRATIONS).map(|_| ()))
+            .await
+            .expect("unable to send request to sqlite pool")
+            .expect("error running migrations");
+        drop(conn);
+        Arc::new(pool)
+    } else if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        let manager =
+            deadpool_diesel::postgres::Manager::new(database_url, deadpool_diesel::Runtime::Tokio1);
+        let pool = deadpool_diesel::postgres::Pool::builder(manager)
+            .build()
+            .expect("unable to connect to postgres");
+        let conn = pool
+            .get()
+            .await
+            .expect("unable to connect to postgres pool");
+        conn.interact(|conn| conn.run_pending_migrations(POSTGRES_MIGRATIONS).map(|_| ()))
+            .await
+            .expect("unable to send request to postgres pool")
+            .expect("error running migrations");
+        drop(conn);
+        Arc::new(pool)
+    } else {
+        panic!("DATABASE_URL must start with postgres://, postgresql:// or sqlite://")
+    }
+}
+
+/// Implements [`Store`] for a deadpool diesel pool, the queries are the same for every backend.
+macro_rules! impl_store {
+    ($pool:ty) => {
+        impl Store for $pool {
+            fn insert_commodities(
+                &self,
+                new_commodities: Vec<responses::MigrateCommodity>,
+            ) -> StoreFuture<'_, Vec<CommodityInsertResponse>> {
+                Box::pin(async move {
+                    use crate::schema::commodities::dsl::*;
+
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+
+                    // Find commodities that already exist and if the ledger id and unit are equal, ignore from insertion script.
+                    let commodities_id_filter: Vec<i32> =
+                        new_commodities.iter().map(|c| c.id.to_owned()).collect();
+                    let commodities_id_filter_clone = commodities_id_filter.clone();
+                    let new_commodities = {
+                        let old_commodities: Vec<CommodityQueryIdUnit> = conn
+                            .interact(move |conn| {
+                                commodities
+                                    .select(CommodityQueryIdUnit::as_select())
+                                    .filter(id.eq_any(commodities_id_filter_clone))
+                                    .get_results::<CommodityQueryIdUnit>(conn)
+                                    .map_err(http_err::internal_error)
+                            })
+                            .await
+                            .map_err(http_err::internal_error)??;
+                        new_commodities
+                            .iter()
+                            .filter_map(|c| {
+                                let found_existing =
+                                    old_commodities.iter().find(|old_c| old_c.id == c.id);
+                                match found_existing {
+                                    Some(found) => {
+                                        if found.unit != c.unit {
+                                            Some(Err(http_err::bad_error(ValidationError::new(
+                                                "invalid ",
+                                            ))))
+                                        } else {
+                                            None
+                                        }
+                                    }
+                                    None => Some(Ok(c.clone())),
+                                }
+                            })
+                            .collect::<http_err::HttpResult<Vec<responses::MigrateCommodity>>>()
+                    }?;
+
+                    // one insert per row, sqlite does not support batch inserts with on conflict
+                    conn.interact(move |conn| {
+                        conn.transaction(|conn| {
+                            for new_commodity in new_commodities {
+                                diesel::insert_into(commodities)
+                                    .values(new_commodity)
+                                    .on_conflict_do_nothing()
+                                    .execute(conn)?;
+                            }
+                            Ok(())
+                        })
+                        .map_err(|err: diesel::result::Error| http_err::internal_error(err))
+                    })
+                    .await
+                    .map_err(http_err::internal_error)??;
+
+                    conn.interact(move |conn| {
+                        commodities
+                            .select(CommodityInsertResponse::as_select())
+                            .filter(id.eq_any(commodities_id_filter))
+                            .get_results::<CommodityInsertResponse>(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn insert_accounts(&self, new_accounts: Vec<Account>) -> StoreFuture<'_, ()> {
+                Box::pin(async move {
+                    use crate::schema::accounts::dsl::*;
+
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        diesel::insert_into(accounts)
+                            .values(&new_accounts)
+                            .execute(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)??;
+
+                    Ok(())
+                })
+            }
+
+            fn list_all_accounts(&self) -> StoreFuture<'_, Vec<String>> {
+                Box::pin(async move {
+                    use crate::schema::accounts::dsl::*;
+
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(|conn| {
+                        accounts
+                            .distinct()
+                            .select(name)
+                            .order(name)
+                            .load::<String>(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn find_account(
+                &self,
+                account_name: String,
+                commodity_id: i32,
+            ) -> StoreFuture<'_, Option<Account>> {
+                Box::pin(async move {
+                    use crate::schema::accounts::dsl::*;
+
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        accounts
+                            .select(Account::as_select())
+                            .filter(name.eq(account_name))
+                            .filter(commodities_id.eq(commodity_id))
+                            .first(conn)
+                            .optional()
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn create_account(&self, account: Account) -> StoreFuture<'_, Account> {
+                Box::pin(async move {
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        diesel::insert_into(crate::schema::accounts::table)
+                            .values(&account)
+                            .returning(Account::as_returning())
+                            .get_result(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn find_or_create_commodity(
+                &self,
+                commodity_unit: String,
+            ) -> StoreFuture<'_, Commodities> {
+                Box::pin(async move {
+                    use crate::schema::commodities::dsl::*;
+
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    let unit_clone = commodity_unit.clone();
+                    let first_commodity = conn
+                        .interact(|conn| {
+                            commodities
+                                .select(Commodities::as_select())
+                                .filter(unit.eq(commodity_unit))
+                                .first(conn)
+                        })
+                        .await
+                        .map_err(http_err::storage_error)?;
+
+                    match first_commodity {
+                        Ok(v) => Ok(v),
+                        Err(err) => {
+                            if err != NotFound {
+                                log::error!("unable to find commodity: {}", err);
+                                Err(http_err::internal_error(err))
+                            } else {
+                                conn.interact(move |conn| {
+                                    let new_commodity = Newcommodity {
+                                        unit: unit_clone.as_str(),
+                                    };
+                                    diesel::insert_into(crate::schema::commodities::table)
+                                        .values(&new_commodity)
+                                        .returning(Commodities::as_returning())
+                                        .get_result(conn)
+                                        .map_err(http_err::internal_error)
+                                })
+                                .await
+                                .map_err(http_err::internal_error)?
+                            }
+                        }
+                    }
+                })
+            }
+
+            fn find_commodity(
+                &self,
+                commodity_unit: String,
+            ) -> StoreFuture<'_, Option<Commodities>> {
+                Box::pin(async move {
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        use crate::schema::commodities::dsl::*;
+                        commodities
+                            .select(Commodities::as_select())
+                            .filter(unit.eq(commodity_unit))
+                            .first(conn)
+                            .optional()
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn save_commodity(
+                &self,
+                commodity: CommodityChangeset,
+            ) -> StoreFuture<'_, Commodities> {
+                Box::pin(async move {
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        use crate::schema::commodities::dsl::*;
+                        diesel::insert_into(commodities)
+                            .values(&commodity)
+                            .on_conflict(unit)
+                            .do_update()
+                            .set(&commodity)
+                            .returning(Commodities::as_returning())
+                            .get_result(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn find_accounts_re(&self, filter: String) -> StoreFuture<'_, Vec<Account>> {
+                Box::pin(async move {
+                    use crate::schema::accounts::dsl::*;
+
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        let filter = filter.replace("**", "%").replace("*", "_");
+                        let mut q = accounts.into_boxed();
+                        for (i, f) in filter.split("|").enumerate() {
+                            if i == 0 {
+                                q = q.filter(name.like(f));
+                            } else {
+                                q = q.or_filter(name.like(f));
+                            }
+                        }
+                        let q = q.select(Account::as_select()).order((name, commodities_id));
+                        q.get_results::<Account>(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn find_accounts_re_by_commodity(
+                &self,
+                filter: String,
+                commodity_unit: String,
+            ) -> StoreFuture<'_, Vec<Account>> {
+                Box::pin(async move {
+                    use crate::schema::accounts::dsl::*;
+                    use crate::schema::commodities::dsl::{
+                        commodities, id as commodities_table_id, unit,
+                    };
+
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        let filter = filter.replace("**", "%").replace("*", "_");
+                        let mut q = accounts
+                            .inner_join(
+                                commodities.on(commodities_table_id
+                                    .eq(commodities_id)
+                                    .and(unit.eq(commodity_unit))),
+                            )
+                            .into_boxed();
+                        for (i, f) in filter.split("|").enumerate() {
+                            if i == 0 {
+                                q = q.filter(name.like(f));
+                            } else {
+                                q = q.or_filter(name.like(f));
+                            }
+                        }
+                        let q = q.select(Account::as_select()).order((name, commodities_id));
+                        q.get_results::<Account>(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn find_accounts_by_tb_ids(
+                &self,
+                tb_ids: Vec<String>,
+            ) -> StoreFuture<'_, Vec<Account>> {
+                Box::pin(async move {
+                    use crate::schema::accounts::dsl;
+
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(|conn| {
+                        dsl::accounts
+                            .select(Account::as_select())
+                            .filter(dsl::tb_id.eq_any(tb_ids))
+                            .get_results::<Account>(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn list_all_commodities(&self) -> StoreFuture<'_, Vec<Commodities>> {
+                Box::pin(async move {
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        use crate::schema::commodities::dsl::*;
+                        commodities
+                            .select(Commodities::as_select())
+                            .order(unit)
+                            .load(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn save_prices(&self, new_prices: Vec<Price>) -> StoreFuture<'_, ()> {
+                Box::pin(async move {
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        use crate::schema::prices::dsl::*;
+                        conn.transaction(|conn| {
+                            for new_price in new_prices.iter() {
+                                diesel::insert_into(prices)
+                                    .values(new_price)
+                                    .on_conflict((commodities_id, quote_commodities_id, date))
+                                    .do_update()
+                                    .set(price.eq(&new_price.price))
+                                    .execute(conn)?;
+                            }
+                            diesel::QueryResult::Ok(())
+                        })
+                        .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+
+            fn find_prices(
+                &self,
+                commodities_ids: Option<Vec<i32>>,
+                date_oldest: i64,
+                date_newest: i64,
+            ) -> StoreFuture<'_, Vec<Price>> {
+                Box::pin(async move {
+                    let conn = self.get().await.map_err(http_err::storage_error)?;
+                    conn.interact(move |conn| {
+                        use crate::schema::prices::dsl::*;
+                        let mut q = prices
+                            .select(Price::as_select())
+                            .filter(date.between(date_oldest, date_newest))
+                            .into_boxed();
+                        if let Some(ids) = commodities_ids {
+                            q = q.filter(
+                                commodities_id
+                                    .eq_any(ids.clone())
+                                    .or(quote_commodities_id.eq_any(ids)),
+                            );
+                        }
+                        q.order((date, commodities_id, quote_commodities_id))
+                            .load(conn)
+                            .map_err(http_err::internal_error)
+                    })
+                    .await
+                    .map_err(http_err::internal_error)?
+                })
+            }
+        }
+    };
+}
+
+impl_store!(deadpool_diesel::postgres::Pool);
+impl_store!(deadpool_diesel::sqlite::Pool);
@@ -1,5 +1,14 @@
 use itertools::Itertools;
 use tigerbeetle_unofficial as tb;
+
+/// user_data_32 marking a transfer as a reversal, the id of the reversed transfer follows from its
+/// id with `u128::from_reversal_id`
+pub const USER_DATA_32_REVERSAL: u32 = 1;
+
+/// user_data_32 marking a pending transfer and the transfers posting or voiding it, so that open
+/// pending transfers are found with `query_transfers` instead of reading every account
+pub const USER_DATA_32_PENDING: u32 = 2;
+
 pub mod u128 {
     pub fn to_hex_string(n: u128) -> String {
         format!("{:x}", n)
@@ -8,26 +17,99 @@ pub mod u128 {
     pub fn from_hex_string(s: &str) -> u128 {
         u128::from_str_radix(s, 16).expect("string can not be converted to u128 as hexadecimal")
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::u128;
+    pub fn try_from_hex_string(s: &str) -> Result<u128, std::num::ParseIntError> {
+        u128::from_str_radix(s, 16)
+    }
 
-    #[test]
-    fn to_hex_string() {
-        let result = u128::to_hex_string(15u128);
-        assert_eq!(result, "f")
+    /// Distance between the id of a transfer and the id of its reversal.
+    const REVERSAL_ID_OFFSET: u128 = 0x9e3779b97f4a7c15f39cc0605cedc834;
+
+    /// Derives the id of the reversal of a transfer, the ledger rejects a second reversal of the
+    /// same transfer because its id already exists.
+    pub fn to_reversal_id(id: u128) -> u128 {
+        id.wrapping_add(REVERSAL_ID_OFFSET)
     }
 
-    #[test]
-    fn from_hex_string() {
-        let result = u128::from_hex_string("f");
-        assert_eq!(result, 15u128)
+    /// Returns the id of the transfer reversed by the reversal with this id.
+    pub fn from_reversal_id(reversal_id: u128) -> u128 {
+        reversal_id.wrapping_sub(REVERSAL_ID_OFFSET)
     }
+
+    /// Distance between the id of a pending transfer and the id of the transfer posting or
+    /// voiding it.
+    const RESOLUTION_ID_OFFSET: u128 = 0x3c6ef372fe94f82ba54ff53a5f1d36f1;
+
+    /// Derives the id of the transfer posting or voiding a pending transfer, a retried post or
+    /// void finds the transfer of the first attempt instead of resolving it again.
+    pub fn to_resolution_id(pending_id: u128) -> u128 {
+        pending_id.wrapping_add(RESOLUTION_ID_OFFSET)
+    }
+
+    /// Derives a deterministic id from the route, an idempotency key and the index of the
+    /// transaction using 128 bit FNV-1a, so that a resubmitted request produces the same transfer
+    /// ids while the same key sent to another route does not.
+    pub fn from_idempotency_key(route: &str, key: &str, index: usize) -> u128 {
+        const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+        const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+        format!("{}:{}:{}", route, key, index)
+            .bytes()
+            .fold(FNV_OFFSET_BASIS, |hash, b| {
+                (hash ^ b as u128).wrapping_mul(FNV_PRIME)
+            })
+    }
+}
+
+/// Returns true when the transfers failed only because they were already created, linked
+/// transfers report `linked_event_failed` next to the transfer that `exists`.
+pub fn is_create_transfers_exists(err: &tb::error::CreateTransfersApiError) -> bool {
+    use tb::error::CreateTransferErrorKind;
+
+    let errs = err.as_slice();
+    errs.iter()
+        .any(|err| matches!(err.kind(), CreateTransferErrorKind::Exists))
+        && errs.iter().all(|err| {
+            matches!(
+                err.kind(),
+                CreateTransferErrorKind::Exists | CreateTransferErrorKind::LinkedEventFailed
+            )
+        })
 }
 
-pub fn create_transfers_error_name(err: tb::core::error::CreateTransfersError) -> String {
+/// Returns true when a transfer collides with an existing or already resolved transfer.
+pub fn is_create_transfers_conflict(err: &tb::error::CreateTransfersApiError) -> bool {
+    use tb::error::CreateTransferErrorKind;
+
+    err.as_slice().iter().any(|err| {
+        matches!(
+            err.kind(),
+            CreateTransferErrorKind::Exists
+                | CreateTransferErrorKind::ExistsWithDifferentFlags
+                | CreateTransferErrorKind::ExistsWithDifferentPendingId
+                | CreateTransferErrorKind::ExistsWithDifferentTimeout
+                | CreateTransferErrorKind::ExistsWithDifferentDebitAccountId
+                | CreateTransferErrorKind::ExistsWithDifferentCreditAccountId
+                | CreateTransferErrorKind::ExistsWithDifferentAmount
+                | CreateTransferErrorKind::ExistsWithDifferentUserData128
+                | CreateTransferErrorKind::ExistsWithDifferentUserData64
+                | CreateTransferErrorKind::ExistsWithDifferentUserData32
+                | CreateTransferErrorKind::ExistsWithDifferentLedger
+                | CreateTransferErrorKind::ExistsWithDifferentCode
+                | CreateTransferErrorKind::IdAlreadyFailed
+                | CreateTransferErrorKind::PendingTransferAlreadyPosted
+                | CreateTransferErrorKind::PendingTransferAlreadyVoided
+        )
+    })
+}
+
+pub fn create_transfer_error_kind_name(kind: tb::error::CreateTransferErrorKind) -> &'static str {
+    match kind {
+        tb::error::CreateTransferErrorKind::UnstableUncategorized => "unknown_error",
+        kind => kind.into_snake_case_str(),
+    }
+}
+
+pub fn create_transfers_error_name(err: &tb::core::error::CreateTransfersError) -> String {
     match err {
         tigerbeetle_unofficial::error::CreateTransfersError::Send(err) => {
             err.kind().into_snake_case_str().to_string()
@@ -37,10 +119,101 @@ pub fn create_transfers_error_name(err: tb::core::error::CreateTransfersError) -
             let errs = err.as_slice();
             let err = errs
                 .iter()
-                .map(|err| err.kind().into_snake_case_str())
+                .map(|err| create_transfer_error_kind_name(err.kind()))
                 .join(", ");
             err
         }
         _ => String::from("unknown error"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::u128;
+    use tigerbeetle_unofficial::error::{
+        CreateTransferErrorKind, CreateTransfersApiError, CreateTransfersIndividualApiError,
+        RawCreateTransfersIndividualApiResult,
+    };
+
+    fn api_error(kinds: &[CreateTransferErrorKind]) -> CreateTransfersApiError {
+        CreateTransfersApiError::from_errors(
+            kinds
+                .iter()
+                .enumerate()
+                .filter_map(|(index, kind)| {
+                    CreateTransfersIndividualApiError::from_raw_result(
+                        RawCreateTransfersIndividualApiResult {
+                            index: index as u32,
+                            result: *kind as u32,
+                        },
+                    )
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn to_hex_string() {
+        let result = u128::to_hex_string(15u128);
+        assert_eq!(result, "f")
+    }
+
+    #[test]
+    fn from_hex_string() {
+        let result = u128::from_hex_string("f");
+        assert_eq!(result, 15u128)
+    }
+
+    #[test]
+    fn try_from_hex_string() {
+        assert_eq!(u128::try_from_hex_string("f"), Ok(15u128));
+        assert!(u128::try_from_hex_string("xyz").is_err());
+    }
+
+    #[test]
+    fn reversal_id() {
+        let reversal_id = u128::to_reversal_id(15);
+        assert_ne!(reversal_id, 15);
+        assert_eq!(u128::from_reversal_id(reversal_id), 15);
+        assert_eq!(
+            u128::from_reversal_id(u128::to_reversal_id(u128::MAX)),
+            u128::MAX
+        );
+    }
+
+    #[test]
+    fn from_idempotency_key() {
+        let id = u128::from_idempotency_key("/mutate/add", "key", 0);
+        assert_eq!(id, u128::from_idempotency_key("/mutate/add", "key", 0));
+        assert_ne!(id, u128::from_idempotency_key("/mutate/add", "key", 1));
+        assert_ne!(id, u128::from_idempotency_key("/mutate/add", "other", 0));
+        assert_ne!(id, u128::from_idempotency_key("/mutate/pending", "key", 0));
+    }
+
+    #[test]
+    fn is_create_transfers_exists() {
+        assert!(super::is_create_transfers_exists(&api_error(&[
+            CreateTransferErrorKind::Exists,
+            CreateTransferErrorKind::LinkedEventFailed,
+        ])));
+        assert!(!super::is_create_transfers_exists(&api_error(&[
+            CreateTransferErrorKind::LinkedEventFailed,
+            CreateTransferErrorKind::ExceedsCredits,
+        ])));
+        assert!(!super::is_create_transfers_exists(&api_error(&[
+            CreateTransferErrorKind::ExistsWithDifferentAmount,
+        ])));
+    }
+
+    #[test]
+    fn is_create_transfers_conflict() {
+        assert!(super::is_create_transfers_conflict(&api_error(&[
+            CreateTransferErrorKind::ExistsWithDifferentAmount,
+        ])));
+        assert!(!super::is_create_transfers_conflict(&api_error(&[
+            CreateTransferErrorKind::LinkedEventFailed,
+            CreateTransferErrorKind::ExceedsCredits,
+        ])));
+    }
+}